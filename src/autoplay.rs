@@ -0,0 +1,68 @@
+//! Headless AI games: `tetris ai [--games N] [--pieces N] [--seed N]`.
+//!
+//! The AI plays with the engine alone, no window nor Bevy plugin is created,
+//! which makes it usable to soak-test the engine. One JSON line is printed per
+//! finished game.
+
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+use crate::game::{
+    ai::AiPlayer,
+    engine::{Engine, Ruleset},
+};
+
+#[derive(Debug, Serialize)]
+pub struct GameReport {
+    pub seed: u64,
+    pub score: i32,
+    pub lines: usize,
+    pub pieces: usize,
+    pub frames: u64,
+    pub topped_out: bool,
+}
+
+/// Let the AI play a game until it tops out or locks `max_pieces` pieces.
+pub fn play(seed: u64, max_pieces: Option<usize>) -> GameReport {
+    let mut engine = Engine::new(seed, Ruleset::default());
+    let mut player = AiPlayer::new();
+    while !engine.is_over() && max_pieces.is_none_or(|max| engine.pieces < max) {
+        let actions = player.next_actions(&engine, 0);
+        engine.step(&actions);
+    }
+    GameReport {
+        seed,
+        score: engine.score.value,
+        lines: engine.total_lines,
+        pieces: engine.pieces,
+        frames: engine.frame,
+        topped_out: engine.is_over(),
+    }
+}
+
+pub fn run(args: &[String]) -> ExitCode {
+    let mut games = 1;
+    let mut max_pieces = None;
+    let mut seed = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|v| v.parse::<u64>().ok());
+        match (arg.as_str(), value) {
+            ("--games", Some(n)) => games = n,
+            ("--pieces", Some(n)) => max_pieces = Some(n as usize),
+            ("--seed", Some(n)) => seed = Some(n),
+            _ => {
+                eprintln!("usage: tetris ai [--games N] [--pieces N] [--seed N]");
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    for game in 0..games {
+        let seed = seed.map_or_else(rand::random, |seed| seed + game);
+        let report = play(seed, max_pieces);
+        println!("{}", serde_json::to_string(&report).unwrap());
+    }
+    ExitCode::SUCCESS
+}
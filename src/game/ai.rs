@@ -0,0 +1,394 @@
+//! Built-in AI player.
+//!
+//! For the current piece, and the hold piece when hold is available, every
+//! placement `find_moves` reaches is tried, tucks and spins included. The
+//! resulting boards are scored with a weighted sum of features in the style of
+//! Dellacherie's and El-Tetris' heuristics, and the inputs leading to the best
+//! one are played.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::{
+    components::MatrixPosition,
+    engine::{Engine, Phase},
+    input::{ActionEvent, ActionQueue, InputAction},
+    matrix::Matrix,
+    movegen::{find_moves, Move},
+    tetromino::Tetromino,
+};
+
+/// Frames between two actions when the AI is watched.
+pub const WATCH_ACTION_INTERVAL: u32 = 4;
+
+/// Weight of each board feature, higher scores are better.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    pub landing_height: f64,
+    /// Cleared lines times the cells of the piece they removed.
+    pub eroded_cells: f64,
+    pub row_transitions: f64,
+    pub column_transitions: f64,
+    pub holes: f64,
+    /// Sum of the well depths, deep wells counting more.
+    pub wells: f64,
+    pub aggregate_height: f64,
+    pub bumpiness: f64,
+}
+
+impl Default for Weights {
+    /// El-Tetris weights, with a little pressure on the overall height and
+    /// surface roughness.
+    fn default() -> Self {
+        Weights {
+            landing_height: -4.500158825082766,
+            eroded_cells: 3.4181268101392694,
+            row_transitions: -3.2178882868487753,
+            column_transitions: -9.348695305445199,
+            holes: -7.899265427351652,
+            wells: -3.3855972247263626,
+            aggregate_height: -0.1,
+            bumpiness: -0.2,
+        }
+    }
+}
+
+/// Features of the board after a placement.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Features {
+    pub landing_height: f64,
+    pub eroded_cells: f64,
+    pub row_transitions: f64,
+    pub column_transitions: f64,
+    pub holes: f64,
+    pub wells: f64,
+    pub aggregate_height: f64,
+    pub bumpiness: f64,
+}
+
+impl Features {
+    /// Features of `board` (row-major, row 0 at the top) after locking the
+    /// piece `cells` in it, before and after the full rows are cleared.
+    pub fn evaluate(board: &[u8], width: usize, cells: &[MatrixPosition]) -> Features {
+        let height = board.len() / width;
+        let mut locked = board.to_vec();
+        for cell in cells {
+            locked[cell.x as usize + cell.y as usize * width] = 1;
+        }
+        let full_rows: Vec<usize> = (0..height)
+            .filter(|y| locked[y * width..(y + 1) * width].iter().all(|c| *c != 0))
+            .collect();
+        let eroded = cells
+            .iter()
+            .filter(|cell| full_rows.contains(&(cell.y as usize)))
+            .count();
+        let mut cleared: Vec<u8> = vec![0; full_rows.len() * width];
+        for (y, row) in locked.chunks(width).enumerate() {
+            if !full_rows.contains(&y) {
+                cleared.extend_from_slice(row);
+            }
+        }
+        let filled = |x: usize, y: usize| cleared[x + y * width] != 0;
+
+        let top = cells.iter().map(|c| c.y).min().unwrap_or(0);
+        let bottom = cells.iter().map(|c| c.y).max().unwrap_or(0);
+        let landing_height = height as f64 - (top + bottom) as f64 / 2.0;
+
+        let mut row_transitions = 0;
+        for y in 0..height {
+            // walls count as filled
+            let mut previous = true;
+            for x in 0..width {
+                if filled(x, y) != previous {
+                    row_transitions += 1;
+                }
+                previous = filled(x, y);
+            }
+            if !previous {
+                row_transitions += 1;
+            }
+        }
+
+        let mut column_transitions = 0;
+        let mut holes = 0;
+        let mut heights = vec![0; width];
+        for (x, column_height) in heights.iter_mut().enumerate() {
+            // above the board is empty, below it is filled
+            let mut previous = false;
+            for y in 0..height {
+                if filled(x, y) != previous {
+                    column_transitions += 1;
+                }
+                previous = filled(x, y);
+                if filled(x, y) && *column_height == 0 {
+                    *column_height = height - y;
+                } else if !filled(x, y) && *column_height > 0 {
+                    holes += 1;
+                }
+            }
+            if !previous {
+                column_transitions += 1;
+            }
+        }
+
+        let mut wells = 0;
+        for x in 0..width {
+            let mut depth = 0;
+            for y in 0..height {
+                let left = x == 0 || filled(x - 1, y);
+                let right = x == width - 1 || filled(x + 1, y);
+                if !filled(x, y) && left && right {
+                    depth += 1;
+                    wells += depth;
+                } else if filled(x, y) {
+                    break;
+                } else {
+                    depth = 0;
+                }
+            }
+        }
+
+        let bumpiness: usize = heights.windows(2).map(|h| h[0].abs_diff(h[1])).sum();
+        Features {
+            landing_height,
+            eroded_cells: (full_rows.len() * eroded) as f64,
+            row_transitions: row_transitions as f64,
+            column_transitions: column_transitions as f64,
+            holes: holes as f64,
+            wells: wells as f64,
+            aggregate_height: heights.iter().sum::<usize>() as f64,
+            bumpiness: bumpiness as f64,
+        }
+    }
+
+    pub fn score(&self, weights: &Weights) -> f64 {
+        self.landing_height * weights.landing_height
+            + self.eroded_cells * weights.eroded_cells
+            + self.row_transitions * weights.row_transitions
+            + self.column_transitions * weights.column_transitions
+            + self.holes * weights.holes
+            + self.wells * weights.wells
+            + self.aggregate_height * weights.aggregate_height
+            + self.bumpiness * weights.bumpiness
+    }
+}
+
+/// Press and release each action in turn.
+fn taps(actions: &[InputAction]) -> Vec<ActionEvent> {
+    actions
+        .iter()
+        .flat_map(|action| [ActionEvent::press(*action), ActionEvent::release(*action)])
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ai {
+    pub weights: Weights,
+}
+
+impl Ai {
+    /// Actions playing the best placement of the current or the hold piece.
+    pub fn plan(&self, engine: &Engine) -> Vec<InputAction> {
+        if engine.is_over() {
+            return vec![];
+        }
+        let mut best = self.best_placement(engine);
+        if engine.can_hold() {
+            let mut held = engine.clone();
+            held.step(&taps(&[InputAction::Hold]));
+            if let Some((score, mut mv)) = self.best_placement(&held) {
+                if best.as_ref().is_none_or(|(best, _)| score > *best) {
+                    mv.path.insert(0, InputAction::Hold);
+                    best = Some((score, mv));
+                }
+            }
+        }
+        best.map(|(_, mv)| mv.path).unwrap_or_default()
+    }
+
+    /// Best resting position of `tetromino` reachable from `start`, tucks and
+    /// spins included.
+    pub fn best_move(
+        &self,
+        matrix: &Matrix,
+        tetromino: &Tetromino,
+        start: MatrixPosition,
+    ) -> Option<Move> {
+        self.scored_best_move(matrix, tetromino, start)
+            .map(|(_, mv)| mv)
+    }
+
+    fn scored_best_move(
+        &self,
+        matrix: &Matrix,
+        tetromino: &Tetromino,
+        start: MatrixPosition,
+    ) -> Option<(f64, Move)> {
+        find_moves(matrix, tetromino, start)
+            .into_iter()
+            .map(|mv| (self.score_cells(matrix, &mv.cells()), mv))
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+    }
+
+    /// Best move of the piece in play of `engine`, from where it is.
+    fn best_placement(&self, engine: &Engine) -> Option<(f64, Move)> {
+        let tetromino = engine.current.as_ref()?;
+        if engine.is_over() {
+            return None;
+        }
+        self.scored_best_move(&engine.matrix, tetromino, engine.matrix.start_pos)
+    }
+
+    /// Score of locking a piece in `cells`.
+    fn score_cells(&self, matrix: &Matrix, cells: &[MatrixPosition]) -> f64 {
+        // locking in the hidden rows tops out
+        if cells.iter().any(|c| c.y <= 1) {
+            return f64::MIN;
+        }
+        Features::evaluate(&matrix.occupation, matrix.field_width, cells).score(&self.weights)
+    }
+}
+
+/// The AI playing the running game.
+#[derive(Resource, Default)]
+pub struct AiPlayer {
+    pub active: bool,
+    pub ai: Ai,
+    plan: VecDeque<InputAction>,
+    /// Pieces locked when the plan was made.
+    planned_at: Option<usize>,
+    wait: u32,
+}
+
+impl AiPlayer {
+    pub fn new() -> Self {
+        AiPlayer {
+            active: true,
+            ..Default::default()
+        }
+    }
+
+    /// Actions for the next engine frame: one every `interval` frames, the
+    /// whole plan at once when `interval` is 0. Nothing during a countdown,
+    /// the engine would drop the actions.
+    pub fn next_actions(&mut self, engine: &Engine, interval: u32) -> Vec<ActionEvent> {
+        if engine.current.is_none() || engine.is_over() || engine.frozen > 0 {
+            return vec![];
+        }
+        if self.planned_at != Some(engine.pieces) {
+            self.plan = self.ai.plan(engine).into();
+            self.planned_at = Some(engine.pieces);
+            self.wait = interval;
+        }
+        if interval == 0 {
+            return taps(&self.plan.drain(..).collect::<Vec<_>>());
+        }
+        if self.wait > 0 {
+            self.wait -= 1;
+            return vec![];
+        }
+        self.wait = interval;
+        // gravity may have brought the piece down already, a soft drop on the
+        // ground would lock it
+        while self.plan.front() == Some(&InputAction::SoftDrop) && engine.phase == Phase::Locking {
+            self.plan.pop_front();
+        }
+        match self.plan.pop_front() {
+            Some(action) => taps(&[action]),
+            None => vec![],
+        }
+    }
+}
+
+pub fn is_ai_playing(ai: Res<AiPlayer>) -> bool {
+    ai.active
+}
+
+pub fn is_not_ai_playing(ai: Res<AiPlayer>) -> bool {
+    !ai.active
+}
+
+/// Queue the actions of the AI for the frame about to be simulated.
+pub fn ai_action_system(
+    engine: Res<Engine>,
+    mut ai: ResMut<AiPlayer>,
+    mut queue: ResMut<ActionQueue>,
+) {
+    let actions = ai.next_actions(&engine, WATCH_ACTION_INTERVAL);
+    queue.0.extend(actions);
+}
+
+/// Hand the game back to the player when leaving it.
+pub fn stop_ai(mut ai: ResMut<AiPlayer>) {
+    *ai = AiPlayer::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AiPlayer, Features};
+    use crate::game::{
+        components::MatrixPosition,
+        engine::{Engine, Ruleset},
+    };
+
+    #[test]
+    fn test_features_of_a_board() {
+        // 4 wide, 3 high: a hole under the left column, a 1 deep well on the right
+        #[rustfmt::skip]
+        let board = [
+            0, 0, 0, 0,
+            1, 0, 0, 0,
+            0, 1, 1, 0,
+        ];
+        let cells = [MatrixPosition { x: 3, y: 2 }];
+        let features = Features::evaluate(&board, 4, &cells);
+        assert_eq!(features.holes, 1.0);
+        assert_eq!(features.eroded_cells, 0.0);
+        assert_eq!(features.aggregate_height, 2.0 + 1.0 + 1.0 + 1.0);
+        assert_eq!(features.landing_height, 1.0);
+
+        // filling the last cell clears the bottom row
+        let board = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 0];
+        let features = Features::evaluate(&board, 4, &cells);
+        assert_eq!(features.eroded_cells, 1.0);
+        assert_eq!(features.aggregate_height, 0.0);
+        assert_eq!(features.holes, 0.0);
+    }
+
+    #[test]
+    fn test_ai_survives() {
+        let mut engine = Engine::new(7, Ruleset::default());
+        let mut player = AiPlayer::new();
+        while !engine.is_over() && engine.pieces < 300 {
+            let actions = player.next_actions(&engine, 0);
+            engine.step(&actions);
+        }
+        assert!(
+            !engine.is_over(),
+            "topped out after {} pieces",
+            engine.pieces
+        );
+        assert!(engine.total_lines >= 100);
+    }
+
+    #[test]
+    fn test_ai_waits_for_the_countdown() {
+        let mut engine = Engine::new(3, Ruleset::default());
+        engine.step(&[]);
+        let mut frozen = engine.clone();
+        frozen.frozen = 30;
+
+        let mut cells = vec![];
+        for engine in [&mut engine, &mut frozen] {
+            let mut player = AiPlayer::new();
+            while engine.pieces == 0 {
+                let actions = player.next_actions(engine, 0);
+                assert!(engine.frozen == 0 || actions.is_empty());
+                engine.step(&actions);
+            }
+            cells.push(engine.locked.as_ref().unwrap().cells.clone());
+        }
+        assert_eq!(cells[0], cells[1]);
+    }
+}
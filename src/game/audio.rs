@@ -0,0 +1,335 @@
+//! Sound effects and music.
+//!
+//! The effects are picked from the engine events of the frame, each played at
+//! most once per frame. Consecutive line clears raise the pitch of the combo
+//! sound. Every effect has a sound file of its own under `sounds/effects`. The
+//! music loops one track for the menus and one for the game, and crossfades
+//! between them; it is ducked while the game is paused. Volumes
+//! come from `AudioSettings` and apply as soon as they change.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::{
+    engine::{EngineEvent, Spin},
+    settings::Settings,
+    GameState,
+};
+use crate::AppState;
+
+const MENU_MUSIC_PATH: &str = "sounds/menu.wav";
+const GAME_MUSIC_PATH: &str = "sounds/Windless Slopes.ogg";
+/// Seconds for a track to fade fully in or out.
+const CROSSFADE: f32 = 1.5;
+/// Gain of the music while paused.
+const DUCKED: f32 = 0.3;
+/// Pitch added by every line clear of a combo, up to `MAX_COMBO_PITCH`.
+const COMBO_PITCH_STEP: f32 = 0.08;
+const MAX_COMBO_PITCH: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    Move,
+    Rotate,
+    Kick,
+    SoftDrop,
+    HardDrop,
+    Lock,
+    Hold,
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    TSpin,
+    LevelUp,
+    Combo,
+    TopOut,
+}
+
+impl Sfx {
+    const ALL: [Sfx; 15] = [
+        Sfx::Move,
+        Sfx::Rotate,
+        Sfx::Kick,
+        Sfx::SoftDrop,
+        Sfx::HardDrop,
+        Sfx::Lock,
+        Sfx::Hold,
+        Sfx::Single,
+        Sfx::Double,
+        Sfx::Triple,
+        Sfx::Tetris,
+        Sfx::TSpin,
+        Sfx::LevelUp,
+        Sfx::Combo,
+        Sfx::TopOut,
+    ];
+
+    /// File and gain of the effect.
+    fn sound(self) -> (&'static str, f32) {
+        match self {
+            Sfx::Move => ("sounds/effects/move.wav", 0.3),
+            Sfx::Rotate => ("sounds/effects/rotate.wav", 0.35),
+            Sfx::Kick => ("sounds/effects/kick.wav", 0.45),
+            Sfx::SoftDrop => ("sounds/effects/soft_drop.wav", 0.15),
+            Sfx::HardDrop => ("sounds/effects/hard_drop.wav", 0.8),
+            Sfx::Lock => ("sounds/effects/lock.wav", 0.5),
+            Sfx::Hold => ("sounds/effects/hold.wav", 0.4),
+            Sfx::Single => ("sounds/effects/single.wav", 0.7),
+            Sfx::Double => ("sounds/effects/double.wav", 0.75),
+            Sfx::Triple => ("sounds/effects/triple.wav", 0.8),
+            Sfx::Tetris => ("sounds/effects/tetris.wav", 1.0),
+            Sfx::TSpin => ("sounds/effects/t_spin.wav", 0.9),
+            Sfx::LevelUp => ("sounds/effects/level_up.wav", 0.9),
+            Sfx::Combo => ("sounds/effects/combo.wav", 0.6),
+            Sfx::TopOut => ("sounds/effects/top_out.wav", 1.0),
+        }
+    }
+}
+
+/// Effects of `events` with the pitch they play at, `combo` counts the
+/// consecutive locks which cleared lines.
+pub fn effects(events: &[EngineEvent], combo: &mut u32) -> Vec<(Sfx, f32)> {
+    let mut effects: Vec<(Sfx, f32)> = vec![];
+    let mut play = |sfx: Sfx, pitch: f32| match effects.iter_mut().find(|(s, _)| *s == sfx) {
+        Some(effect) => effect.1 = pitch,
+        None => effects.push((sfx, pitch)),
+    };
+    for ev in events {
+        match ev {
+            EngineEvent::Shifted => play(Sfx::Move, 1.0),
+            EngineEvent::Rotated => play(Sfx::Rotate, 1.0),
+            EngineEvent::Kicked => play(Sfx::Kick, 1.0),
+            EngineEvent::SoftDropped => play(Sfx::SoftDrop, 1.0),
+            EngineEvent::HardDropped => play(Sfx::HardDrop, 1.0),
+            EngineEvent::Locked => play(Sfx::Lock, 1.0),
+            EngineEvent::Held => play(Sfx::Hold, 1.0),
+            EngineEvent::Cleared(clear) => {
+                if clear.spin != Spin::None {
+                    play(Sfx::TSpin, 1.0);
+                }
+                match clear.lines {
+                    0 => *combo = 0,
+                    lines => {
+                        let sfx = match lines {
+                            1 => Sfx::Single,
+                            2 => Sfx::Double,
+                            3 => Sfx::Triple,
+                            _ => Sfx::Tetris,
+                        };
+                        play(sfx, 1.0);
+                        *combo += 1;
+                        if *combo > 1 {
+                            let pitch = 1.0 + COMBO_PITCH_STEP * (*combo - 1) as f32;
+                            play(Sfx::Combo, pitch.min(MAX_COMBO_PITCH));
+                        }
+                    }
+                }
+            }
+            EngineEvent::LevelUp => play(Sfx::LevelUp, 1.0),
+            EngineEvent::GameOver => play(Sfx::TopOut, 1.0),
+            EngineEvent::Finished => play(Sfx::LevelUp, 1.0),
+            _ => {}
+        }
+    }
+    // a kick is the louder version of the rotation, a hard drop of the lock
+    let kicked = effects.iter().any(|(sfx, _)| *sfx == Sfx::Kick);
+    let dropped = effects.iter().any(|(sfx, _)| *sfx == Sfx::HardDrop);
+    effects.retain(|(sfx, _)| match sfx {
+        Sfx::Rotate => !kicked,
+        Sfx::Lock => !dropped,
+        _ => true,
+    });
+    effects
+}
+
+/// Move `gain` toward `target` by at most `step`.
+pub fn fade(gain: f32, target: f32, step: f32) -> f32 {
+    match gain < target {
+        true => (gain + step).min(target),
+        false => (gain - step).max(target),
+    }
+}
+
+/// Sounds loaded at startup, so that they are ready the first time they play.
+#[derive(Resource, Default)]
+pub struct Sounds(HashMap<&'static str, Handle<AudioSource>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Track {
+    Menu,
+    Game,
+}
+
+impl Track {
+    const ALL: [Track; 2] = [Track::Menu, Track::Game];
+
+    fn path(self) -> &'static str {
+        match self {
+            Track::Menu => MENU_MUSIC_PATH,
+            Track::Game => GAME_MUSIC_PATH,
+        }
+    }
+}
+
+/// The looping music tracks and their current gain.
+#[derive(Resource, Default)]
+pub struct Music {
+    sinks: Vec<(Handle<AudioSink>, f32)>,
+}
+
+pub fn load_sounds(mut sounds: ResMut<Sounds>, asset_server: Res<AssetServer>) {
+    let effects = Sfx::ALL.map(|sfx| sfx.sound().0);
+    let tracks = Track::ALL.map(Track::path);
+    for path in effects.into_iter().chain(tracks) {
+        sounds.0.insert(path, asset_server.load(path));
+    }
+}
+
+/// Play the effects of the engine events.
+pub fn play_effects_system(
+    mut events: EventReader<EngineEvent>,
+    mut combo: Local<u32>,
+    audio: Res<Audio>,
+    sounds: Res<Sounds>,
+    settings: Res<Settings>,
+) {
+    let events: Vec<EngineEvent> = events.iter().cloned().collect();
+    let gain = settings.audio.sfx_gain();
+    for (sfx, pitch) in effects(&events, &mut combo) {
+        let (path, volume) = sfx.sound();
+        let Some(sound) = sounds.0.get(path) else {
+            continue;
+        };
+        if gain > 0.0 {
+            audio.play_with_settings(
+                sound.clone(),
+                PlaybackSettings::ONCE
+                    .with_volume(gain * volume)
+                    .with_speed(pitch),
+            );
+        }
+    }
+}
+
+/// Start the tracks, crossfade to the one of the current screen and duck it
+/// while paused.
+#[allow(clippy::too_many_arguments)]
+pub fn music_system(
+    mut music: ResMut<Music>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    sounds: Res<Sounds>,
+    settings: Res<Settings>,
+    app_state: Res<State<AppState>>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    if music.sinks.is_empty() {
+        for track in Track::ALL {
+            let Some(sound) = sounds.0.get(track.path()) else {
+                return;
+            };
+            let sink =
+                audio.play_with_settings(sound.clone(), PlaybackSettings::LOOP.with_volume(0.0));
+            music.sinks.push((audio_sinks.get_handle(sink), 0.0));
+        }
+    }
+
+    let playing = match app_state.0 {
+        AppState::Game => Track::Game,
+        _ => Track::Menu,
+    };
+    let ducked = match game_state.0 {
+        GameState::Paused => DUCKED,
+        _ => 1.0,
+    };
+    let step = time.delta_seconds() / CROSSFADE;
+    for (track, (sink, gain)) in Track::ALL.into_iter().zip(music.sinks.iter_mut()) {
+        let target = match track == playing {
+            true => ducked,
+            false => 0.0,
+        };
+        *gain = fade(*gain, target, step);
+        if let Some(sink) = audio_sinks.get(sink) {
+            sink.set_volume(*gain * settings.audio.music_gain());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path};
+
+    use super::{effects, fade, Sfx};
+    use crate::game::engine::{Clear, EngineEvent, Spin};
+
+    fn cleared(lines: usize, spin: Spin) -> EngineEvent {
+        EngineEvent::Cleared(Clear {
+            lines,
+            spin,
+            perfect: false,
+        })
+    }
+
+    #[test]
+    fn test_effects_of_the_events() {
+        let mut combo = 0;
+        let events = [
+            EngineEvent::Moved,
+            EngineEvent::Shifted,
+            EngineEvent::Moved,
+            EngineEvent::Shifted,
+            EngineEvent::Rotated,
+            EngineEvent::Kicked,
+            EngineEvent::HardDropped,
+            EngineEvent::Locked,
+            cleared(2, Spin::Full),
+        ];
+        let sounds: Vec<Sfx> = effects(&events, &mut combo)
+            .into_iter()
+            .map(|(sfx, _)| sfx)
+            .collect();
+        assert_eq!(
+            sounds,
+            [Sfx::Move, Sfx::Kick, Sfx::HardDrop, Sfx::TSpin, Sfx::Double]
+        );
+        assert_eq!(combo, 1);
+    }
+
+    #[test]
+    fn test_combo_raises_the_pitch() {
+        let mut combo = 0;
+        let mut pitches = vec![];
+        for lines in [1, 4, 1, 0, 1] {
+            let played = effects(&[cleared(lines, Spin::None)], &mut combo);
+            pitches.push(
+                played
+                    .iter()
+                    .find(|(sfx, _)| *sfx == Sfx::Combo)
+                    .map(|(_, pitch)| *pitch),
+            );
+        }
+        assert_eq!(pitches[0], None);
+        assert!(pitches[2].unwrap() > pitches[1].unwrap());
+        assert_eq!(pitches[3], None);
+        assert_eq!(pitches[4], None);
+    }
+
+    #[test]
+    fn test_every_effect_has_its_own_sound() {
+        let paths: HashSet<&str> = Sfx::ALL.iter().map(|sfx| sfx.sound().0).collect();
+        assert_eq!(paths.len(), Sfx::ALL.len());
+        for path in paths {
+            assert!(Path::new("assets").join(path).exists(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_fade_stops_at_the_target() {
+        assert_eq!(fade(0.0, 1.0, 0.4), 0.4);
+        assert_eq!(fade(0.8, 1.0, 0.4), 1.0);
+        assert_eq!(fade(0.5, 0.3, 0.4), 0.3);
+    }
+}
@@ -2,7 +2,7 @@ use std::ops::Add;
 
 use bevy::prelude::*;
 
-use super::{global::BLOCK_SIZE, matrix::Matrix};
+use super::{global::BLOCK_SIZE, matrix::Matrix, theme::Tile};
 
 #[derive(Component)]
 pub struct PausedLayout;
@@ -12,13 +12,15 @@ pub struct GameOverLayout;
 #[derive(Component)]
 pub enum PausedButtonAction {
     Continue,
+    Settings,
     Renew,
     Exit,
 }
 
 #[derive(Component)]
 pub enum GameOverButtonAction {
-    Renew,
+    Retry,
+    SaveReplay,
     MainMenu,
     Exit,
 }
@@ -26,6 +28,26 @@ pub enum GameOverButtonAction {
 #[derive(Component)]
 pub struct GameDisplay;
 
+/// Text of the ready and resume countdowns.
+#[derive(Component)]
+pub struct CountdownText;
+
+/// Name typed for a new high score.
+#[derive(Component)]
+pub struct NameInput;
+
+/// Node holding the high-score table of the game over page.
+#[derive(Component)]
+pub struct HighScoreTable;
+
+/// Text of the replay viewer.
+#[derive(Component)]
+pub struct ReplayHud;
+
+/// Text of the stats panel.
+#[derive(Component)]
+pub struct StatsPanel;
+
 #[derive(Component, PartialEq, Eq)]
 pub enum GameArea {
     Block,
@@ -49,7 +71,11 @@ pub struct CurrentTetromino;
 #[derive(Component)]
 pub struct HoldQueueTetromino;
 
-#[derive(Debug, Clone, Copy, Component)]
+/// Tetromino put aside with the hold action.
+#[derive(Component)]
+pub struct HeldTetromino;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component)]
 pub struct MatrixPosition {
     pub x: i32,
     pub y: i32,
@@ -69,6 +95,49 @@ pub struct TetrominoPosition(pub MatrixPosition);
 #[derive(Component)]
 pub struct LockedDownBlock;
 
+/// Block showing where the current tetromino would land.
+#[derive(Component)]
+pub struct GhostBlock;
+
+/// Outline of the placement suggested by the coach.
+#[derive(Component)]
+pub struct HintBlock;
+
+/// Part of a versus player drawn with cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersusArea {
+    Board,
+    Hold,
+    Next,
+}
+
+/// Cell of a versus board, or of the hold or next piece beside it.
+#[derive(Component)]
+pub struct VersusCell {
+    pub player: usize,
+    pub area: VersusArea,
+    pub position: MatrixPosition,
+    /// Occupation drawn in the cell and whether it is the ghost piece.
+    pub shown: Option<(u8, bool)>,
+}
+
+/// Lines, sent and incoming garbage of a versus player.
+#[derive(Component)]
+pub struct VersusStats(pub usize);
+
+/// Bar of the incoming garbage beside a board, of the versus player or of the
+/// practice game when `None`. One bar for the garbage done waiting, another
+/// one above it for the delayed garbage.
+#[derive(Component)]
+pub struct GarbageMeter {
+    pub player: Option<usize>,
+    pub ready: bool,
+    /// Height of one garbage row.
+    pub row: f32,
+    /// Bottom of the meter.
+    pub bottom: f32,
+}
+
 #[derive(Resource)]
 pub struct HeapCounter(pub usize);
 
@@ -96,32 +165,38 @@ impl BlockBundle {
     /// * start_postion: Tetromino position at Matrix
     /// * rel_position: Block position relative to Tetromino position
     /// * matrix: Matrix resource of the global game.
+    /// * atlas, tile: Texture atlas of the theme and the tile drawn.
     ///
     pub fn new(
         start_position: MatrixPosition,
         rel_position: MatrixPosition,
         matrix: &Matrix,
-        path: &'static str,
-        asset_server: &AssetServer,
-        texture_atlas_res: &mut Assets<TextureAtlas>,
+        atlas: Handle<TextureAtlas>,
+        tile: Tile,
     ) -> Self {
-        let texture = asset_server.load(path);
-        let texture_atlas =
-            TextureAtlas::from_grid(texture, Vec2::new(BLOCK_SIZE, BLOCK_SIZE), 1, 1, None, None);
         let position = start_position + rel_position;
         let (x, y) = matrix.get_translation(position);
         BlockBundle {
             block: Block { position },
             sprite: SpriteSheetBundle {
                 sprite: TextureAtlasSprite {
+                    index: tile.index,
+                    color: tile.tint,
                     custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_SIZE)),
                     anchor: bevy::sprite::Anchor::TopLeft,
                     ..default()
                 },
-                texture_atlas: texture_atlas_res.add(texture_atlas),
+                texture_atlas: atlas,
                 transform: Transform::from_xyz(x, y, 0.0),
                 ..default()
             },
         }
     }
+
+    /// Draw the block at `scale` of its size with its top-left corner at `translation`.
+    pub fn with_scale(mut self, translation: Vec3, scale: f32) -> Self {
+        self.sprite.transform =
+            Transform::from_translation(translation).with_scale(Vec3::splat(scale));
+        self
+    }
 }
@@ -0,0 +1,173 @@
+//! Options of the next game, picked in the new game screen and saved as TOML
+//! in the user config directory so that the last choice is offered again.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{GameMode, RulePreset, Ruleset},
+    global::{get_config_dir, MAX_LEVEL},
+    resources::Randomizer,
+    settings::{cycle, Settings},
+};
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub mode: GameMode,
+    pub start_level: usize,
+    pub randomizer: Randomizer,
+    pub preset: RulePreset,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            mode: GameMode::Marathon,
+            start_level: 1,
+            randomizer: Randomizer::Bag,
+            preset: RulePreset::Modern,
+        }
+    }
+}
+
+/// Options which can be changed in the new game screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameOption {
+    Mode,
+    Level,
+    Randomizer,
+    Rules,
+}
+
+impl GameOption {
+    pub const ALL: [GameOption; 4] = [
+        GameOption::Mode,
+        GameOption::Level,
+        GameOption::Randomizer,
+        GameOption::Rules,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GameOption::Mode => "Mode",
+            GameOption::Level => "Level",
+            GameOption::Randomizer => "Randomizer",
+            GameOption::Rules => "Rules",
+        }
+    }
+}
+
+impl GameConfig {
+    /// Load the last choice, use the defaults if there is none yet.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+                warn!("failed to parse game options {}: {}", path.display(), e);
+                GameConfig::default()
+            }),
+            Err(_) => GameConfig::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, content)
+    }
+
+    /// Rules of a game with these options and the handling of the settings.
+    pub fn ruleset(&self, settings: &Settings) -> Ruleset {
+        Ruleset {
+            mode: self.mode,
+            start_level: self.start_level.clamp(1, MAX_LEVEL),
+            handling: settings.handling,
+            delays: settings.delays,
+            randomizer: self.randomizer,
+            preset: self.preset,
+            countdown: settings.gameplay.countdown,
+        }
+    }
+
+    /// Text shown for an option.
+    pub fn value(&self, option: GameOption) -> String {
+        match option {
+            GameOption::Mode => format!("{:?}", self.mode),
+            GameOption::Level => self.start_level.to_string(),
+            GameOption::Randomizer => format!("{:?}", self.randomizer),
+            GameOption::Rules => format!("{:?}", self.preset),
+        }
+    }
+
+    /// What the current value of an option means.
+    pub fn description(&self, option: GameOption) -> &'static str {
+        match option {
+            GameOption::Mode => self.mode.description(),
+            GameOption::Level => match self.mode {
+                GameMode::Zen | GameMode::Puzzle => "The speed stays at this level.",
+                _ => "The speed to start at, it rises every 10 lines.",
+            },
+            GameOption::Randomizer => self.randomizer.description(),
+            GameOption::Rules => self.preset.description(),
+        }
+    }
+
+    /// Next (`step > 0`) or previous value of an option, levels stop at the
+    /// first and the last.
+    pub fn adjust(&mut self, option: GameOption, step: i32) {
+        match option {
+            GameOption::Mode => self.mode = cycle(&GameMode::ALL, self.mode, step),
+            GameOption::Level => {
+                self.start_level =
+                    (self.start_level as i64 + step as i64).clamp(1, MAX_LEVEL as i64) as usize
+            }
+            GameOption::Randomizer => {
+                self.randomizer = cycle(&Randomizer::ALL, self.randomizer, step)
+            }
+            GameOption::Rules => self.preset = cycle(&RulePreset::ALL, self.preset, step),
+        }
+    }
+}
+
+pub fn get_game_config_path() -> PathBuf {
+    get_config_dir().join("new_game.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameConfig, GameOption};
+    use crate::game::{engine::GameMode, global::MAX_LEVEL, settings::Settings};
+
+    #[test]
+    fn test_game_config_roundtrip() {
+        let mut config = GameConfig::default();
+        config.adjust(GameOption::Mode, -1);
+        config.adjust(GameOption::Level, 3);
+        config.adjust(GameOption::Randomizer, 1);
+        let content = toml::to_string_pretty(&config).unwrap();
+        let loaded: GameConfig = toml::from_str(&content).unwrap();
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.mode, GameMode::Puzzle);
+
+        let ruleset = loaded.ruleset(&Settings::default());
+        assert_eq!(ruleset.start_level, 4);
+        assert_eq!(ruleset.randomizer, loaded.randomizer);
+    }
+
+    #[test]
+    fn test_level_stays_in_range() {
+        let mut config = GameConfig::default();
+        config.adjust(GameOption::Level, -5);
+        assert_eq!(config.start_level, 1);
+        config.adjust(GameOption::Level, 100);
+        assert_eq!(config.start_level, MAX_LEVEL);
+    }
+}
@@ -0,0 +1,1383 @@
+//! Deterministic game simulation.
+//!
+//! `Engine` owns the whole state of a running game and is advanced one fixed
+//! frame at a time with the actions pressed or released during that frame.
+//! Given the same seed, ruleset and actions it always produces the same game,
+//! which is what replays rely on.
+
+use std::{cmp::min, time::Duration};
+
+use bevy::{
+    prelude::{Component, Resource},
+    time::{Timer, TimerMode},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    components::MatrixPosition,
+    global::{get_falling_speed, FRAME_DURATION, MAX_LEVEL},
+    input::{ActionEvent, InputAction},
+    matrix::Matrix,
+    resources::{HoldOnQueueResoure, Randomizer, Score, ScoreAction, ScoreEvent},
+    tetromino::{Tetromino, TetrominoType},
+};
+
+/// Lines to clear in sprint.
+pub const SPRINT_LINES: usize = 40;
+/// Length of an ultra game, two minutes.
+pub const ULTRA_FRAMES: u64 = 2 * 60 * 60;
+/// Rows of garbage to dig through.
+pub const DIG_ROWS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Marathon,
+    Sprint,
+    Ultra,
+    Zen,
+    Dig,
+    Puzzle,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 6] = [
+        GameMode::Marathon,
+        GameMode::Sprint,
+        GameMode::Ultra,
+        GameMode::Zen,
+        GameMode::Dig,
+        GameMode::Puzzle,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => {
+                "Survive as long as you can, the pieces fall faster every 10 lines."
+            }
+            GameMode::Sprint => "Clear 40 lines as fast as you can.",
+            GameMode::Ultra => "Score as much as you can in two minutes.",
+            GameMode::Zen => "No speed up and no game over, the stack is cleared when it tops out.",
+            GameMode::Dig => "Dig through 10 rows of garbage as fast as you can.",
+            GameMode::Puzzle => "Clear the whole board with the pieces given.",
+        }
+    }
+
+    /// Whether the level rises with the lines cleared.
+    fn levels_up(&self) -> bool {
+        !matches!(self, GameMode::Zen | GameMode::Puzzle)
+    }
+
+    /// Whether the game is won by reaching a goal, the faster the better.
+    pub fn has_goal(&self) -> bool {
+        matches!(self, GameMode::Sprint | GameMode::Dig | GameMode::Puzzle)
+    }
+}
+
+/// A board to clear entirely with a fixed list of pieces.
+pub struct Puzzle {
+    /// Bottom rows of the board, `X` for a filled cell.
+    pub rows: &'static [&'static str],
+    pub pieces: &'static [TetrominoType],
+}
+
+pub const PUZZLES: [Puzzle; 3] = [
+    Puzzle {
+        rows: &["XXXX....XX", "XXXX....XX"],
+        pieces: &[TetrominoType::O, TetrominoType::O],
+    },
+    Puzzle {
+        rows: &["XXXXX...XX", "XXXXXX.XXX"],
+        pieces: &[TetrominoType::T],
+    },
+    Puzzle {
+        rows: &["XXXXXXX...", "XXXXXXX...", "XXXXXXXXX.", "XXXXXXXXX."],
+        pieces: &[TetrominoType::O, TetrominoType::I],
+    },
+];
+
+impl Puzzle {
+    /// The puzzle of a game, picked by its seed.
+    pub fn of(seed: u64) -> &'static Puzzle {
+        &PUZZLES[(seed % PUZZLES.len() as u64) as usize]
+    }
+}
+
+/// Which moves are allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum RulePreset {
+    #[default]
+    Modern,
+    /// No hold, no hard drop and no 180 rotation.
+    Classic,
+}
+
+impl RulePreset {
+    pub const ALL: [RulePreset; 2] = [RulePreset::Modern, RulePreset::Classic];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            RulePreset::Modern => "Hold, hard drop and 180 rotation.",
+            RulePreset::Classic => "No hold, no hard drop and no 180 rotation.",
+        }
+    }
+
+    pub fn allows(&self, action: InputAction) -> bool {
+        match self {
+            RulePreset::Modern => true,
+            RulePreset::Classic => !matches!(
+                action,
+                InputAction::Hold | InputAction::HardDrop | InputAction::Rotate180
+            ),
+        }
+    }
+}
+
+/// Auto-repeat of held actions, in frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handling {
+    /// Delayed auto shift: frames a move is held before it repeats.
+    pub das: u32,
+    /// Auto repeat rate: frames between repeated moves, 0 moves to the wall at once.
+    pub arr: u32,
+    /// Soft drop factor: how many times faster than gravity the soft drop is.
+    pub sdf: u32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 10,
+            arr: 2,
+            sdf: 20,
+        }
+    }
+}
+
+/// Delays between two pieces, in frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Delays {
+    /// Entry delay: frames without a piece between a lock and the next spawn.
+    pub are: u32,
+    /// Frames the cleared rows are shown before they collapse.
+    pub line_clear: u32,
+}
+
+/// Delays at the first level and at `MAX_LEVEL`, the levels in between get
+/// delays interpolated from them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DelayCurve {
+    pub first: Delays,
+    pub last: Delays,
+}
+
+impl Default for DelayCurve {
+    fn default() -> Self {
+        DelayCurve {
+            first: Delays {
+                are: 10,
+                line_clear: 30,
+            },
+            last: Delays {
+                are: 4,
+                line_clear: 12,
+            },
+        }
+    }
+}
+
+impl DelayCurve {
+    /// No delay at all, the rules of the replays recorded before the delays.
+    pub fn none() -> Self {
+        DelayCurve {
+            first: Delays::default(),
+            last: Delays::default(),
+        }
+    }
+
+    pub fn at(&self, level: usize) -> Delays {
+        let t = (level.clamp(1, MAX_LEVEL) - 1) as f32 / (MAX_LEVEL - 1) as f32;
+        let lerp = |first: u32, last: u32| {
+            (first as f32 + (last as f32 - first as f32) * t).round() as u32
+        };
+        Delays {
+            are: lerp(self.first.are, self.last.are),
+            line_clear: lerp(self.first.line_clear, self.last.line_clear),
+        }
+    }
+}
+
+/// Frames the game is frozen before it starts and when it resumes after a
+/// pause. Held moves charge during the countdowns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Countdown {
+    pub ready: u32,
+    pub resume: u32,
+}
+
+/// Rules a game is played with, stored in replays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ruleset {
+    #[serde(default)]
+    pub mode: GameMode,
+    pub start_level: usize,
+    pub handling: Handling,
+    #[serde(default = "DelayCurve::none")]
+    pub delays: DelayCurve,
+    #[serde(default)]
+    pub randomizer: Randomizer,
+    #[serde(default)]
+    pub preset: RulePreset,
+    #[serde(default)]
+    pub countdown: Countdown,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset {
+            mode: GameMode::default(),
+            start_level: 1,
+            handling: Handling::default(),
+            delays: DelayCurve::default(),
+            randomizer: Randomizer::default(),
+            preset: RulePreset::default(),
+            countdown: Countdown::default(),
+        }
+    }
+}
+
+/// What the engine is doing between two frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Phase {
+    /// The current piece falls.
+    Falling,
+    /// The current piece rests on the stack, it locks at the next gravity
+    /// step unless it is moved off.
+    Locking,
+    /// The full `rows` are shown `frames` more frames before they collapse.
+    Clearing { rows: Vec<usize>, frames: u32 },
+    /// No piece, the next one spawns after `frames` more frames.
+    Entry { frames: u32 },
+}
+
+impl Ruleset {
+    /// Name of the high-score table games played with these rules belong to.
+    pub fn variant(&self) -> String {
+        format!(
+            "{:?} - Level {} - {:?} - {:?}",
+            self.mode, self.start_level, self.randomizer, self.preset
+        )
+    }
+}
+
+/// T-spin of a locked piece, by the 3-corner rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Spin {
+    #[default]
+    None,
+    /// Only one of the corners the T points to is filled.
+    Mini,
+    Full,
+}
+
+/// A tetromino as it locked, the rows it cleared may be gone from the matrix.
+#[derive(Clone)]
+pub struct LockedPiece {
+    /// The matrix just before the lock.
+    pub board: Matrix,
+    /// Cells the tetromino locked into.
+    pub cells: Vec<MatrixPosition>,
+}
+
+/// What a locked piece cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Clear {
+    pub lines: usize,
+    pub spin: Spin,
+    /// The matrix is empty after the clear.
+    pub perfect: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum EngineEvent {
+    Spawned(TetrominoType),
+    Moved,
+    /// The current tetromino moved sideways, next to the `Moved` of the move.
+    Shifted,
+    Rotated,
+    /// The last rotation needed a kick to fit.
+    Kicked,
+    /// The current tetromino fell a row because of a soft drop.
+    SoftDropped,
+    /// Sent before the `Locked` of a hard drop.
+    HardDropped,
+    /// The current tetromino was swapped with the hold one.
+    Held,
+    Locked,
+    /// The cleared rows collapsed, after the line clear delay.
+    LinesCleared,
+    /// Sent on every lock, `lines` is 0 when nothing was cleared.
+    Cleared(Clear),
+    LevelUp,
+    /// Rows of garbage pushed under the stack.
+    GarbageReceived,
+    Scored,
+    /// Topped out, or out of pieces in a puzzle.
+    GameOver,
+    /// The goal of the mode was reached, or the time of an ultra game is up.
+    Finished,
+}
+
+/// Occupation of a garbage cell, after the 7 tetromino types.
+pub const GARBAGE_CELL: u8 = 8;
+
+/// The game of the player, or of each player of a versus match.
+#[derive(Resource, Component, Clone)]
+pub struct Engine {
+    pub seed: u64,
+    pub ruleset: Ruleset,
+    pub matrix: Matrix,
+    pub queue: HoldOnQueueResoure,
+    pub current: Option<Tetromino>,
+    pub score: Score,
+    pub phase: Phase,
+    /// Number of simulated frames.
+    pub frame: u64,
+    /// Frames played, the countdowns excluded.
+    pub clock: u64,
+    /// Frames left of the current countdown.
+    pub frozen: u32,
+    /// The current or last countdown is a resume one, not the ready one.
+    pub resumed: bool,
+    pub pieces: usize,
+    pub total_lines: usize,
+    /// The game ended on the goal of its mode rather than a top out.
+    pub finished: bool,
+    /// Tetromino put aside with the hold action.
+    pub hold: Option<TetrominoType>,
+    /// The last locked tetromino, with the board from before its lock.
+    pub locked: Option<LockedPiece>,
+    /// Hold was used since the last lock, it can't be used again until then.
+    hold_used: bool,
+    /// The last successful move of the current piece was a rotation.
+    rotated_last: bool,
+    gravity: Timer,
+    /// Held horizontal direction, the last pressed one wins.
+    shift: i32,
+    left_held: bool,
+    right_held: bool,
+    /// Frames the horizontal direction has been held.
+    shift_frames: u32,
+    soft_dropping: bool,
+    soft_drop_frames: u32,
+    events: Vec<EngineEvent>,
+}
+
+impl Engine {
+    pub fn new(seed: u64, ruleset: Ruleset) -> Self {
+        let mut matrix = Matrix {
+            level: ruleset.start_level,
+            ..Matrix::default()
+        };
+        let width = matrix.field_width;
+        let queue = match ruleset.mode {
+            GameMode::Puzzle => {
+                let puzzle = Puzzle::of(seed);
+                let rows = puzzle.rows.iter().flat_map(|row| row.chars());
+                let start = matrix.occupation.len() - puzzle.rows.len() * width;
+                for (cell, c) in matrix.occupation[start..].iter_mut().zip(rows) {
+                    *cell = match c {
+                        'X' => GARBAGE_CELL,
+                        _ => 0,
+                    };
+                }
+                HoldOnQueueResoure::fixed(matrix.start_pos, puzzle.pieces)
+            }
+            _ => HoldOnQueueResoure::new(matrix.start_pos, seed, ruleset.randomizer),
+        };
+        if ruleset.mode == GameMode::Dig {
+            // a hole in every row, never right above the one of the row below
+            let mut rng = StdRng::seed_from_u64(seed.rotate_left(32));
+            let mut hole = rng.gen_range(0..width);
+            let len = matrix.occupation.len();
+            for row in matrix.occupation[len - DIG_ROWS * width..]
+                .chunks_mut(width)
+                .rev()
+            {
+                row.fill(GARBAGE_CELL);
+                row[hole] = 0;
+                hole = (hole + rng.gen_range(1..width)) % width;
+            }
+        }
+        Engine {
+            seed,
+            ruleset,
+            gravity: Timer::new(
+                Duration::from_secs_f32(get_falling_speed(matrix.level)),
+                TimerMode::Repeating,
+            ),
+            matrix,
+            queue,
+            current: None,
+            score: Score::default(),
+            phase: Phase::Entry { frames: 0 },
+            frame: 0,
+            clock: 0,
+            frozen: ruleset.countdown.ready,
+            resumed: false,
+            pieces: 0,
+            total_lines: 0,
+            finished: false,
+            hold: None,
+            locked: None,
+            hold_used: false,
+            rotated_last: false,
+            shift: 0,
+            left_held: false,
+            right_held: false,
+            shift_frames: 0,
+            soft_dropping: false,
+            soft_drop_frames: 0,
+            events: vec![],
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.matrix.game_over
+    }
+
+    /// Whether the hold action can be used on the current tetromino.
+    pub fn can_hold(&self) -> bool {
+        !self.hold_used
+    }
+
+    /// Elapsed game time, from the end of the ready countdown.
+    pub fn time(&self) -> Duration {
+        FRAME_DURATION * self.clock as u32
+    }
+
+    /// Absolute positions of the current tetromino blocks.
+    pub fn current_blocks(&self) -> Vec<MatrixPosition> {
+        match self.current.as_ref() {
+            Some(tetromino) => tetromino
+                .get_blocks_position()
+                .into_iter()
+                .map(|p| self.matrix.start_pos + p)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Absolute positions where the current tetromino would land.
+    pub fn ghost_blocks(&self) -> Vec<MatrixPosition> {
+        let Some(tetromino) = self.current.as_ref() else {
+            return vec![];
+        };
+        let mut pos = self.matrix.start_pos;
+        while !self
+            .matrix
+            .collides(tetromino, pos + MatrixPosition { x: 0, y: 1 })
+        {
+            pos = pos + MatrixPosition { x: 0, y: 1 };
+        }
+        tetromino
+            .get_blocks_position()
+            .into_iter()
+            .map(|p| pos + p)
+            .collect()
+    }
+
+    /// Push `lines` rows of garbage under the stack, each one full but for the
+    /// `hole` column. The falling piece is pushed up if the garbage reaches it,
+    /// blocks pushed out of the top of the matrix, or a piece with no room
+    /// left, top out.
+    pub fn add_garbage(&mut self, lines: usize, hole: usize) {
+        let width = self.matrix.field_width;
+        let lines = lines.min(self.matrix.field_height);
+        if lines == 0 || self.is_over() {
+            return;
+        }
+        // the rows being cleared go first
+        self.collapse();
+        let mut overflow = self.matrix.occupation[..lines * width]
+            .iter()
+            .any(|c| *c != 0);
+        self.matrix.occupation.drain(..lines * width);
+        for _ in 0..lines {
+            let mut row = vec![GARBAGE_CELL; width];
+            row[hole.min(width - 1)] = 0;
+            self.matrix.occupation.extend(row);
+        }
+        self.events.push(EngineEvent::GarbageReceived);
+        if let Some(tetromino) = self.current.as_ref() {
+            while self.matrix.collides(tetromino, self.matrix.start_pos)
+                && self.matrix.start_pos.y > 0
+            {
+                self.matrix.start_pos.y -= 1;
+            }
+            overflow |= self.matrix.collides(tetromino, self.matrix.start_pos);
+            self.events.push(EngineEvent::Moved);
+        }
+        if overflow {
+            self.top_out();
+        }
+    }
+
+    /// Take the events produced since the last call.
+    pub fn drain_events(&mut self) -> Vec<EngineEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Advance the game by one frame.
+    pub fn step(&mut self, actions: &[ActionEvent]) {
+        if self.is_over() {
+            return;
+        }
+        self.frame += 1;
+
+        // a pause ends with the resume countdown
+        if actions
+            .iter()
+            .any(|ev| ev.pressed && ev.action == InputAction::Pause)
+        {
+            self.frozen = self.ruleset.countdown.resume;
+            self.resumed = true;
+        }
+        if self.frozen > 0 {
+            self.frozen -= 1;
+            for ev in actions.iter() {
+                self.track_held(ev);
+            }
+            if self.shift != 0 {
+                self.shift_frames = (self.shift_frames + 1).min(self.ruleset.handling.das);
+            }
+            return;
+        }
+        self.clock += 1;
+        // the time is up in the frame which reaches it
+        if self.ruleset.mode == GameMode::Ultra && self.clock >= ULTRA_FRAMES {
+            self.finish();
+            return;
+        }
+
+        if let Phase::Clearing { frames, .. } = &mut self.phase {
+            match *frames {
+                0 => self.collapse(),
+                _ => *frames -= 1,
+            }
+        }
+        if let Phase::Entry { frames } = &mut self.phase {
+            match *frames {
+                0 => {
+                    self.spawn();
+                    if self.matrix.game_over {
+                        return;
+                    }
+                }
+                _ => *frames -= 1,
+            }
+        }
+
+        for ev in actions.iter() {
+            self.track_held(ev);
+            if ev.pressed && self.current.is_some() && self.ruleset.preset.allows(ev.action) {
+                self.apply(ev.action);
+            }
+        }
+        self.auto_repeat();
+
+        if self.current.is_some() {
+            self.gravity.tick(FRAME_DURATION);
+            if self.gravity.just_finished() {
+                self.fall();
+            }
+        }
+        if let Some(tetromino) = self.current.as_ref() {
+            let below = self.matrix.start_pos + MatrixPosition { x: 0, y: 1 };
+            self.phase = match self.matrix.collides(tetromino, below) {
+                true => Phase::Locking,
+                false => Phase::Falling,
+            };
+        }
+    }
+
+    /// Delays of the current level.
+    pub fn delays(&self) -> Delays {
+        self.ruleset.delays.at(self.matrix.level)
+    }
+
+    /// Keep track of the held actions which repeat.
+    fn track_held(&mut self, ev: &ActionEvent) {
+        match ev.action {
+            InputAction::MoveLeft => self.left_held = ev.pressed,
+            InputAction::MoveRight => self.right_held = ev.pressed,
+            InputAction::SoftDrop => {
+                self.soft_dropping = ev.pressed;
+                self.soft_drop_frames = 0;
+                return;
+            }
+            _ => return,
+        }
+        let shift = match (ev.pressed, ev.action) {
+            (true, InputAction::MoveLeft) => -1,
+            (true, _) => 1,
+            _ if self.left_held => -1,
+            _ if self.right_held => 1,
+            _ => 0,
+        };
+        if shift != self.shift {
+            self.shift = shift;
+            self.shift_frames = 0;
+        }
+    }
+
+    /// Repeat the held moves and soft drop according to the handling.
+    fn auto_repeat(&mut self) {
+        let handling = self.ruleset.handling;
+        if self.shift != 0 {
+            self.shift_frames += 1;
+            if self.current.is_some() && self.shift_frames > handling.das {
+                if handling.arr == 0 {
+                    if self.try_move(self.shift, 0) {
+                        while self.try_move(self.shift, 0) {}
+                        self.events.push(EngineEvent::Shifted);
+                    }
+                } else if (self.shift_frames - handling.das).is_multiple_of(handling.arr) {
+                    self.shift(self.shift);
+                }
+            }
+        }
+
+        if self.soft_dropping && self.current.is_some() {
+            self.soft_drop_frames += 1;
+            let gravity_frames =
+                self.gravity.duration().as_secs_f64() / FRAME_DURATION.as_secs_f64();
+            let interval = (gravity_frames / handling.sdf.max(1) as f64).max(1.0) as u32;
+            if self.soft_drop_frames >= interval {
+                self.soft_drop_frames = 0;
+                self.gravity.reset();
+                self.soft_drop();
+            }
+        }
+    }
+
+    fn apply(&mut self, action: InputAction) {
+        match action {
+            InputAction::MoveLeft => self.shift(-1),
+            InputAction::MoveRight => self.shift(1),
+            InputAction::RotateCW => self.rotate(1),
+            InputAction::RotateCCW => self.rotate(-1),
+            InputAction::Rotate180 => self.rotate_180(),
+            InputAction::Hold => self.hold(),
+            InputAction::SoftDrop => self.soft_drop(),
+            InputAction::HardDrop => self.hard_drop(),
+            // the pause is seen by `step`, the others are handled outside of the engine
+            InputAction::Pause | InputAction::Restart | InputAction::Hint => {}
+        }
+    }
+
+    fn spawn(&mut self) {
+        // the hold piece is the last one left of a puzzle
+        match (self.queue.pop_push(), self.hold.take()) {
+            (Some(tetromino), hold) => {
+                self.hold = hold;
+                self.spawn_tetromino(tetromino);
+            }
+            (None, Some(ty)) => self.spawn_tetromino(Tetromino::new(ty)),
+            (None, None) => self.top_out(),
+        }
+    }
+
+    fn spawn_tetromino(&mut self, tetromino: Tetromino) {
+        self.phase = Phase::Falling;
+        self.rotated_last = false;
+        self.matrix.reset_start_pos();
+        self.gravity
+            .set_duration(Duration::from_secs_f32(get_falling_speed(
+                self.matrix.level,
+            )));
+        self.gravity.reset();
+        self.events.push(EngineEvent::Spawned(tetromino.ty));
+
+        if self.matrix.collides(&tetromino, self.matrix.start_pos) {
+            self.top_out();
+            if self.is_over() {
+                return;
+            }
+        }
+        self.current = Some(tetromino);
+    }
+
+    fn try_move(&mut self, x: i32, y: i32) -> bool {
+        let Some(tetromino) = self.current.as_ref() else {
+            return false;
+        };
+        let pos = self.matrix.start_pos + MatrixPosition { x, y };
+        if self.matrix.collides(tetromino, pos) {
+            return false;
+        }
+        self.matrix.start_pos = pos;
+        self.rotated_last = false;
+        self.events.push(EngineEvent::Moved);
+        true
+    }
+
+    fn shift(&mut self, x: i32) {
+        if self.try_move(x, 0) {
+            self.events.push(EngineEvent::Shifted);
+        }
+    }
+
+    fn rotate(&mut self, direction: i32) {
+        let Some(tetromino) = self.current.as_ref() else {
+            return;
+        };
+        let start_pos = self.matrix.start_pos;
+        if let Some(new_tetromino) = self.matrix.can_rotate(direction, tetromino) {
+            self.current = Some(new_tetromino);
+            self.rotated_last = true;
+            self.events.push(EngineEvent::Rotated);
+            if self.matrix.start_pos != start_pos {
+                self.events.push(EngineEvent::Kicked);
+            }
+        }
+    }
+
+    /// Two clockwise rotations, kicks included, applied only if both succeed.
+    fn rotate_180(&mut self) {
+        let Some(tetromino) = self.current.as_ref() else {
+            return;
+        };
+        let start_pos = self.matrix.start_pos;
+        let rotated = self
+            .matrix
+            .can_rotate(1, tetromino)
+            .and_then(|half| self.matrix.can_rotate(1, &half));
+        match rotated {
+            Some(new_tetromino) => {
+                self.current = Some(new_tetromino);
+                self.rotated_last = true;
+                self.events.push(EngineEvent::Rotated);
+                if self.matrix.start_pos != start_pos {
+                    self.events.push(EngineEvent::Kicked);
+                }
+            }
+            None => self.matrix.start_pos = start_pos,
+        }
+    }
+
+    /// Swap the current tetromino with the hold one, once per piece.
+    fn hold(&mut self) {
+        if self.hold_used {
+            return;
+        }
+        let Some(tetromino) = self.current.take() else {
+            return;
+        };
+        self.hold_used = true;
+        self.events.push(EngineEvent::Held);
+        match self.hold.replace(tetromino.ty) {
+            Some(ty) => self.spawn_tetromino(Tetromino::new(ty)),
+            None => self.spawn(),
+        }
+    }
+
+    /// Move the current tetromino one row down, lock it if it can't.
+    fn fall(&mut self) -> bool {
+        if self.try_move(0, 1) {
+            return true;
+        }
+        self.lock();
+        false
+    }
+
+    fn soft_drop(&mut self) {
+        if self.fall() {
+            self.add_score(ScoreEvent::soft_drop());
+            self.events.push(EngineEvent::SoftDropped);
+        }
+    }
+
+    fn hard_drop(&mut self) {
+        self.matrix.hard_dropping = true;
+        let mut lines = 0;
+        while self.try_move(0, 1) {
+            lines += 1;
+        }
+        self.add_score(ScoreEvent::hard_drop(lines));
+        self.events.push(EngineEvent::HardDropped);
+        self.lock();
+    }
+
+    /// T-spin of `tetromino` about to lock: a T rotated into place with 3 of the
+    /// corners of its box filled, both corners it points to for a full one.
+    fn spin(&self, tetromino: &Tetromino) -> Spin {
+        if tetromino.ty != TetrominoType::T || !self.rotated_last {
+            return Spin::None;
+        }
+        let filled = |x, y| {
+            self.matrix
+                .check_collision(&(self.matrix.start_pos + MatrixPosition { x, y }))
+        };
+        let corners = [filled(0, 0), filled(2, 0), filled(2, 2), filled(0, 2)];
+        if corners.iter().filter(|c| **c).count() < 3 {
+            return Spin::None;
+        }
+        // corners clockwise from the top left, the T points up in R0
+        let front = tetromino.rotation as usize;
+        match corners[front] && corners[(front + 1) % 4] {
+            true => Spin::Full,
+            false => Spin::Mini,
+        }
+    }
+
+    fn lock(&mut self) {
+        let Some(tetromino) = self.current.take() else {
+            return;
+        };
+        let spin = self.spin(&tetromino);
+        let blocks: Vec<MatrixPosition> = tetromino
+            .get_blocks_position()
+            .into_iter()
+            .map(|p| self.matrix.start_pos + p)
+            .collect();
+        self.locked = Some(LockedPiece {
+            board: self.matrix.clone(),
+            cells: blocks.clone(),
+        });
+        for pos in blocks.iter() {
+            let index = self.matrix.get_index(pos);
+            self.matrix.occupation[index] = tetromino.ty as u8 + 1;
+        }
+        self.pieces += 1;
+        self.hold_used = false;
+        self.matrix.hard_dropping = false;
+        self.events.push(EngineEvent::Locked);
+
+        if blocks.iter().any(|pos| pos.y <= 1) {
+            self.top_out();
+            self.phase = Phase::Entry {
+                frames: self.delays().are,
+            };
+            return;
+        }
+
+        let full_rows = self.matrix.full_rows();
+        let perfect = self
+            .matrix
+            .occupation
+            .chunks(self.matrix.field_width)
+            .enumerate()
+            .all(|(y, row)| full_rows.contains(&y) || row.iter().all(|c| *c == 0));
+        self.events.push(EngineEvent::Cleared(Clear {
+            lines: full_rows.len(),
+            spin,
+            perfect: !full_rows.is_empty() && perfect,
+        }));
+        if full_rows.is_empty() {
+            self.phase = Phase::Entry {
+                frames: self.delays().are,
+            };
+            if self.out_of_pieces() {
+                self.top_out();
+            }
+            return;
+        }
+        let cleared = full_rows.len();
+        self.add_score(ScoreEvent {
+            action: ScoreAction::from(cleared),
+            cleared_lines: cleared,
+        });
+        self.matrix.lines_cleared += cleared;
+        self.total_lines += cleared;
+        // the delays of the clear are those of the level it was made at
+        let delays = self.delays();
+        if self.ruleset.mode.levels_up() && self.matrix.lines_cleared >= self.matrix.level * 10 {
+            self.matrix.level = min(self.matrix.level + 1, MAX_LEVEL);
+            self.matrix.lines_cleared = 0;
+            self.events.push(EngineEvent::LevelUp);
+        }
+        self.phase = Phase::Clearing {
+            rows: full_rows,
+            frames: delays.line_clear,
+        };
+        if delays.line_clear == 0 {
+            self.collapse();
+        }
+        if self.ruleset.mode == GameMode::Sprint && self.total_lines >= SPRINT_LINES {
+            self.finish();
+        }
+    }
+
+    /// Remove the cleared rows and wait for the next piece.
+    fn collapse(&mut self) {
+        if !matches!(self.phase, Phase::Clearing { .. }) {
+            return;
+        }
+        let entry = Phase::Entry {
+            frames: self.delays().are,
+        };
+        let Phase::Clearing { rows, .. } = std::mem::replace(&mut self.phase, entry) else {
+            return;
+        };
+        self.matrix.remove_rows(&rows);
+        self.events.push(EngineEvent::LinesCleared);
+        let cleared = match self.ruleset.mode {
+            GameMode::Dig => !self.matrix.occupation.contains(&GARBAGE_CELL),
+            GameMode::Puzzle => self.matrix.occupation.iter().all(|c| *c == 0),
+            _ => false,
+        };
+        if cleared {
+            self.finish();
+        } else if self.out_of_pieces() {
+            self.top_out();
+        }
+    }
+
+    /// No piece is left to finish the puzzle with.
+    fn out_of_pieces(&self) -> bool {
+        self.ruleset.mode == GameMode::Puzzle
+            && self.queue.values.is_empty()
+            && self.hold.is_none()
+            && self.current.is_none()
+    }
+
+    /// End the game, in zen clear the whole stack instead.
+    fn top_out(&mut self) {
+        if self.ruleset.mode == GameMode::Zen {
+            self.matrix.occupation.fill(0);
+            self.events.push(EngineEvent::LinesCleared);
+            return;
+        }
+        self.matrix.game_over = true;
+        self.events.push(EngineEvent::GameOver);
+    }
+
+    fn finish(&mut self) {
+        self.matrix.game_over = true;
+        self.finished = true;
+        self.events.push(EngineEvent::Finished);
+    }
+
+    fn add_score(&mut self, ev: ScoreEvent) {
+        self.score.apply(self.matrix.level, &ev);
+        self.events.push(EngineEvent::Scored);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Clear, Countdown, DelayCurve, Delays, Engine, EngineEvent, GameMode, Handling, Phase,
+        RulePreset, Ruleset, Spin, DIG_ROWS, GARBAGE_CELL, PUZZLES, SPRINT_LINES, ULTRA_FRAMES,
+    };
+    use crate::game::{
+        global::MAX_LEVEL,
+        input::{ActionEvent, InputAction},
+        movegen::find_moves,
+        tetromino::{Tetromino, TetrominoType},
+    };
+
+    fn play(seed: u64) -> Engine {
+        let mut engine = Engine::new(seed, Ruleset::default());
+        let mut frame = 0;
+        while !engine.is_over() && frame < 20_000 {
+            let actions = match frame % 7 {
+                0 => vec![ActionEvent::press(InputAction::MoveLeft)],
+                3 => vec![ActionEvent::press(InputAction::RotateCW)],
+                5 if frame % 3 == 0 => vec![ActionEvent::press(InputAction::HardDrop)],
+                _ => vec![],
+            };
+            engine.step(&actions);
+            frame += 1;
+        }
+        engine
+    }
+
+    #[test]
+    fn test_engine_is_deterministic() {
+        let a = play(42);
+        let b = play(42);
+        assert_eq!(a.frame, b.frame);
+        assert_eq!(a.pieces, b.pieces);
+        assert_eq!(a.score.value, b.score.value);
+        assert_eq!(a.matrix.occupation, b.matrix.occupation);
+    }
+
+    #[test]
+    fn test_hard_drop_locks_piece() {
+        let mut engine = Engine::new(7, Ruleset::default());
+        engine.step(&[]);
+        assert!(engine.current.is_some());
+        engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        assert!(engine.current.is_none());
+        assert_eq!(engine.pieces, 1);
+        assert_eq!(
+            engine.matrix.occupation.iter().filter(|c| **c != 0).count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_held_move_repeats_after_das() {
+        let ruleset = Ruleset {
+            handling: Handling {
+                das: 5,
+                arr: 0,
+                sdf: 20,
+            },
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(7, ruleset);
+        engine.step(&[]);
+        let start = engine.matrix.start_pos.x;
+        engine.step(&[ActionEvent::press(InputAction::MoveLeft)]);
+        assert_eq!(engine.matrix.start_pos.x, start - 1);
+        for _ in 0..4 {
+            engine.step(&[]);
+        }
+        assert_eq!(engine.matrix.start_pos.x, start - 1);
+        engine.step(&[]);
+        let wall = engine.matrix.start_pos.x;
+        assert!(wall < start - 1);
+        assert!(engine.current_blocks().iter().any(|block| block.x == 0));
+
+        engine.step(&[ActionEvent::release(InputAction::MoveLeft)]);
+        engine.step(&[ActionEvent::press(InputAction::MoveRight)]);
+        assert_eq!(engine.matrix.start_pos.x, wall + 1);
+    }
+
+    #[test]
+    fn test_garbage_pushes_the_stack_up() {
+        let mut engine = Engine::new(7, Ruleset::default());
+        engine.step(&[]);
+        engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        let width = engine.matrix.field_width;
+        let before = engine.matrix.occupation.clone();
+        engine.add_garbage(2, 4);
+        let len = before.len();
+        assert_eq!(
+            engine.matrix.occupation[..len - 2 * width],
+            before[2 * width..]
+        );
+        let bottom = &engine.matrix.occupation[len - width..];
+        assert_eq!(
+            bottom.iter().filter(|c| **c == GARBAGE_CELL).count(),
+            width - 1
+        );
+        assert_eq!(bottom[4], 0);
+        assert!(!engine.is_over());
+
+        // the stack pushed out of the matrix tops out
+        engine.add_garbage(engine.matrix.field_height, 0);
+        assert!(engine.is_over());
+    }
+
+    #[test]
+    fn test_hold_swaps_once_per_piece() {
+        let mut engine = Engine::new(3, Ruleset::default());
+        engine.step(&[]);
+        let first = engine.current.as_ref().unwrap().ty;
+        let next = engine.queue.values.front().unwrap().ty;
+        engine.step(&[ActionEvent::press(InputAction::Hold)]);
+        assert_eq!(engine.hold, Some(first));
+        assert_eq!(engine.current.as_ref().unwrap().ty, next);
+        // a second hold is ignored until the piece locks
+        engine.step(&[ActionEvent::press(InputAction::Hold)]);
+        assert_eq!(engine.current.as_ref().unwrap().ty, next);
+
+        engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        while engine.current.is_none() {
+            engine.step(&[]);
+        }
+        engine.step(&[ActionEvent::press(InputAction::Hold)]);
+        assert_eq!(engine.current.as_ref().unwrap().ty, first);
+    }
+
+    #[test]
+    fn test_line_clear_and_entry_delays() {
+        let ruleset = Ruleset {
+            delays: DelayCurve {
+                first: Delays {
+                    are: 3,
+                    line_clear: 5,
+                },
+                ..DelayCurve::none()
+            },
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(5, ruleset);
+        engine.step(&[]);
+        // an O piece dropped in the gap of the bottom row clears it
+        let width = engine.matrix.field_width;
+        let len = engine.matrix.occupation.len();
+        for x in 0..width {
+            engine.matrix.occupation[len - width + x] = u8::from(!matches!(x, 4 | 5));
+        }
+        engine.current = Some(Tetromino::new(TetrominoType::O));
+        engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        let bottom = engine.matrix.field_height - 1;
+        assert_eq!(
+            engine.phase,
+            Phase::Clearing {
+                rows: vec![bottom],
+                frames: 5
+            }
+        );
+        engine.drain_events();
+
+        // the full row is shown for the line clear delay, then collapses
+        let mut frames = 0;
+        while matches!(engine.phase, Phase::Clearing { .. }) {
+            assert!(engine.matrix.occupation[len - width..]
+                .iter()
+                .all(|c| *c != 0));
+            engine.step(&[]);
+            frames += 1;
+        }
+        assert_eq!(frames, 6);
+        assert!(engine
+            .drain_events()
+            .iter()
+            .any(|ev| matches!(ev, EngineEvent::LinesCleared)));
+        assert_eq!(
+            engine.matrix.occupation[len - width..][4],
+            1 + TetrominoType::O as u8
+        );
+
+        // the next piece spawns after the entry delay
+        assert_eq!(engine.phase, Phase::Entry { frames: 2 });
+        for _ in 0..2 {
+            engine.step(&[]);
+            assert!(engine.current.is_none());
+        }
+        engine.step(&[]);
+        assert_eq!(engine.phase, Phase::Falling);
+    }
+
+    #[test]
+    fn test_delays_are_interpolated_by_level() {
+        let curve = DelayCurve::default();
+        assert_eq!(curve.at(1), curve.first);
+        assert_eq!(curve.at(MAX_LEVEL), curve.last);
+        let middle = curve.at(MAX_LEVEL / 2);
+        assert!(middle.line_clear < curve.first.line_clear);
+        assert!(middle.line_clear > curve.last.line_clear);
+    }
+
+    #[test]
+    fn test_t_spin_double_is_detected() {
+        let mut engine = Engine::new(1, Ruleset::default());
+        engine.step(&[]);
+        let width = engine.matrix.field_width;
+        let first_row = engine.matrix.field_height - 3;
+        for (i, row) in ["...X......", "XXX...XXXX", "XXXX.XXXXX"]
+            .iter()
+            .enumerate()
+        {
+            for (x, c) in row.chars().enumerate() {
+                engine.matrix.occupation[x + (first_row + i) * width] = u8::from(c == 'X');
+            }
+        }
+        engine.current = Some(Tetromino::new(TetrominoType::T));
+        engine.matrix.reset_start_pos();
+
+        let moves = find_moves(
+            &engine.matrix,
+            &Tetromino::new(TetrominoType::T),
+            engine.matrix.start_pos,
+        );
+        let clears = |path: &[InputAction]| {
+            let mut sim = engine.clone();
+            sim.drain_events();
+            let taps: Vec<ActionEvent> = path
+                .iter()
+                .flat_map(|a| [ActionEvent::press(*a), ActionEvent::release(*a)])
+                .collect();
+            sim.step(&taps);
+            sim.drain_events()
+                .into_iter()
+                .find_map(|ev| match ev {
+                    EngineEvent::Cleared(clear) => Some(clear),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let spin = moves
+            .iter()
+            .find(|mv| {
+                let cells = mv.cells();
+                cells.iter().filter(|c| c.y == 20).count() == 3
+                    && cells.iter().any(|c| c.x == 4 && c.y == 21)
+            })
+            .expect("T-spin double slot");
+        assert_eq!(
+            clears(&spin.path),
+            Clear {
+                lines: 2,
+                spin: Spin::Full,
+                perfect: false,
+            }
+        );
+        // dropped flat without a rotation, nothing is cleared
+        assert_eq!(clears(&[InputAction::HardDrop]), Clear::default());
+    }
+
+    fn taps(path: &[InputAction]) -> Vec<ActionEvent> {
+        path.iter()
+            .flat_map(|a| [ActionEvent::press(*a), ActionEvent::release(*a)])
+            .collect()
+    }
+
+    /// Whether some placements of the pieces left finish the game.
+    fn solve(engine: &Engine) -> bool {
+        let Some(current) = engine.current.as_ref() else {
+            return false;
+        };
+        find_moves(&engine.matrix, current, engine.matrix.start_pos)
+            .iter()
+            .any(|mv| {
+                let mut sim = engine.clone();
+                sim.step(&taps(&mv.path));
+                while sim.current.is_none() && !sim.is_over() {
+                    sim.step(&[]);
+                }
+                sim.finished || (!sim.is_over() && solve(&sim))
+            })
+    }
+
+    fn start(seed: u64, mode: GameMode) -> Engine {
+        let mut engine = Engine::new(
+            seed,
+            Ruleset {
+                mode,
+                ..Ruleset::default()
+            },
+        );
+        while engine.current.is_none() {
+            engine.step(&[]);
+        }
+        engine
+    }
+
+    #[test]
+    fn test_puzzles_can_be_solved_and_failed() {
+        for seed in 0..PUZZLES.len() as u64 {
+            let engine = start(seed, GameMode::Puzzle);
+            assert!(solve(&engine), "puzzle {} has no solution", seed);
+
+            // dropping every piece where it spawns runs out of pieces
+            let mut engine = engine.clone();
+            while !engine.is_over() {
+                engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+            }
+            assert!(!engine.finished);
+        }
+    }
+
+    #[test]
+    fn test_modes_end_on_their_goal() {
+        let mut sprint = start(2, GameMode::Sprint);
+        sprint.total_lines = SPRINT_LINES - 1;
+        let width = sprint.matrix.field_width;
+        let len = sprint.matrix.occupation.len();
+        for x in 0..width {
+            sprint.matrix.occupation[len - width + x] = u8::from(!matches!(x, 4 | 5));
+        }
+        sprint.current = Some(Tetromino::new(TetrominoType::O));
+        sprint.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        assert!(sprint.is_over() && sprint.finished);
+        assert!(sprint
+            .drain_events()
+            .iter()
+            .any(|ev| matches!(ev, EngineEvent::Finished)));
+
+        let mut ultra = start(2, GameMode::Ultra);
+        ultra.clock = ULTRA_FRAMES - 2;
+        ultra.step(&[]);
+        assert!(!ultra.is_over());
+        ultra.step(&[]);
+        assert!(ultra.finished);
+        assert_eq!(ultra.clock, ULTRA_FRAMES);
+
+        let dig = start(2, GameMode::Dig);
+        let rows: Vec<&[u8]> = dig.matrix.occupation.chunks(width).collect();
+        let garbage = &rows[rows.len() - DIG_ROWS..];
+        assert!(garbage
+            .iter()
+            .all(|row| row.iter().filter(|c| **c == GARBAGE_CELL).count() == width - 1));
+        assert!(rows[rows.len() - DIG_ROWS - 1].iter().all(|c| *c == 0));
+    }
+
+    #[test]
+    fn test_zen_never_tops_out() {
+        let mut engine = start(4, GameMode::Zen);
+        for _ in 0..1000 {
+            engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        }
+        assert!(!engine.is_over());
+        assert!(engine.pieces > 50);
+        assert_eq!(engine.matrix.level, 1);
+    }
+
+    #[test]
+    fn test_classic_rules_ignore_modern_moves() {
+        let mut engine = Engine::new(
+            3,
+            Ruleset {
+                preset: RulePreset::Classic,
+                ..Ruleset::default()
+            },
+        );
+        engine.step(&[]);
+        engine.step(&[ActionEvent::press(InputAction::Hold)]);
+        assert_eq!(engine.hold, None);
+        engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        assert_eq!(engine.pieces, 0);
+    }
+
+    #[test]
+    fn test_countdowns_freeze_the_game_but_charge_das() {
+        let ruleset = Ruleset {
+            handling: Handling {
+                das: 5,
+                arr: 0,
+                sdf: 20,
+            },
+            countdown: Countdown {
+                ready: 30,
+                resume: 20,
+            },
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(7, ruleset);
+        engine.step(&[ActionEvent::press(InputAction::MoveLeft)]);
+        for _ in 1..30 {
+            engine.step(&[]);
+        }
+        assert!(engine.current.is_none());
+        assert_eq!(engine.clock, 0);
+
+        // the piece spawns at go and slides at once with the charged DAS
+        engine.step(&[]);
+        assert_eq!(engine.clock, 1);
+        assert!(engine.current_blocks().iter().any(|block| block.x == 0));
+
+        let start = engine.matrix.start_pos;
+        engine.step(&[ActionEvent::press(InputAction::Pause)]);
+        for _ in 1..20 {
+            engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        }
+        assert_eq!(engine.matrix.start_pos, start);
+        assert_eq!(engine.pieces, 0);
+        assert_eq!(engine.clock, 1);
+        engine.step(&[ActionEvent::press(InputAction::HardDrop)]);
+        assert_eq!(engine.pieces, 1);
+        assert_eq!(engine.clock, 2);
+    }
+}
@@ -0,0 +1,421 @@
+//! Garbage exchanged between players.
+//!
+//! Every lock is turned into an attack with the `AttackTable`: the lines it
+//! cleared, T-spins, back-to-back and combo bonuses and perfect clears. An
+//! attack first cancels the garbage waiting in the incoming queue of the
+//! attacker, what is left is sent to the opponent. Incoming garbage waits for
+//! a delay, then rises under the stack the next time a piece locks without
+//! clearing a line.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{Clear, Engine, EngineEvent, Spin},
+    settings::Settings,
+};
+
+/// Garbage lines sent for each kind of clear.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttackTable {
+    /// Indexed by the cleared lines, 0 to 4.
+    pub clears: [usize; 5],
+    /// Indexed by the lines cleared with a T-spin, 0 to 3.
+    pub t_spins: [usize; 4],
+    /// Indexed by the lines cleared with a mini T-spin, 0 to 2.
+    pub mini_t_spins: [usize; 3],
+    /// Bonus of a tetris or T-spin clear following another one.
+    pub back_to_back: usize,
+    /// Bonus of the consecutive clears, the last one repeats.
+    pub combo: [usize; 12],
+    /// Bonus for emptying the matrix.
+    pub perfect_clear: usize,
+}
+
+impl Default for AttackTable {
+    fn default() -> Self {
+        AttackTable {
+            clears: [0, 0, 1, 2, 4],
+            t_spins: [0, 2, 4, 6],
+            mini_t_spins: [0, 0, 1],
+            back_to_back: 1,
+            combo: [0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5],
+            perfect_clear: 10,
+        }
+    }
+}
+
+/// Where the holes of the rising garbage are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HoleMode {
+    /// One column for all the rows of an attack.
+    #[default]
+    Clean,
+    /// A new column for every row.
+    Messy,
+}
+
+impl HoleMode {
+    pub const ALL: [HoleMode; 2] = [HoleMode::Clean, HoleMode::Messy];
+}
+
+/// How incoming garbage rises, set in the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GarbageSettings {
+    /// Frames incoming garbage waits before it can rise.
+    pub delay: u32,
+    pub holes: HoleMode,
+    /// Most rows rising at once, the rest waits for the next lock.
+    pub cap: usize,
+}
+
+impl Default for GarbageSettings {
+    fn default() -> Self {
+        GarbageSettings {
+            delay: 30,
+            holes: HoleMode::default(),
+            cap: 8,
+        }
+    }
+}
+
+/// An attack waiting in the incoming queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncomingGarbage {
+    pub lines: usize,
+    /// Frames left before it can rise.
+    pub delay: u32,
+    /// Hole column of a clean attack.
+    pub hole: usize,
+}
+
+/// Garbage of one player: the incoming queue and the state of its attacks.
+#[derive(Debug, Clone, Default)]
+pub struct GarbageState {
+    pub incoming: VecDeque<IncomingGarbage>,
+    /// Garbage lines sent to the opponent.
+    pub sent: usize,
+    /// Consecutive clears minus one, `None` once a piece locks without a clear.
+    combo: Option<usize>,
+    /// The last clear was a tetris or a T-spin.
+    back_to_back: bool,
+}
+
+impl GarbageState {
+    /// Incoming lines, and the ones done waiting.
+    pub fn pending(&self) -> (usize, usize) {
+        let total = self.incoming.iter().map(|g| g.lines).sum();
+        let ready = self
+            .incoming
+            .iter()
+            .filter(|g| g.delay == 0)
+            .map(|g| g.lines)
+            .sum();
+        (total, ready)
+    }
+
+    /// Queue an attack of the opponent.
+    pub fn receive(
+        &mut self,
+        lines: usize,
+        width: usize,
+        rules: &GarbageSettings,
+        rng: &mut StdRng,
+    ) {
+        if lines == 0 {
+            return;
+        }
+        self.incoming.push_back(IncomingGarbage {
+            lines,
+            delay: rules.delay,
+            hole: rng.gen_range(0..width),
+        });
+    }
+
+    /// Lines sent by `clear`, bonuses included.
+    pub fn attack(&mut self, table: &AttackTable, clear: &Clear) -> usize {
+        if clear.lines == 0 {
+            self.combo = None;
+            return 0;
+        }
+        let lines = clear.lines.min(4);
+        let base = match clear.spin {
+            Spin::None => table.clears[lines],
+            Spin::Mini => table.mini_t_spins[lines.min(2)],
+            Spin::Full => table.t_spins[lines.min(3)],
+        };
+        let difficult = lines == 4 || clear.spin != Spin::None;
+        let back_to_back = difficult && self.back_to_back;
+        self.back_to_back = difficult;
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        self.combo = Some(combo);
+
+        let mut attack = base + table.combo[combo.min(table.combo.len() - 1)];
+        if back_to_back {
+            attack += table.back_to_back;
+        }
+        if clear.perfect {
+            attack += table.perfect_clear;
+        }
+        attack
+    }
+
+    /// Cancel incoming garbage with `attack`, oldest first, and return what
+    /// is left of it.
+    pub fn cancel(&mut self, mut attack: usize) -> usize {
+        while attack > 0 {
+            let Some(front) = self.incoming.front_mut() else {
+                break;
+            };
+            let cancelled = front.lines.min(attack);
+            front.lines -= cancelled;
+            attack -= cancelled;
+            if front.lines == 0 {
+                self.incoming.pop_front();
+            }
+        }
+        attack
+    }
+
+    /// Raise the garbage done waiting under the stack, at most `rules.cap` rows.
+    fn rise(&mut self, engine: &mut Engine, rules: &GarbageSettings, rng: &mut StdRng) {
+        let mut room = rules.cap;
+        while room > 0 {
+            let Some(front) = self.incoming.front_mut().filter(|g| g.delay == 0) else {
+                break;
+            };
+            let lines = front.lines.min(room);
+            match rules.holes {
+                HoleMode::Clean => engine.add_garbage(lines, front.hole),
+                HoleMode::Messy => {
+                    for _ in 0..lines {
+                        engine.add_garbage(1, rng.gen_range(0..engine.matrix.field_width));
+                    }
+                }
+            }
+            front.lines -= lines;
+            room -= lines;
+            if front.lines == 0 {
+                self.incoming.pop_front();
+            }
+        }
+    }
+
+    /// Follow one engine frame: count down the delays, attack with the clears
+    /// and raise the garbage on locks without one. Return the lines to send
+    /// to the opponent.
+    pub fn update(
+        &mut self,
+        engine: &mut Engine,
+        events: &[EngineEvent],
+        rules: &GarbageSettings,
+        rng: &mut StdRng,
+    ) -> usize {
+        for garbage in self.incoming.iter_mut() {
+            garbage.delay = garbage.delay.saturating_sub(1);
+        }
+        let table = AttackTable::default();
+        let mut sent = 0;
+        for ev in events {
+            let EngineEvent::Cleared(clear) = ev else {
+                continue;
+            };
+            let attack = self.attack(&table, clear);
+            if clear.lines == 0 {
+                self.rise(engine, rules, rng);
+            } else {
+                sent += self.cancel(attack);
+            }
+        }
+        self.sent += sent;
+        sent
+    }
+}
+
+/// Attacks of the scripted practice opponent: waits in frames and the lines
+/// sent after each, played in a loop.
+const PRACTICE_SCRIPT: [(u32, usize); 6] =
+    [(300, 1), (240, 2), (300, 1), (360, 4), (240, 2), (300, 3)];
+
+/// Versus practice against a scripted opponent sending garbage on a timer.
+#[derive(Resource)]
+pub struct Practice {
+    pub active: bool,
+    pub garbage: GarbageState,
+    rules: GarbageSettings,
+    /// Attack of the script played next.
+    step: usize,
+    /// Frames before that attack.
+    wait: u32,
+    rng: StdRng,
+}
+
+impl Default for Practice {
+    fn default() -> Self {
+        Practice {
+            active: false,
+            garbage: GarbageState::default(),
+            rules: GarbageSettings::default(),
+            step: 0,
+            wait: PRACTICE_SCRIPT[0].0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl Practice {
+    pub fn new() -> Self {
+        Practice {
+            active: true,
+            ..Default::default()
+        }
+    }
+
+    /// Lines the opponent attacks with this frame.
+    fn opponent_attack(&mut self) -> usize {
+        self.wait = self.wait.saturating_sub(1);
+        if self.wait > 0 {
+            return 0;
+        }
+        let (_, lines) = PRACTICE_SCRIPT[self.step];
+        self.step = (self.step + 1) % PRACTICE_SCRIPT.len();
+        self.wait = PRACTICE_SCRIPT[self.step].0;
+        lines
+    }
+}
+
+pub fn is_practicing(practice: Res<Practice>) -> bool {
+    practice.active
+}
+
+pub fn is_not_practicing(practice: Res<Practice>) -> bool {
+    !practice.active
+}
+
+/// Start the script over with the garbage settings, for a new game.
+pub fn start_practice(mut practice: ResMut<Practice>, settings: Res<Settings>) {
+    *practice = Practice {
+        rules: settings.garbage,
+        rng: StdRng::seed_from_u64(rand::random()),
+        ..Practice::new()
+    };
+}
+
+/// Exchange garbage between the player and the script after each engine frame.
+pub fn practice_garbage_system(
+    mut engine: ResMut<Engine>,
+    mut events: EventReader<EngineEvent>,
+    mut practice: ResMut<Practice>,
+) {
+    let events: Vec<EngineEvent> = events.iter().cloned().collect();
+    let practice = practice.as_mut();
+    // what the player sends only cancels, the script doesn't keep a board
+    practice
+        .garbage
+        .update(&mut engine, &events, &practice.rules, &mut practice.rng);
+    let attack = practice.opponent_attack();
+    let width = engine.matrix.field_width;
+    practice
+        .garbage
+        .receive(attack, width, &practice.rules, &mut practice.rng);
+}
+
+/// Back to plain single player when leaving the game.
+pub fn stop_practice(mut practice: ResMut<Practice>) {
+    *practice = Practice::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{AttackTable, GarbageSettings, GarbageState, HoleMode};
+    use crate::game::engine::{Clear, Engine, EngineEvent, Ruleset, Spin, GARBAGE_CELL};
+
+    fn clear(lines: usize, spin: Spin) -> Clear {
+        Clear {
+            lines,
+            spin,
+            perfect: false,
+        }
+    }
+
+    #[test]
+    fn test_attack_bonuses() {
+        let table = AttackTable::default();
+        let mut state = GarbageState::default();
+        assert_eq!(state.attack(&table, &clear(4, Spin::None)), 4);
+        // back-to-back tetris, the second clear of a combo
+        assert_eq!(state.attack(&table, &clear(4, Spin::None)), 4 + 1);
+        // a double breaks the back-to-back, the combo goes on
+        assert_eq!(state.attack(&table, &clear(2, Spin::None)), 1 + 1);
+        assert_eq!(state.attack(&table, &clear(2, Spin::Full)), 4 + 1);
+        // a lock without a clear ends the combo
+        assert_eq!(state.attack(&table, &clear(0, Spin::None)), 0);
+        // a mini T-spin single only sends its back-to-back bonus
+        assert_eq!(state.attack(&table, &clear(1, Spin::Mini)), 1);
+
+        let mut state = GarbageState::default();
+        let perfect = Clear {
+            perfect: true,
+            ..clear(1, Spin::None)
+        };
+        assert_eq!(state.attack(&table, &perfect), table.perfect_clear);
+    }
+
+    #[test]
+    fn test_attack_cancels_incoming_first() {
+        let rules = GarbageSettings::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut state = GarbageState::default();
+        state.receive(2, 10, &rules, &mut rng);
+        state.receive(3, 10, &rules, &mut rng);
+        assert_eq!(state.cancel(3), 0);
+        assert_eq!(state.pending(), (2, 0));
+        assert_eq!(state.cancel(4), 2);
+        assert!(state.incoming.is_empty());
+    }
+
+    #[test]
+    fn test_garbage_rises_after_the_delay() {
+        let rules = GarbageSettings {
+            delay: 2,
+            holes: HoleMode::Clean,
+            cap: 8,
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut engine = Engine::new(3, Ruleset::default());
+        let mut state = GarbageState::default();
+        state.receive(3, engine.matrix.field_width, &rules, &mut rng);
+        let lock = [EngineEvent::Cleared(clear(0, Spin::None))];
+
+        // still waiting on the first lock
+        state.update(&mut engine, &lock, &rules, &mut rng);
+        assert_eq!(state.pending(), (3, 0));
+        state.update(&mut engine, &[], &rules, &mut rng);
+        assert_eq!(state.pending(), (3, 3));
+        state.update(&mut engine, &lock, &rules, &mut rng);
+        assert_eq!(state.pending(), (0, 0));
+
+        let width = engine.matrix.field_width;
+        let garbage = engine
+            .matrix
+            .occupation
+            .iter()
+            .filter(|c| **c == GARBAGE_CELL)
+            .count();
+        assert_eq!(garbage, 3 * (width - 1));
+        // a clean attack has a single hole column
+        let bottom = engine.matrix.occupation.len() - width;
+        for row in 1..3 {
+            assert_eq!(
+                engine.matrix.occupation[bottom - row * width..bottom - (row - 1) * width],
+                engine.matrix.occupation[bottom..]
+            );
+        }
+    }
+}
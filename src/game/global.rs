@@ -1,5 +1,10 @@
 //! Game Global Configuration.
 
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use super::resources::ScoreAction;
 
 pub const FIELD_WIDTH: usize = 10;
@@ -14,7 +19,8 @@ pub const WHITESPACE_HEIGHT: f32 = 60.0;
 pub const SEPARATE: f32 = BLOCK_SIZE;
 pub const RIGHT_WIDTH: f32 = 5.0 * (BLOCK_SIZE + BLOCK_SPACE);
 
-pub const HARD_DROP_SPEED: f32 = 0.0001;
+/// Duration of one simulated frame, the game logic runs at a fixed 60 Hz.
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 #[cfg(debug_assertions)]
 pub const BLOCK_SPACE: f32 = 1.0;
@@ -22,6 +28,10 @@ pub const BLOCK_SPACE: f32 = 1.0;
 #[cfg(release)]
 pub const BLOCK_SPACE: f32 = 0.0;
 
+/// Smallest zoom of the layout, windows can't be resized below their layout
+/// size times this.
+pub const MIN_LAYOUT_SCALE: f32 = 0.5;
+
 /// Calculate Game Window min-width and min-height
 pub fn get_game_window_min_size() -> (f32, f32) {
     let mut width = (BLOCK_SIZE + BLOCK_SPACE) * FIELD_WIDTH as f32 - BLOCK_SPACE;
@@ -33,6 +43,30 @@ pub fn get_game_window_min_size() -> (f32, f32) {
     (width, height)
 }
 
+/// Size of the boards of a versus match, relative to the single player one.
+pub const VERSUS_SCALE: f32 = 0.7;
+/// Size of the hold and next pieces beside a versus board, relative to its cells.
+pub const VERSUS_PREVIEW_SCALE: f32 = 0.6;
+/// Space between a versus board and the pieces beside it, or between players.
+pub const VERSUS_MARGIN: f32 = 16.0;
+/// Height of the texts above and under a versus board.
+pub const VERSUS_TEXT_HEIGHT: f32 = 48.0;
+
+/// Width of a versus player: hold piece, board and next piece.
+pub fn get_versus_player_width() -> f32 {
+    let cell = (BLOCK_SIZE + BLOCK_SPACE) * VERSUS_SCALE;
+    let preview = 4.0 * cell * VERSUS_PREVIEW_SCALE;
+    FIELD_WIDTH as f32 * cell + 2.0 * (preview + VERSUS_MARGIN)
+}
+
+/// Calculate the versus window min-width and min-height, both players side by side.
+pub fn get_versus_window_min_size() -> (f32, f32) {
+    let cell = (BLOCK_SIZE + BLOCK_SPACE) * VERSUS_SCALE;
+    let width = 2.0 * get_versus_player_width() + 4.0 * VERSUS_MARGIN + 2.0 * WHITESPACE_WIDTH;
+    let height = FIELD_HEIGHT as f32 * cell + 2.0 * VERSUS_TEXT_HEIGHT + 2.0 * WHITESPACE_HEIGHT;
+    (width, height)
+}
+
 pub fn get_matrix_size() -> (f32, f32) {
     (
         (BLOCK_SIZE + BLOCK_SPACE) * FIELD_WIDTH as f32 - BLOCK_SPACE,
@@ -40,6 +74,59 @@ pub fn get_matrix_size() -> (f32, f32) {
     )
 }
 
+/// Directory where replays and scores are saved.
+pub fn get_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("tetris")
+}
+
+/// Directory where the settings are saved.
+pub fn get_config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("tetris")
+}
+
+/// Format a duration as `mm:ss.cc`.
+pub fn format_duration(duration: Duration) -> String {
+    let centis = duration.as_millis() / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+/// Current unix timestamp in seconds.
+pub fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Format a unix timestamp as an UTC `YYYY-MM-DD HH:MM` date.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hour, minute) = (secs % 86400 / 3600, secs % 3600 / 60);
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year, month, day, hour, minute
+    )
+}
+
 pub fn get_falling_speed(level: usize) -> f32 {
     0.8 - ((level - 1) as f32 * 0.007)
 }
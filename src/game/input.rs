@@ -0,0 +1,379 @@
+//! Input action layer.
+//!
+//! Gameplay never reads raw key codes: keyboard and gamepad input is
+//! translated into `InputAction` presses and releases, collected in
+//! `ActionQueue` and handed to the engine once per fixed frame through
+//! `FrameInput`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::{engine::Engine, settings::Settings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum InputAction {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCW,
+    RotateCCW,
+    Rotate180,
+    Hold,
+    Pause,
+    Restart,
+    /// Show or hide the placement suggested by the coach.
+    Hint,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 11] = [
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::SoftDrop,
+        InputAction::HardDrop,
+        InputAction::RotateCW,
+        InputAction::RotateCCW,
+        InputAction::Rotate180,
+        InputAction::Hold,
+        InputAction::Pause,
+        InputAction::Restart,
+        InputAction::Hint,
+    ];
+
+    /// Whether the action is handed to the engine and recorded in replays.
+    pub fn is_gameplay(&self) -> bool {
+        !matches!(
+            self,
+            InputAction::Pause | InputAction::Restart | InputAction::Hint
+        )
+    }
+}
+
+/// Most keys or buttons bound to a single action.
+pub const MAX_KEYS_PER_ACTION: usize = 3;
+
+/// A press or release of an `InputAction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionEvent {
+    pub action: InputAction,
+    pub pressed: bool,
+}
+
+impl ActionEvent {
+    pub fn press(action: InputAction) -> Self {
+        ActionEvent {
+            action,
+            pressed: true,
+        }
+    }
+
+    pub fn release(action: InputAction) -> Self {
+        ActionEvent {
+            action,
+            pressed: false,
+        }
+    }
+}
+
+/// Actions collected since the last fixed frame.
+#[derive(Resource, Default)]
+pub struct ActionQueue(pub Vec<ActionEvent>);
+
+/// Actions applied to the engine in the current fixed frame.
+#[derive(Resource, Default)]
+pub struct FrameInput {
+    pub frame: u64,
+    pub actions: Vec<ActionEvent>,
+}
+
+/// Keys or gamepad buttons bound to each action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bindings<T>(pub BTreeMap<InputAction, Vec<T>>);
+
+/// Keyboard keys bound to each action.
+pub type Controls = Bindings<KeyCode>;
+
+/// Gamepad buttons bound to each action.
+pub type GamepadControls = Bindings<GamepadButtonType>;
+
+impl Default for Controls {
+    fn default() -> Self {
+        Bindings(BTreeMap::from([
+            (InputAction::MoveLeft, vec![KeyCode::Left, KeyCode::J]),
+            (InputAction::MoveRight, vec![KeyCode::Right, KeyCode::L]),
+            (InputAction::SoftDrop, vec![KeyCode::Down, KeyCode::K]),
+            (InputAction::HardDrop, vec![KeyCode::Space]),
+            (InputAction::RotateCW, vec![KeyCode::Up]),
+            (InputAction::RotateCCW, vec![KeyCode::X]),
+            (InputAction::Rotate180, vec![KeyCode::A]),
+            (InputAction::Hold, vec![KeyCode::C, KeyCode::LShift]),
+            (InputAction::Pause, vec![KeyCode::Escape, KeyCode::P]),
+            (InputAction::Restart, vec![KeyCode::R]),
+            (InputAction::Hint, vec![KeyCode::H]),
+        ]))
+    }
+}
+
+impl Default for GamepadControls {
+    fn default() -> Self {
+        use GamepadButtonType::*;
+        Bindings(BTreeMap::from([
+            (InputAction::MoveLeft, vec![DPadLeft]),
+            (InputAction::MoveRight, vec![DPadRight]),
+            (InputAction::SoftDrop, vec![DPadDown]),
+            (InputAction::HardDrop, vec![DPadUp]),
+            (InputAction::RotateCW, vec![East]),
+            (InputAction::RotateCCW, vec![South]),
+            (InputAction::Rotate180, vec![North]),
+            (InputAction::Hold, vec![LeftTrigger, RightTrigger]),
+            (InputAction::Pause, vec![Start]),
+            (InputAction::Restart, vec![Select]),
+            (InputAction::Hint, vec![LeftThumb]),
+        ]))
+    }
+}
+
+impl<T: Copy + PartialEq> Bindings<T> {
+    pub fn keys(&self, action: InputAction) -> &[T] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Action `key` is bound to, if any.
+    pub fn bound_to(&self, key: T) -> Option<InputAction> {
+        self.0
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    /// Bind `key` to `action`, taking it from the action it was bound to.
+    /// The oldest key of `action` is dropped when it already has too many.
+    pub fn bind(&mut self, action: InputAction, key: T) {
+        for keys in self.0.values_mut() {
+            keys.retain(|k| *k != key);
+        }
+        let keys = self.0.entry(action).or_default();
+        if keys.len() >= MAX_KEYS_PER_ACTION {
+            keys.remove(0);
+        }
+        keys.push(key);
+    }
+
+    pub fn clear(&mut self, action: InputAction) {
+        self.0.insert(action, vec![]);
+    }
+}
+
+impl<T: Copy + PartialEq> Bindings<T>
+where
+    Bindings<T>: Default,
+{
+    /// Bind the default keys of actions missing from an older settings file.
+    pub fn add_missing_defaults(&mut self) {
+        for (action, keys) in Bindings::<T>::default().0 {
+            if !self.0.contains_key(&action) {
+                let keys = keys
+                    .into_iter()
+                    .filter(|key| self.bound_to(*key).is_none())
+                    .collect();
+                self.0.insert(action, keys);
+            }
+        }
+    }
+}
+
+/// Keyboard and gamepad state seen through the bindings in the settings.
+#[derive(SystemParam)]
+pub struct ActionInput<'w> {
+    keys: Res<'w, Input<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    settings: Res<'w, Settings>,
+}
+
+impl<'w> ActionInput<'w> {
+    fn buttons<'a>(
+        &'a self,
+        pads: &'a [Gamepad],
+        action: InputAction,
+    ) -> impl Iterator<Item = GamepadButton> + 'a {
+        let types = self.settings.gamepad.buttons.keys(action);
+        pads.iter()
+            .flat_map(move |pad| types.iter().map(move |ty| GamepadButton::new(*pad, *ty)))
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        let pads: Vec<Gamepad> = self.gamepads.iter().collect();
+        self.just_pressed_on(&self.settings.controls, &pads, action)
+    }
+
+    /// Whether a key, button or the left stick holds `action` down.
+    pub fn pressed(&self, action: InputAction) -> bool {
+        let pads: Vec<Gamepad> = self.gamepads.iter().collect();
+        self.pressed_on(&self.settings.controls, &pads, action)
+    }
+
+    /// Gamepad of the versus player `index`, if any: gamepads are handed out
+    /// in the order they were connected.
+    fn player_gamepad(&self, index: usize) -> Vec<Gamepad> {
+        let mut pads: Vec<Gamepad> = self.gamepads.iter().collect();
+        pads.sort_by_key(|pad| pad.id);
+        pads.into_iter().skip(index).take(1).collect()
+    }
+
+    /// `just_pressed` on the keys and gamepad of the versus player `index`.
+    pub fn player_just_pressed(&self, index: usize, action: InputAction) -> bool {
+        let controls = &self.settings.versus.players[index];
+        self.just_pressed_on(controls, &self.player_gamepad(index), action)
+    }
+
+    /// `pressed` on the keys and gamepad of the versus player `index`.
+    pub fn player_pressed(&self, index: usize, action: InputAction) -> bool {
+        let controls = &self.settings.versus.players[index];
+        self.pressed_on(controls, &self.player_gamepad(index), action)
+    }
+
+    fn just_pressed_on(&self, controls: &Controls, pads: &[Gamepad], action: InputAction) -> bool {
+        self.keys
+            .any_just_pressed(controls.keys(action).iter().copied())
+            || self.buttons.any_just_pressed(self.buttons(pads, action))
+    }
+
+    fn pressed_on(&self, controls: &Controls, pads: &[Gamepad], action: InputAction) -> bool {
+        self.keys.any_pressed(controls.keys(action).iter().copied())
+            || self.buttons.any_pressed(self.buttons(pads, action))
+            || pads.iter().any(|pad| self.stick_pressed(*pad, action))
+    }
+
+    fn stick_pressed(&self, pad: Gamepad, action: InputAction) -> bool {
+        let axis = |ty| self.axes.get(GamepadAxis::new(pad, ty)).unwrap_or_default();
+        let (x, y) = (
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        stick_action(x, y, self.settings.gamepad.deadzone_ratio()) == Some(action)
+    }
+}
+
+/// Movement action the left stick points to, the soft drop only when the stick
+/// is pushed down more than sideways.
+pub fn stick_action(x: f32, y: f32, deadzone: f32) -> Option<InputAction> {
+    if -y > deadzone && -y > x.abs() {
+        Some(InputAction::SoftDrop)
+    } else if x > deadzone {
+        Some(InputAction::MoveRight)
+    } else if x < -deadzone {
+        Some(InputAction::MoveLeft)
+    } else {
+        None
+    }
+}
+
+/// Translate keyboard and gamepad presses and releases into actions. An action
+/// stays held while any of its keys, buttons or the stick holds it.
+pub fn action_input_system(
+    input: ActionInput,
+    mut held: Local<HashSet<InputAction>>,
+    mut queue: ResMut<ActionQueue>,
+) {
+    queue_actions(
+        &mut held,
+        &mut queue.0,
+        |action| input.just_pressed(action),
+        |action| input.pressed(action),
+    );
+}
+
+/// Queue a press for each gameplay action tapped or newly held down and a
+/// release for each one let go, `held` keeps the actions held down.
+pub fn queue_actions(
+    held: &mut HashSet<InputAction>,
+    queue: &mut Vec<ActionEvent>,
+    just_pressed: impl Fn(InputAction) -> bool,
+    pressed: impl Fn(InputAction) -> bool,
+) {
+    for action in InputAction::ALL
+        .into_iter()
+        .filter(InputAction::is_gameplay)
+    {
+        let tapped = just_pressed(action);
+        let down = pressed(action);
+        let was_down = held.contains(&action);
+        if tapped || (down && !was_down) {
+            queue.push(ActionEvent::press(action));
+        }
+        if (tapped || was_down) && !down {
+            queue.push(ActionEvent::release(action));
+        }
+        match down {
+            true => held.insert(action),
+            false => held.remove(&action),
+        };
+    }
+}
+
+/// Move the queued actions into `FrameInput` for the frame about to be simulated.
+pub fn collect_frame_input(
+    engine: Res<Engine>,
+    mut queue: ResMut<ActionQueue>,
+    mut frame_input: ResMut<FrameInput>,
+) {
+    frame_input.frame = engine.frame;
+    frame_input.actions = std::mem::take(&mut queue.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::KeyCode;
+
+    use super::{stick_action, Controls, GamepadControls, InputAction, MAX_KEYS_PER_ACTION};
+
+    #[test]
+    fn test_bind_moves_conflicting_key() {
+        let mut controls = Controls::default();
+        assert_eq!(controls.bound_to(KeyCode::K), Some(InputAction::SoftDrop));
+        controls.bind(InputAction::Hold, KeyCode::K);
+        assert_eq!(controls.bound_to(KeyCode::K), Some(InputAction::Hold));
+        assert_eq!(controls.keys(InputAction::SoftDrop), &[KeyCode::Down]);
+
+        for key in [KeyCode::Q, KeyCode::W, KeyCode::E] {
+            controls.bind(InputAction::Hold, key);
+        }
+        assert_eq!(controls.keys(InputAction::Hold).len(), MAX_KEYS_PER_ACTION);
+        assert_eq!(controls.keys(InputAction::Hold)[0], KeyCode::Q);
+    }
+
+    #[test]
+    fn test_missing_actions_get_free_default_keys() {
+        let mut controls = Controls::default();
+        controls.0.remove(&InputAction::Hold);
+        controls.bind(InputAction::HardDrop, KeyCode::C);
+        controls.add_missing_defaults();
+        assert_eq!(controls.keys(InputAction::Hold), &[KeyCode::LShift]);
+    }
+
+    #[test]
+    fn test_stick_respects_deadzone() {
+        assert_eq!(stick_action(0.2, 0.0, 0.3), None);
+        assert_eq!(stick_action(-0.5, 0.1, 0.3), Some(InputAction::MoveLeft));
+        assert_eq!(stick_action(0.4, -0.9, 0.3), Some(InputAction::SoftDrop));
+        assert_eq!(stick_action(0.9, -0.4, 0.3), Some(InputAction::MoveRight));
+        // pushing up never hard drops
+        assert_eq!(stick_action(0.0, 1.0, 0.3), None);
+    }
+
+    #[test]
+    fn test_default_gamepad_bindings_are_unique() {
+        let controls = GamepadControls::default();
+        for action in InputAction::ALL {
+            for button in controls.keys(action) {
+                assert_eq!(controls.bound_to(*button), Some(action));
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Local high-score tables.
+//!
+//! One top-10 table is kept per `Ruleset::variant`, they are all stored in a
+//! single file of the user data directory. Modes with a goal are ranked by the
+//! fewest frames to reach it and only finished games make it, the others by
+//! score.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{Engine, GameMode},
+    global::{format_duration, get_data_dir, now_timestamp, FRAME_DURATION},
+};
+
+pub const TABLE_SIZE: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: i32,
+    pub lines: usize,
+    pub level: usize,
+    pub frames: u64,
+    /// Unix timestamp of the game.
+    pub date: u64,
+    /// File name of the replay saved with the score.
+    #[serde(default)]
+    pub replay: Option<String>,
+}
+
+impl ScoreEntry {
+    /// Entry of the game of `engine`, without a name nor a replay yet.
+    pub fn of(engine: &Engine) -> Self {
+        ScoreEntry {
+            name: String::new(),
+            score: engine.score.value,
+            lines: engine.total_lines,
+            level: engine.matrix.level,
+            frames: engine.clock,
+            date: now_timestamp(),
+            replay: None,
+        }
+    }
+}
+
+/// What the entries of a table are sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranking {
+    /// Highest score first.
+    Score,
+    /// Fewest frames first.
+    Time,
+}
+
+impl Ranking {
+    pub fn of(mode: GameMode) -> Self {
+        match mode.has_goal() {
+            true => Ranking::Time,
+            false => Ranking::Score,
+        }
+    }
+
+    /// `entry` ranks before `other`, a tie ranks after.
+    fn before(self, entry: &ScoreEntry, other: &ScoreEntry) -> bool {
+        match self {
+            Ranking::Score => entry.score > other.score,
+            Ranking::Time => entry.frames < other.frames,
+        }
+    }
+
+    /// The value an entry is ranked by.
+    pub fn format(self, entry: &ScoreEntry) -> String {
+        match self {
+            Ranking::Score => entry.score.to_string(),
+            Ranking::Time => format_duration(FRAME_DURATION * entry.frames as u32),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub tables: BTreeMap<String, Vec<ScoreEntry>>,
+    /// Name entered for the last record, proposed for the next one.
+    pub last_name: String,
+}
+
+impl Default for Leaderboard {
+    fn default() -> Self {
+        Leaderboard {
+            tables: BTreeMap::new(),
+            last_name: "Player".to_string(),
+        }
+    }
+}
+
+impl Leaderboard {
+    /// Load the high scores, start with empty tables if there are none yet.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => ron::from_str(&content).unwrap_or_else(|e| {
+                warn!("failed to parse high scores {}: {}", path.display(), e);
+                Leaderboard::default()
+            }),
+            Err(_) => Leaderboard::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, content)
+    }
+
+    pub fn table(&self, variant: &str) -> &[ScoreEntry] {
+        self.tables.get(variant).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Position an entry would take in the table, `None` if it doesn't make it.
+    pub fn rank(&self, variant: &str, ranking: Ranking, entry: &ScoreEntry) -> Option<usize> {
+        let valid = match ranking {
+            Ranking::Score => entry.score > 0,
+            Ranking::Time => entry.frames > 0,
+        };
+        if !valid {
+            return None;
+        }
+        let rank = self
+            .table(variant)
+            .iter()
+            .position(|other| ranking.before(entry, other))
+            .unwrap_or(self.table(variant).len());
+        (rank < TABLE_SIZE).then_some(rank)
+    }
+
+    /// Position the game of `engine` would take in the table of its variant,
+    /// games of a mode with a goal only rank once they reached it.
+    pub fn rank_game(&self, engine: &Engine) -> Option<usize> {
+        let mode = engine.ruleset.mode;
+        if mode.has_goal() && !engine.finished {
+            return None;
+        }
+        self.rank(
+            &engine.ruleset.variant(),
+            Ranking::of(mode),
+            &ScoreEntry::of(engine),
+        )
+    }
+
+    /// Insert an entry and return its position, `None` if it doesn't make it.
+    pub fn insert(&mut self, variant: &str, ranking: Ranking, entry: ScoreEntry) -> Option<usize> {
+        let rank = self.rank(variant, ranking, &entry)?;
+        let table = self.tables.entry(variant.to_string()).or_default();
+        table.insert(rank, entry);
+        table.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+pub fn get_leaderboard_path() -> PathBuf {
+    get_data_dir().join("scores.ron")
+}
+
+/// Score of the game just finished that made it into its table.
+#[derive(Resource)]
+pub struct NewRecord {
+    pub variant: String,
+    pub ranking: Ranking,
+    pub entry: ScoreEntry,
+    pub rank: usize,
+    /// The player confirmed the name.
+    pub confirmed: bool,
+    pub saved: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Leaderboard, Ranking, ScoreEntry, TABLE_SIZE};
+    use crate::game::engine::{Engine, GameMode, Ruleset};
+
+    fn entry(score: i32) -> ScoreEntry {
+        ScoreEntry {
+            name: "test".to_string(),
+            score,
+            lines: 0,
+            level: 1,
+            frames: 0,
+            date: 0,
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_leaderboard_keeps_top_scores() {
+        let mut leaderboard = Leaderboard::default();
+        for score in 1..=TABLE_SIZE as i32 {
+            leaderboard.insert("a", Ranking::Score, entry(score * 10));
+        }
+        assert_eq!(leaderboard.rank("a", Ranking::Score, &entry(5)), None);
+        assert_eq!(leaderboard.insert("a", Ranking::Score, entry(55)), Some(5));
+        assert_eq!(leaderboard.insert("a", Ranking::Score, entry(200)), Some(0));
+        let table = leaderboard.table("a");
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table[0].score, 200);
+        assert_eq!(table[TABLE_SIZE - 1].score, 30);
+        // a tie ranks after the existing score
+        assert_eq!(leaderboard.rank("a", Ranking::Score, &entry(200)), Some(1));
+        assert!(leaderboard.table("b").is_empty());
+    }
+
+    #[test]
+    fn test_sprint_ranks_finished_games_by_time() {
+        let ruleset = Ruleset {
+            mode: GameMode::Sprint,
+            ..Ruleset::default()
+        };
+        let mut leaderboard = Leaderboard::default();
+        let mut finished = Engine::new(1, ruleset);
+        finished.finished = true;
+        finished.clock = 6000;
+        finished.score.value = 100;
+        let rank = leaderboard.rank_game(&finished);
+        assert_eq!(rank, Some(0));
+        let entry = ScoreEntry::of(&finished);
+        assert!(Ranking::Time.format(&entry).starts_with("01:39"));
+        leaderboard.insert(&ruleset.variant(), Ranking::Time, entry);
+
+        // a top out doesn't rank, whatever its score and time
+        let mut topped_out = Engine::new(2, ruleset);
+        topped_out.clock = 600;
+        topped_out.score.value = 10_000;
+        assert_eq!(leaderboard.rank_game(&topped_out), None);
+
+        // a faster finish ranks first, a slower one after
+        finished.clock = 5000;
+        assert_eq!(leaderboard.rank_game(&finished), Some(0));
+        finished.clock = 7000;
+        assert_eq!(leaderboard.rank_game(&finished), Some(1));
+    }
+}
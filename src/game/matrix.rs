@@ -1,7 +1,5 @@
 //! Tetromino pieces data with `Matrix`
 
-use bevy::prelude::Resource;
-
 use crate::game::global::{BLOCK_SIZE, BLOCK_SPACE};
 
 use super::{
@@ -10,14 +8,13 @@ use super::{
     tetromino::{get_offset_data, Tetromino},
 };
 
-#[derive(Resource)]
+#[derive(Clone)]
 pub struct Matrix {
     pub field_width: usize,
     pub field_height: usize,
     pub width: f32,
     pub height: f32,
     pub occupation: Vec<u8>,
-    pub create: bool,
     pub hard_dropping: bool,
     pub level: usize,
     pub game_over: bool,
@@ -38,7 +35,6 @@ impl Default for Matrix {
             width,
             height,
             occupation,
-            create: true,
             hard_dropping: false,
             level: 1,
             game_over: false,
@@ -57,7 +53,10 @@ impl Matrix {
     }
 
     pub fn check_collision(&self, pos: &MatrixPosition) -> bool {
-        if pos.x < 0 || pos.x > self.field_width as i32 - 1 || pos.y > self.field_height as i32 - 1
+        if pos.x < 0
+            || pos.x > self.field_width as i32 - 1
+            || pos.y < 0
+            || pos.y > self.field_height as i32 - 1
         {
             return true;
         }
@@ -66,6 +65,42 @@ impl Matrix {
         self.occupation[index] != 0
     }
 
+    /// Check if `tetromino` placed at `pos` overlaps a wall or an occupied cell.
+    pub fn collides(&self, tetromino: &Tetromino, pos: MatrixPosition) -> bool {
+        tetromino
+            .get_blocks_position()
+            .into_iter()
+            .any(|p| self.check_collision(&(pos + p)))
+    }
+
+    /// Rows without an empty cell, top to bottom.
+    pub fn full_rows(&self) -> Vec<usize> {
+        let width = self.field_width;
+        (0..self.field_height)
+            .filter(|y| {
+                self.occupation[y * width..(y + 1) * width]
+                    .iter()
+                    .all(|c| *c != 0)
+            })
+            .collect()
+    }
+
+    /// Remove `rows` and shift everything above them down.
+    pub fn remove_rows(&mut self, rows: &[usize]) {
+        let width = self.field_width;
+        let mut kept: Vec<Vec<u8>> = self
+            .occupation
+            .chunks(width)
+            .enumerate()
+            .filter(|(y, _)| !rows.contains(y))
+            .map(|(_, row)| row.to_vec())
+            .collect();
+        for _ in 0..rows.len() {
+            kept.insert(0, vec![0u8; width]);
+        }
+        self.occupation = kept.concat();
+    }
+
     pub fn get_translation(&self, position: MatrixPosition) -> (f32, f32) {
         let x = position.x;
         let y = position.y;
@@ -75,10 +110,6 @@ impl Matrix {
         (x, y)
     }
 
-    pub fn renew(&mut self) {
-        *self = Matrix::default();
-    }
-
     /// Check if current tetromino can rotate or not, return New Tetromino if allowed.
     pub fn can_rotate(
         &mut self,
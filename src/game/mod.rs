@@ -1,26 +1,86 @@
 //! Game logic and structs definitions
+pub mod ai;
+mod audio;
 mod components;
+pub mod config;
+pub mod engine;
+pub mod garbage;
+pub mod input;
+pub mod leaderboard;
 mod matrix;
+pub mod movegen;
+pub mod net;
+pub mod replay;
 pub mod resources;
+pub mod settings;
+pub mod spectator;
+pub mod stats;
 mod systems;
 mod tetromino;
+pub mod theme;
 pub mod timer;
+pub mod versus;
 use systems::*;
-mod global;
+pub mod global;
 mod style;
 
 use self::{
-    components::{Block, GameArea, GameDisplay, GameOverLayout, HeapCounter, PausedLayout},
-    matrix::Matrix,
-    resources::{HoldOnQueueResoure, Score, ScoreEvent},
+    ai::{ai_action_system, is_ai_playing, is_not_ai_playing, stop_ai, AiPlayer},
+    audio::{load_sounds, music_system, play_effects_system, Music, Sounds},
+    components::{
+        Block, GameArea, GameDisplay, GameOverLayout, HeapCounter, HintBlock, PausedLayout,
+    },
+    config::{get_game_config_path, GameConfig},
+    engine::{Engine, EngineEvent, Ruleset},
+    garbage::{
+        is_not_practicing, is_practicing, practice_garbage_system, start_practice, stop_practice,
+        Practice,
+    },
+    input::{action_input_system, collect_frame_input, ActionQueue, FrameInput},
+    leaderboard::{get_leaderboard_path, Leaderboard},
+    net::{is_not_online, is_online, net_step_system, stop_net, NetMatch},
+    replay::{
+        finish_recording, is_not_replaying, is_replaying, record_input_system, start_recording,
+        ReplayPlayer, ReplayRecorder,
+    },
+    settings::apply_window_mode,
+    spectator::{
+        is_not_spectating, is_spectating, publish_feed_system, spectate_system, start_spectating,
+        toggle_spectator_window_system, Spectator, SpectatorFeed,
+    },
+    stats::{record_stats_system, reset_stats, Stats},
     systems::{
+        countdown::{spawn_countdown_system, start_resume_countdown, update_countdown_system},
+        garbage_meter::{spawn_practice_meter_system, update_garbage_meter_system},
+        high_score::{
+            check_new_record, name_input_system, save_record_system, save_unconfirmed_record,
+        },
+        hint::{start_coaching, toggle_hint_system, update_hint_system, Coach},
         interactions::{game_over_button_actions, paused_button_actions},
-        minos::{spawn_current_tetromino, update_block_system},
+        minos::{
+            clear_animation_system, refresh_on_settings_change, sync_blocks_system,
+            update_block_system, RefreshBlocks,
+        },
         movement::debug_minos,
-        paused::{is_game_resumed_or_new, is_game_resumed_or_new_or_paused},
+        paused::{
+            gamepad_connection_system, is_game_resumed_or_new, is_game_resumed_or_new_or_paused,
+        },
+        playback::{replay_control_system, replay_playback_system, stop_replay, update_replay_hud},
+        scaling::fit_cameras_system,
+        setup_game::{restart_system, NextSeed},
+        stats_panel::{spawn_stats_panel_system, update_stats_panel_system},
+        versus_board::{spawn_versus_layout_system, update_versus_board_system},
+    },
+    versus::{
+        is_not_versus, is_versus, start_versus, stop_versus, versus_input_system,
+        versus_step_system, VersusMatch,
     },
 };
-use crate::{despawn_components, ui::systems::interactions::button_system, AppState};
+use crate::{
+    despawn_components,
+    ui::{components::SettingsState, systems::interactions::button_system},
+    AppState,
+};
 use bevy::prelude::*;
 use systems::layout::spawn_pause_layout_system;
 
@@ -28,17 +88,66 @@ pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        let matrix = Matrix::default();
-        let hold_on_queue = HoldOnQueueResoure::new(matrix.start_pos);
-        app.insert_resource(Score::default());
-        app.insert_resource(hold_on_queue);
-        app.insert_resource(matrix);
+        app.insert_resource(Engine::new(0, Ruleset::default()));
         app.insert_resource(HeapCounter(0));
-        app.add_event::<ScoreEvent>();
+        app.init_resource::<ActionQueue>();
+        app.init_resource::<NextSeed>();
+        app.init_resource::<FrameInput>();
+        app.init_resource::<ReplayRecorder>();
+        app.init_resource::<ReplayPlayer>();
+        app.init_resource::<AiPlayer>();
+        app.init_resource::<Coach>();
+        app.init_resource::<VersusMatch>();
+        app.init_resource::<Practice>();
+        app.init_resource::<Stats>();
+        app.init_resource::<NetMatch>();
+        app.init_resource::<Spectator>();
+        app.init_resource::<SpectatorFeed>();
+        app.init_resource::<Sounds>();
+        app.init_resource::<Music>();
+        app.insert_resource(Leaderboard::load(&get_leaderboard_path()));
+        app.insert_resource(GameConfig::load(&get_game_config_path()));
+        app.add_event::<EngineEvent>();
+        app.add_event::<RefreshBlocks>();
         // init game page
-        app.add_system(setup_game::setup_game.in_schedule(OnEnter(GameState::New)));
+        app.add_system(
+            setup_game::setup_game
+                .run_if(is_not_versus)
+                .in_schedule(OnEnter(GameState::New)),
+        );
+        // start a new engine and record its replay, games of the AI and
+        // practice games aren't recorded
+        app.add_systems(
+            (
+                setup_game::reset_engine,
+                start_recording
+                    .run_if(is_not_replaying)
+                    .run_if(is_not_ai_playing)
+                    .run_if(is_not_practicing)
+                    .run_if(is_not_spectating),
+                start_coaching,
+            )
+                .chain()
+                .in_schedule(OnEnter(GameState::New)),
+        );
         // enter game over page
-        app.add_system(layout::spawn_game_over_layout_system.in_schedule(OnEnter(GameState::Over)));
+        // keep the replay, then check for a new high score before showing the results
+        app.add_systems(
+            (
+                finish_recording.run_if(is_not_versus),
+                check_new_record,
+                apply_system_buffers,
+                layout::spawn_game_over_layout_system,
+            )
+                .chain()
+                .in_schedule(OnEnter(GameState::Over)),
+        );
+        app.add_systems(
+            (name_input_system, save_record_system)
+                .chain()
+                .in_set(OnUpdate(GameState::Over)),
+        );
+        app.add_system(save_unconfirmed_record.in_schedule(OnExit(GameState::Over)));
         // init board area
         // init right area (Score, Next shape...)
         app.add_systems(
@@ -48,61 +157,236 @@ impl Plugin for GamePlugin {
                 layout::spawn_right_system,
             )
                 .chain()
+                .distributive_run_if(is_not_versus)
+                .in_schedule(OnEnter(GameState::New)),
+        );
+        // countdowns before the game and after a pause
+        app.add_system(
+            spawn_countdown_system
+                .run_if(is_not_versus)
+                .in_schedule(OnEnter(GameState::New)),
+        );
+        app.add_system(
+            update_countdown_system
+                .run_if(is_not_versus)
+                .in_set(OnUpdate(AppState::Game)),
+        );
+        app.add_system(
+            start_resume_countdown
+                .run_if(is_not_replaying)
+                .run_if(is_not_versus)
+                .run_if(is_not_spectating)
+                .in_schedule(OnEnter(GameState::Resume)),
+        );
+
+        // live stats of the player's own games
+        app.add_systems(
+            (reset_stats, spawn_stats_panel_system)
+                .distributive_run_if(is_not_versus)
+                .distributive_run_if(is_not_replaying)
+                .distributive_run_if(is_not_spectating)
+                .in_schedule(OnEnter(GameState::New)),
+        );
+        app.add_system(update_stats_panel_system.in_set(OnUpdate(AppState::Game)));
+
+        // versus match: both players and their boards side by side
+        app.add_systems(
+            (start_versus, spawn_versus_layout_system)
+                .distributive_run_if(is_versus)
+                .in_schedule(OnEnter(GameState::New)),
+        );
+
+        // practice against the scripted opponent, its garbage meter left of the board
+        app.add_systems(
+            (start_practice, spawn_practice_meter_system)
+                .distributive_run_if(is_practicing)
                 .in_schedule(OnEnter(GameState::New)),
         );
+        app.add_system(update_garbage_meter_system.in_set(OnUpdate(AppState::Game)));
 
         // despawn game page when exit
         app.add_system(despawn_components::<GameDisplay>.in_schedule(OnExit(AppState::Game)));
         app.add_system(despawn_components::<GameArea>.in_schedule(OnExit(AppState::Game)));
+        app.add_system(despawn_components::<Block>.in_schedule(OnExit(AppState::Game)));
+        app.add_system(despawn_components::<HintBlock>.in_schedule(OnExit(AppState::Game)));
+        app.add_system(stop_replay.in_schedule(OnExit(AppState::Game)));
+        app.add_system(stop_ai.in_schedule(OnExit(AppState::Game)));
+        app.add_system(stop_versus.in_schedule(OnExit(AppState::Game)));
+        app.add_system(stop_practice.in_schedule(OnExit(AppState::Game)));
+        app.add_system(stop_net.in_schedule(OnExit(AppState::Game)));
 
-        // spawn current_tetromino
+        // translate keyboard and gamepad into actions
         app.add_system(
-            spawn_current_tetromino
+            action_input_system
                 .in_set(OnUpdate(AppState::Game))
-                .run_if(is_game_resumed_or_new),
+                .run_if(is_game_resumed_or_new)
+                .run_if(is_not_replaying)
+                .run_if(is_not_ai_playing)
+                .run_if(is_not_versus)
+                .run_if(is_not_spectating),
+        );
+
+        // step the engine once per fixed frame, the recorder observes the applied actions
+        app.add_systems(
+            (
+                ai_action_system.run_if(is_ai_playing),
+                collect_frame_input,
+                record_input_system,
+                movement::movement_system,
+                record_stats_system,
+                practice_garbage_system.run_if(is_practicing),
+            )
+                .chain()
+                .distributive_run_if(is_game_resumed_or_new)
+                .distributive_run_if(is_not_replaying)
+                .distributive_run_if(is_not_versus)
+                .distributive_run_if(is_not_spectating)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system(
+            versus_input_system
+                .in_set(OnUpdate(AppState::Game))
+                .run_if(is_game_resumed_or_new)
+                .run_if(is_versus),
+        );
+        app.add_system(
+            versus_step_system
+                .run_if(is_game_resumed_or_new)
+                .run_if(is_versus)
+                .run_if(is_not_online)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        // online, both boards are simulated with rollback on the inputs of the opponent
+        app.add_system(
+            net_step_system
+                .run_if(is_game_resumed_or_new)
+                .run_if(is_online)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system(
+            update_versus_board_system
+                .in_set(OnUpdate(AppState::Game))
+                .run_if(is_versus),
         );
 
-        // movement in game state with new or resumed
+        // replay viewer, recorded actions feed the engine instead of the keyboard
         app.add_system(
-            movement::movement_system
+            layout::spawn_replay_hud_system
+                .run_if(is_replaying)
+                .in_schedule(OnEnter(GameState::New)),
+        );
+        app.add_systems(
+            (
+                replay_control_system,
+                replay_playback_system,
+                update_replay_hud,
+            )
+                .chain()
+                .distributive_run_if(is_replaying)
                 .in_set(OnUpdate(AppState::Game))
-                .run_if(is_game_resumed_or_new),
+                .before(sync_blocks_system),
         );
 
-        // debug system
-        #[cfg(debug_assertions)]
+        // spectator feed: publish the game, or show the one of another game
+        app.add_startup_system(start_spectating);
         app.add_system(
-            debug_minos
+            publish_feed_system
                 .in_set(OnUpdate(AppState::Game))
-                .run_if(is_game_resumed_or_new),
+                .run_if(is_not_versus)
+                .run_if(is_not_spectating),
         );
         app.add_system(
-            score::update_score
+            spectate_system
                 .in_set(OnUpdate(AppState::Game))
-                .run_if(is_game_resumed_or_new),
+                .run_if(is_spectating)
+                .before(sync_blocks_system),
         );
+        app.add_system(toggle_spectator_window_system);
+
+        // sound effects of the engine events, music on every screen
+        app.add_startup_system(load_sounds);
+        app.add_system(play_effects_system.in_set(OnUpdate(AppState::Game)));
+        app.add_system(music_system);
+
+        // debug system
+        #[cfg(debug_assertions)]
         app.add_system(
-            score::update_level
+            debug_minos
                 .in_set(OnUpdate(AppState::Game))
                 .run_if(is_game_resumed_or_new),
         );
-        // update tetromino blocks
         app.add_system(
-            update_block_system
+            score::update_score
                 .in_set(OnUpdate(AppState::Game))
                 .run_if(is_game_resumed_or_new),
         );
+        // spawn and update tetromino blocks
+        app.add_systems(
+            (
+                refresh_on_settings_change,
+                sync_blocks_system,
+                update_block_system,
+                clear_animation_system,
+            )
+                .chain()
+                .distributive_run_if(is_not_versus)
+                .in_set(OnUpdate(AppState::Game)),
+        );
+        app.add_system(apply_window_mode);
+        // zoom the cameras so that the layout fits the windows
+        app.add_system(fit_cameras_system);
+        app.add_system(layout::restyle_board_system.in_set(OnUpdate(AppState::Game)));
+
+        // coach overlay, only for the player's own games
+        app.add_systems(
+            (
+                toggle_hint_system.run_if(is_game_resumed_or_new),
+                update_hint_system,
+            )
+                .chain()
+                .distributive_run_if(is_not_replaying)
+                .distributive_run_if(is_not_ai_playing)
+                .distributive_run_if(is_not_versus)
+                .distributive_run_if(is_not_spectating)
+                .in_set(OnUpdate(AppState::Game)),
+        );
 
         // change game state in AppState::Game state
-        // press key P/Esc to change to paused state
+        // press key P/Esc to change to paused state, an online match can't be paused
         app.add_system(
             systems::paused::pause_resume_system
                 .in_set(OnUpdate(AppState::Game))
-                .run_if(is_game_resumed_or_new_or_paused),
+                .run_if(is_game_resumed_or_new_or_paused)
+                .run_if(is_not_replaying)
+                .run_if(is_not_online)
+                .run_if(is_not_spectating)
+                .run_if(in_state(SettingsState::Closed)),
+        );
+        // restart at once, only the player's own games
+        app.add_system(
+            restart_system
+                .in_set(OnUpdate(AppState::Game))
+                .run_if(is_game_resumed_or_new_or_paused)
+                .run_if(is_not_replaying)
+                .run_if(is_not_ai_playing)
+                .run_if(is_not_versus)
+                .run_if(is_not_spectating)
+                .run_if(in_state(SettingsState::Closed)),
         );
+        // gamepads may be plugged in and out at any time
+        app.add_system(gamepad_connection_system);
 
         // when in game state and new or resumed
         app.add_system(spawn_pause_layout_system.in_schedule(OnEnter(GameState::Paused)));
+        // the settings menu replaces the pause layout while it is open
+        app.add_system(
+            despawn_components::<PausedLayout>.in_schedule(OnEnter(SettingsState::Open)),
+        );
+        app.add_system(
+            spawn_pause_layout_system
+                .run_if(in_state(GameState::Paused))
+                .in_schedule(OnExit(SettingsState::Open)),
+        );
 
         // Paused interactions running when paused
         app.add_system(paused_button_actions.in_set(OnUpdate(GameState::Paused)));
@@ -116,6 +400,7 @@ impl Plugin for GamePlugin {
         // despawn all entity in GameState on exit GameOver State
         app.add_system(despawn_components::<GameDisplay>.in_schedule(OnExit(GameState::Over)));
         app.add_system(despawn_components::<Block>.in_schedule(OnExit(GameState::Over)));
+        app.add_system(despawn_components::<HintBlock>.in_schedule(OnExit(GameState::Over)));
         app.add_system(despawn_components::<GameOverLayout>.in_schedule(OnExit(GameState::Over)));
         app.add_system(despawn_components::<GameArea>.in_schedule(OnExit(GameState::Over)));
     }
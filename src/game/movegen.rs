@@ -0,0 +1,343 @@
+//! Reachable placements of a tetromino.
+//!
+//! A breadth-first search over the `(x, y, rotation)` states of the piece,
+//! starting from its spawn position, with the moves the engine knows: shifts,
+//! one-row soft drops and rotations, SRS kicks included through
+//! `Matrix::can_rotate`. A hard drop from any visited state ends in a resting
+//! position; as every move costs one input, the first path found to each
+//! resting position is a shortest one. Tucks, slides under overhangs and spins
+//! come out of the search without special cases.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::{
+    components::MatrixPosition,
+    input::InputAction,
+    matrix::Matrix,
+    tetromino::{Rotation, Tetromino},
+};
+
+/// Position of a piece: the top-left corner of its box and its rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PieceState {
+    pub x: i32,
+    pub y: i32,
+    pub rotation: Rotation,
+}
+
+impl PieceState {
+    pub fn position(&self) -> MatrixPosition {
+        MatrixPosition {
+            x: self.x,
+            y: self.y,
+        }
+    }
+}
+
+/// A resting position and the shortest inputs to reach it.
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub state: PieceState,
+    pub tetromino: Tetromino,
+    /// Inputs from the spawn position, ending with the hard drop.
+    pub path: Vec<InputAction>,
+}
+
+impl Move {
+    /// Cells the piece locks into, sorted by row then column.
+    pub fn cells(&self) -> Vec<MatrixPosition> {
+        let mut cells: Vec<MatrixPosition> = self
+            .tetromino
+            .get_blocks_position()
+            .into_iter()
+            .map(|p| self.state.position() + p)
+            .collect();
+        cells.sort_by_key(|c| (c.y, c.x));
+        cells
+    }
+}
+
+/// Collision checks of a tetromino in a matrix, each one computed once.
+struct Collisions<'a> {
+    matrix: &'a Matrix,
+    cache: HashMap<PieceState, bool>,
+}
+
+impl<'a> Collisions<'a> {
+    fn new(matrix: &'a Matrix) -> Self {
+        Collisions {
+            matrix,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn collides(&mut self, tetromino: &Tetromino, state: PieceState) -> bool {
+        *self
+            .cache
+            .entry(state)
+            .or_insert_with(|| self.matrix.collides(tetromino, state.position()))
+    }
+
+    /// Lowest state reached by dropping from `state`.
+    fn drop(&mut self, tetromino: &Tetromino, mut state: PieceState) -> PieceState {
+        loop {
+            let below = PieceState {
+                y: state.y + 1,
+                ..state
+            };
+            if self.collides(tetromino, below) {
+                return state;
+            }
+            state = below;
+        }
+    }
+}
+
+/// Every resting position of `tetromino` reachable from `start`, with the
+/// shortest inputs leading to it. Nothing is reachable if the piece collides
+/// at `start`.
+pub fn find_moves(matrix: &Matrix, tetromino: &Tetromino, start: MatrixPosition) -> Vec<Move> {
+    let mut collisions = Collisions::new(matrix);
+    // `can_rotate` kicks the piece by moving the start position of the matrix
+    let mut rotator = matrix.clone();
+
+    let start = PieceState {
+        x: start.x,
+        y: start.y,
+        rotation: tetromino.rotation,
+    };
+    if collisions.collides(tetromino, start) {
+        return vec![];
+    }
+
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, tetromino.clone(), vec![])]);
+    let mut resting = HashSet::new();
+    let mut moves = vec![];
+    while let Some((state, tetromino, path)) = queue.pop_front() {
+        let landed = collisions.drop(&tetromino, state);
+        if resting.insert(landed) {
+            let mut path = path.clone();
+            path.push(InputAction::HardDrop);
+            moves.push(Move {
+                state: landed,
+                tetromino: tetromino.clone(),
+                path,
+            });
+        }
+
+        let mut next = vec![];
+        for (action, dx, dy) in [
+            (InputAction::MoveLeft, -1, 0),
+            (InputAction::MoveRight, 1, 0),
+            (InputAction::SoftDrop, 0, 1),
+        ] {
+            let moved = PieceState {
+                x: state.x + dx,
+                y: state.y + dy,
+                ..state
+            };
+            if !collisions.collides(&tetromino, moved) {
+                next.push((action, moved, tetromino.clone()));
+            }
+        }
+        for action in [
+            InputAction::RotateCW,
+            InputAction::RotateCCW,
+            InputAction::Rotate180,
+        ] {
+            if let Some((rotated, new_tetromino)) = rotate(&mut rotator, &tetromino, state, action)
+            {
+                next.push((action, rotated, new_tetromino));
+            }
+        }
+
+        for (action, state, tetromino) in next {
+            if visited.insert(state) {
+                let mut path = path.clone();
+                path.push(action);
+                queue.push_back((state, tetromino, path));
+            }
+        }
+    }
+    moves
+}
+
+/// Rotate the way the engine does, a 180 being two clockwise rotations.
+fn rotate(
+    rotator: &mut Matrix,
+    tetromino: &Tetromino,
+    state: PieceState,
+    action: InputAction,
+) -> Option<(PieceState, Tetromino)> {
+    rotator.start_pos = state.position();
+    let rotated = match action {
+        InputAction::RotateCW => rotator.can_rotate(1, tetromino),
+        InputAction::RotateCCW => rotator.can_rotate(-1, tetromino),
+        _ => rotator
+            .can_rotate(1, tetromino)
+            .and_then(|half| rotator.can_rotate(1, &half)),
+    }?;
+    let state = PieceState {
+        x: rotator.start_pos.x,
+        y: rotator.start_pos.y,
+        rotation: rotated.rotation,
+    };
+    Some((state, rotated))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{find_moves, Move};
+    use crate::game::{
+        engine::{Engine, Ruleset},
+        input::{ActionEvent, InputAction},
+        matrix::Matrix,
+        tetromino::{Rotation, Tetromino, TetrominoType},
+    };
+
+    /// Matrix with the rows of `rows` at its bottom, `X` for filled cells.
+    fn matrix_with(rows: &[&str]) -> Matrix {
+        let mut matrix = Matrix::default();
+        let first_row = matrix.field_height - rows.len();
+        for (i, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == 'X' {
+                    let index = x + (first_row + i) * matrix.field_width;
+                    matrix.occupation[index] = 1;
+                }
+            }
+        }
+        matrix
+    }
+
+    /// The piece was rotated into place, it didn't move after the rotation.
+    fn is_spin(mv: &Move) -> bool {
+        let before_drop = mv.path.len().checked_sub(2).map(|i| mv.path[i]);
+        matches!(
+            before_drop,
+            Some(InputAction::RotateCW | InputAction::RotateCCW | InputAction::Rotate180)
+        )
+    }
+
+    /// Play the path of every move in an engine, the piece has to lock in
+    /// the cells the move claims.
+    fn assert_paths_lead_to_moves(matrix: &Matrix, ty: TetrominoType) {
+        let mut engine = Engine::new(0, Ruleset::default());
+        engine.step(&[]);
+        engine.matrix.occupation = matrix.occupation.clone();
+        engine.current = Some(Tetromino::new(ty));
+        engine.matrix.reset_start_pos();
+
+        for mv in find_moves(&engine.matrix, &Tetromino::new(ty), engine.matrix.start_pos) {
+            let mut sim = engine.clone();
+            let taps: Vec<ActionEvent> = mv
+                .path
+                .iter()
+                .flat_map(|a| [ActionEvent::press(*a), ActionEvent::release(*a)])
+                .collect();
+            sim.step(&taps);
+            if sim.total_lines > 0 {
+                // cleared rows moved the cells
+                continue;
+            }
+            let locked: Vec<usize> = (0..sim.matrix.occupation.len())
+                .filter(|i| sim.matrix.occupation[*i] != 0 && matrix.occupation[*i] == 0)
+                .collect();
+            let mut expected: Vec<usize> = mv
+                .cells()
+                .iter()
+                .map(|c| engine.matrix.get_index(c))
+                .collect();
+            expected.sort();
+            assert_eq!(locked, expected, "{:?} via {:?}", ty, mv.path);
+        }
+    }
+
+    #[test]
+    fn test_empty_matrix_placements() {
+        let matrix = Matrix::default();
+        let t = find_moves(&matrix, &Tetromino::new(TetrominoType::T), matrix.start_pos);
+        // 8 columns flat, 9 upright, in each of the two orientations
+        assert_eq!(t.len(), 8 + 9 + 8 + 9);
+        // dropping straight down is a single input
+        assert!(t.iter().any(|mv| mv.path == [InputAction::HardDrop]));
+
+        let o = find_moves(&matrix, &Tetromino::new(TetrominoType::O), matrix.start_pos);
+        let cells: HashSet<_> = o.iter().map(|mv| mv.cells()).collect();
+        assert_eq!(cells.len(), 9);
+    }
+
+    #[test]
+    fn test_paths_are_shortest() {
+        let matrix = Matrix::default();
+        let start = matrix.start_pos;
+        let moves = find_moves(&matrix, &Tetromino::new(TetrominoType::J), start);
+        for mv in moves.iter() {
+            // nothing to kick against: one rotation at most, the shifts, the drop
+            let rotations = (mv.state.rotation != Rotation::R0) as usize;
+            let shifts = (mv.state.x - start.x).unsigned_abs() as usize;
+            assert_eq!(mv.path.len(), rotations + shifts + 1, "{:?}", mv.path);
+        }
+    }
+
+    #[test]
+    fn test_tuck_under_overhang() {
+        // the left of the second row from the bottom is covered, the piece has
+        // to be soft dropped then slid under the overhang
+        let matrix = matrix_with(&["XXX.......", "..........", "XXXXXXXXX."]);
+        let moves = find_moves(&matrix, &Tetromino::new(TetrominoType::I), matrix.start_pos);
+        let tuck = moves
+            .iter()
+            .find(|mv| mv.cells().iter().all(|c| c.y == 20 && c.x < 4))
+            .expect("tuck under the overhang");
+        let drop = tuck.path.iter().rposition(|a| *a == InputAction::SoftDrop);
+        let slide = tuck.path.iter().position(|a| *a == InputAction::MoveLeft);
+        assert!(drop.unwrap() < slide.unwrap(), "{:?}", tuck.path);
+
+        assert_paths_lead_to_moves(&matrix, TetrominoType::I);
+    }
+
+    #[test]
+    fn test_t_spin_double() {
+        let matrix = matrix_with(&["...X......", "XXX...XXXX", "XXXX.XXXXX"]);
+        let moves = find_moves(&matrix, &Tetromino::new(TetrominoType::T), matrix.start_pos);
+        let spin = moves
+            .iter()
+            .find(|mv| {
+                let cells = mv.cells();
+                cells.iter().filter(|c| c.y == 20).count() == 3
+                    && cells.iter().any(|c| c.x == 4 && c.y == 21)
+            })
+            .expect("T-spin double slot");
+        assert!(is_spin(spin), "{:?}", spin.path);
+
+        assert_paths_lead_to_moves(&matrix, TetrominoType::T);
+    }
+
+    #[test]
+    fn test_every_piece_matches_the_engine() {
+        let matrix = matrix_with(&["X....XX...", "XX..XXX.X.", "XXX.XXXXX."]);
+        for ty in [
+            TetrominoType::I,
+            TetrominoType::J,
+            TetrominoType::L,
+            TetrominoType::S,
+            TetrominoType::Z,
+            TetrominoType::T,
+            TetrominoType::O,
+        ] {
+            assert_paths_lead_to_moves(&matrix, ty);
+        }
+    }
+
+    #[test]
+    fn test_blocked_spawn_has_no_moves() {
+        let mut matrix = Matrix::default();
+        matrix.occupation.iter_mut().take(40).for_each(|c| *c = 1);
+        let moves = find_moves(&matrix, &Tetromino::new(TetrominoType::T), matrix.start_pos);
+        assert!(moves.is_empty());
+    }
+}
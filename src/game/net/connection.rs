@@ -0,0 +1,106 @@
+//! Non-blocking TCP connection exchanging protocol messages.
+
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::protocol::{Message, Wire};
+
+/// How long connecting to a server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Both ends of the protocol: messages are queued by `send` and written by
+/// `flush`, `poll` returns the ones fully received. Neither blocks, so they
+/// can be called every frame.
+pub struct Connection<M = Message> {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    message: PhantomData<M>,
+}
+
+impl<M: Wire> Connection<M> {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            incoming: vec![],
+            outgoing: vec![],
+            message: PhantomData,
+        })
+    }
+
+    /// Connect to `address`, `host:port`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+        let mut error = io::Error::new(io::ErrorKind::NotFound, "no address to connect to");
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                Ok(stream) => return Connection::new(stream),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    /// Queue `message` and write what the socket takes.
+    pub fn send(&mut self, message: &M) -> io::Result<()> {
+        message.encode(&mut self.outgoing);
+        self.flush()
+    }
+
+    /// Write the queued messages, as far as the socket takes them.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write what is left to send, waiting for it, and hang up.
+    pub fn close(mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        self.flush()?;
+        self.stream.shutdown(Shutdown::Write)
+    }
+
+    /// Messages received since the last poll. A closed connection is an
+    /// `UnexpectedEof` error, once the messages before it were returned.
+    pub fn poll(&mut self) -> io::Result<Vec<M>> {
+        self.flush()?;
+        let mut closed = false;
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(read) => self.incoming.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut messages = vec![];
+        while let Some(message) = M::decode(&mut self.incoming)? {
+            messages.push(message);
+        }
+        if closed && messages.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(messages)
+    }
+}
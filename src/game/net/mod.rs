@@ -0,0 +1,187 @@
+//! Versus matches over the network.
+//!
+//! Both players connect to a `tetris server` which pairs them, hands them the
+//! seed of the match and the handling of both players, and relays their
+//! inputs (see `protocol`). Each client simulates the whole match with
+//! rollback (see `rollback`) and tells the server the result once the inputs
+//! of both players confirm it.
+
+pub mod connection;
+pub mod protocol;
+pub mod rollback;
+
+use bevy::prelude::*;
+
+use self::{
+    connection::Connection,
+    protocol::Message,
+    rollback::{Progress, Rollback},
+};
+use super::{
+    engine::{Engine, Handling},
+    garbage::GarbageSettings,
+    versus::{VersusMatch, VersusPlayer, PLAYERS},
+    GameState,
+};
+use crate::AppState;
+
+/// Where joining a server stands.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum NetStatus {
+    #[default]
+    Idle,
+    /// Connected, waiting for the server to pair us with an opponent.
+    Waiting,
+    Playing,
+    Failed(String),
+}
+
+/// The connection to a versus server and the online match it plays.
+#[derive(Resource)]
+pub struct NetMatch {
+    /// An online match is being played.
+    pub active: bool,
+    pub status: NetStatus,
+    connection: Option<Connection>,
+    /// Index of the local player, given by the server.
+    pub player: usize,
+    pub seed: u64,
+    pub rules: GarbageSettings,
+    /// Handling of each player.
+    pub handling: [Handling; PLAYERS],
+    rollback: Rollback,
+}
+
+impl Default for NetMatch {
+    fn default() -> Self {
+        NetMatch {
+            active: false,
+            status: NetStatus::Idle,
+            connection: None,
+            player: 0,
+            seed: 0,
+            rules: GarbageSettings::default(),
+            handling: [Handling::default(); PLAYERS],
+            rollback: Rollback::new(0),
+        }
+    }
+}
+
+impl NetMatch {
+    /// Connect to the server at `address` and wait there for an opponent,
+    /// playing with `handling`.
+    pub fn join(&mut self, address: &str, handling: Handling) {
+        *self = NetMatch::default();
+        let joined = Connection::connect(address).and_then(|mut connection| {
+            connection.send(&Message::Hello {
+                version: protocol::PROTOCOL_VERSION,
+                handling,
+            })?;
+            Ok(connection)
+        });
+        match joined {
+            Ok(connection) => {
+                self.connection = Some(connection);
+                self.status = NetStatus::Waiting;
+            }
+            Err(e) => self.status = NetStatus::Failed(e.to_string()),
+        }
+    }
+
+    /// Close the connection.
+    pub fn leave(&mut self) {
+        *self = NetMatch::default();
+    }
+
+    fn fail(&mut self, reason: String) {
+        warn!("versus server: {}", reason);
+        self.connection = None;
+        self.status = NetStatus::Failed(reason);
+    }
+}
+
+pub fn is_online(net: Res<NetMatch>) -> bool {
+    net.active
+}
+
+pub fn is_not_online(net: Res<NetMatch>) -> bool {
+    !net.active
+}
+
+/// Wait for the server to pair us, then start the match.
+pub fn lobby_system(
+    mut net: ResMut<NetMatch>,
+    mut versus: ResMut<VersusMatch>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let Some(connection) = net.connection.as_mut() else {
+        return;
+    };
+    let messages = match connection.poll() {
+        Ok(messages) => messages,
+        Err(e) => return net.fail(e.to_string()),
+    };
+    for message in messages {
+        match message {
+            Message::Welcome { player } => net.player = player as usize % PLAYERS,
+            Message::Reject { reason } => return net.fail(reason),
+            Message::Start {
+                seed,
+                garbage,
+                handling,
+            } => {
+                net.active = true;
+                net.status = NetStatus::Playing;
+                net.seed = seed;
+                net.rules = garbage;
+                net.handling = handling;
+                net.rollback = Rollback::new(net.player);
+                *versus = VersusMatch::new();
+                app_state.set(AppState::Game);
+                game_state.set(GameState::New);
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Play a frame of the online match, see `rollback`.
+pub fn net_step_system(
+    mut net: ResMut<NetMatch>,
+    mut versus: ResMut<VersusMatch>,
+    mut players: Query<(&mut VersusPlayer, &mut Engine)>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let net = net.as_mut();
+    let Some(connection) = net.connection.as_mut() else {
+        return;
+    };
+    let mut players: Vec<_> = players.iter_mut().collect();
+    players.sort_by_key(|(player, _)| player.index);
+    let mut players: Vec<_> = players
+        .iter_mut()
+        .map(|(player, engine)| (player.as_mut(), engine.as_mut()))
+        .collect();
+    match net
+        .rollback
+        .frame(connection, versus.as_mut(), &mut players)
+    {
+        Ok(Progress::Playing) => {}
+        Ok(Progress::Over { winner }) => {
+            versus.winner = winner;
+            game_state.set(GameState::Over);
+        }
+        Err(e) => {
+            net.fail(e.to_string());
+            versus.winner = None;
+            game_state.set(GameState::Over);
+        }
+    }
+}
+
+/// Hang up when leaving the game.
+pub fn stop_net(mut net: ResMut<NetMatch>) {
+    net.leave();
+}
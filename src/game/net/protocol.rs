@@ -0,0 +1,381 @@
+//! Binary protocol spoken between the versus server and its clients.
+//!
+//! Every message is framed by its length as a little-endian `u32`, followed by
+//! a tag byte and the fields of the message, integers in little-endian. A
+//! client opens the connection with `Hello`, which carries `MAGIC` and
+//! `PROTOCOL_VERSION`; the server rejects other versions. Bump the version
+//! whenever a message changes, but keep `Hello` readable up to the version so
+//! that older clients are told why they are rejected.
+
+use std::io;
+
+use crate::game::{
+    engine::Handling,
+    garbage::{GarbageSettings, HoleMode},
+    input::{ActionEvent, InputAction},
+    versus::PLAYERS,
+};
+
+/// First bytes of a `Hello`.
+pub const MAGIC: [u8; 4] = *b"TTRS";
+pub const PROTOCOL_VERSION: u16 = 2;
+/// Port the server listens on by default.
+pub const DEFAULT_PORT: u16 = 7878;
+/// Longest message accepted, anything longer is a broken peer.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Client to server, first message of a connection, with the handling the
+    /// player plays with.
+    Hello { version: u16, handling: Handling },
+    /// Server to client, the connection is accepted as player `player`.
+    Welcome { player: u8 },
+    /// Server to client, the connection is refused and closed.
+    Reject { reason: String },
+    /// Server to both clients once the two players are there, with the
+    /// handling of each player.
+    Start {
+        seed: u64,
+        garbage: GarbageSettings,
+        handling: [Handling; PLAYERS],
+    },
+    /// Actions a player pressed or released at `frame`, sent for every frame.
+    Inputs {
+        frame: u64,
+        actions: Vec<ActionEvent>,
+    },
+    /// End of the match, `None` for a draw. Sent by a client once the inputs
+    /// of both players confirm it, then by the server to both clients.
+    Result { winner: Option<u8> },
+}
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// An action is sent as its index in `InputAction::ALL`, the high bit set for
+/// a press.
+fn encode_action(event: &ActionEvent) -> u8 {
+    let index = InputAction::ALL
+        .iter()
+        .position(|a| *a == event.action)
+        .unwrap_or_default() as u8;
+    index | if event.pressed { 0x80 } else { 0 }
+}
+
+fn encode_handling(handling: &Handling, body: &mut Vec<u8>) {
+    for value in [handling.das, handling.arr, handling.sdf] {
+        body.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn decode_action(byte: u8) -> io::Result<ActionEvent> {
+    let action = *InputAction::ALL
+        .get((byte & 0x7f) as usize)
+        .ok_or_else(|| invalid("unknown action"))?;
+    Ok(ActionEvent {
+        action,
+        pressed: byte & 0x80 != 0,
+    })
+}
+
+/// A message with a binary encoding, framed by its length.
+pub trait Wire: Sized {
+    /// Append the tag and the fields of the message to `body`.
+    fn encode_body(&self, body: &mut Vec<u8>);
+
+    /// Read the tag and the fields of a message.
+    fn decode_body(reader: &mut Reader) -> io::Result<Self>;
+
+    /// Append the framed message to `out`.
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut body = vec![];
+        self.encode_body(&mut body);
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+    }
+
+    /// Take the first complete message out of `buffer`, `None` until all its
+    /// bytes arrived.
+    fn decode(buffer: &mut Vec<u8>) -> io::Result<Option<Self>> {
+        let Some(len) = buffer.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(invalid("message too long"));
+        }
+        if buffer.len() < 4 + len {
+            return Ok(None);
+        }
+        let mut reader = Reader {
+            bytes: &buffer[4..4 + len],
+        };
+        let message =
+            Self::decode_body(&mut reader).and_then(|message| match reader.bytes.is_empty() {
+                true => Ok(message),
+                false => Err(invalid("trailing bytes in message")),
+            });
+        buffer.drain(..4 + len);
+        message.map(Some)
+    }
+}
+
+/// Fields of a message being read.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("truncated message"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The bytes left in the message.
+    pub fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn handling(&mut self) -> io::Result<Handling> {
+        Ok(Handling {
+            das: self.u32()?,
+            arr: self.u32()?,
+            sdf: self.u32()?,
+        })
+    }
+}
+
+impl Message {
+    fn tag(&self) -> u8 {
+        match self {
+            Message::Hello { .. } => 0,
+            Message::Welcome { .. } => 1,
+            Message::Reject { .. } => 2,
+            Message::Start { .. } => 3,
+            Message::Inputs { .. } => 4,
+            Message::Result { .. } => 5,
+        }
+    }
+}
+
+impl Wire for Message {
+    fn encode_body(&self, body: &mut Vec<u8>) {
+        body.push(self.tag());
+        match self {
+            Message::Hello { version, handling } => {
+                body.extend_from_slice(&MAGIC);
+                body.extend_from_slice(&version.to_le_bytes());
+                encode_handling(handling, body);
+            }
+            Message::Welcome { player } => body.push(*player),
+            Message::Reject { reason } => body.extend_from_slice(reason.as_bytes()),
+            Message::Start {
+                seed,
+                garbage,
+                handling,
+            } => {
+                body.extend_from_slice(&seed.to_le_bytes());
+                body.extend_from_slice(&garbage.delay.to_le_bytes());
+                body.push(match garbage.holes {
+                    HoleMode::Clean => 0,
+                    HoleMode::Messy => 1,
+                });
+                body.push(garbage.cap.min(u8::MAX as usize) as u8);
+                for handling in handling {
+                    encode_handling(handling, body);
+                }
+            }
+            Message::Inputs { frame, actions } => {
+                body.extend_from_slice(&frame.to_le_bytes());
+                body.push(actions.len() as u8);
+                body.extend(actions.iter().map(encode_action));
+            }
+            Message::Result { winner } => body.push(winner.unwrap_or(u8::MAX)),
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> io::Result<Message> {
+        let message = match reader.u8()? {
+            0 => {
+                if reader.take(MAGIC.len())? != MAGIC {
+                    return Err(invalid("not a tetris client"));
+                }
+                let version = reader.u16()?;
+                if version != PROTOCOL_VERSION {
+                    // the rest is laid out as that version wants
+                    reader.rest();
+                }
+                let handling = match reader.bytes.is_empty() {
+                    true => Handling::default(),
+                    false => reader.handling()?,
+                };
+                Message::Hello { version, handling }
+            }
+            1 => Message::Welcome {
+                player: reader.u8()?,
+            },
+            2 => {
+                let reason = reader.rest();
+                Message::Reject {
+                    reason: String::from_utf8_lossy(reason).into_owned(),
+                }
+            }
+            3 => Message::Start {
+                seed: reader.u64()?,
+                garbage: GarbageSettings {
+                    delay: reader.u32()?,
+                    holes: match reader.u8()? {
+                        0 => HoleMode::Clean,
+                        1 => HoleMode::Messy,
+                        _ => return Err(invalid("unknown hole mode")),
+                    },
+                    cap: reader.u8()? as usize,
+                },
+                handling: [reader.handling()?, reader.handling()?],
+            },
+            4 => {
+                let frame = reader.u64()?;
+                let count = reader.u8()? as usize;
+                let actions = reader
+                    .take(count)?
+                    .iter()
+                    .map(|b| decode_action(*b))
+                    .collect::<io::Result<_>>()?;
+                Message::Inputs { frame, actions }
+            }
+            5 => Message::Result {
+                winner: Some(reader.u8()?).filter(|w| *w != u8::MAX),
+            },
+            _ => return Err(invalid("unknown message")),
+        };
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, Wire, MAGIC, PROTOCOL_VERSION};
+    use crate::game::{
+        engine::Handling,
+        garbage::{GarbageSettings, HoleMode},
+        input::{ActionEvent, InputAction},
+    };
+
+    #[test]
+    fn test_messages_roundtrip() {
+        let messages = [
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                handling: Handling {
+                    das: 8,
+                    arr: 0,
+                    sdf: 40,
+                },
+            },
+            Message::Welcome { player: 1 },
+            Message::Reject {
+                reason: "match full".to_string(),
+            },
+            Message::Start {
+                seed: u64::MAX - 3,
+                garbage: GarbageSettings {
+                    delay: 45,
+                    holes: HoleMode::Messy,
+                    cap: 6,
+                },
+                handling: [
+                    Handling::default(),
+                    Handling {
+                        das: 7,
+                        ..Default::default()
+                    },
+                ],
+            },
+            Message::Inputs {
+                frame: 1234,
+                actions: vec![
+                    ActionEvent::press(InputAction::Rotate180),
+                    ActionEvent::release(InputAction::MoveLeft),
+                ],
+            },
+            Message::Inputs {
+                frame: 1235,
+                actions: vec![],
+            },
+            Message::Result { winner: Some(0) },
+            Message::Result { winner: None },
+        ];
+        let mut buffer = vec![];
+        for message in messages.iter() {
+            message.encode(&mut buffer);
+        }
+        // a message cut in the middle waits for its last bytes
+        let last = buffer.pop().unwrap();
+        let mut decoded = vec![];
+        while let Some(message) = Message::decode(&mut buffer).unwrap() {
+            decoded.push(message);
+        }
+        assert_eq!(decoded, messages[..messages.len() - 1]);
+        buffer.push(last);
+        assert_eq!(
+            Message::decode(&mut buffer).unwrap().as_ref(),
+            messages.last()
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_garbage_bytes_are_rejected() {
+        let mut buffer = vec![3, 0, 0, 0, 42, 0, 0];
+        assert!(Message::decode(&mut buffer).is_err());
+        let mut hello = vec![];
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            handling: Handling::default(),
+        }
+        .encode(&mut hello);
+        hello[5] = b'X';
+        assert!(Message::decode(&mut hello).is_err());
+    }
+
+    #[test]
+    fn test_older_hello_is_read() {
+        // a version 1 hello stops after the version
+        let mut hello = vec![7, 0, 0, 0, 0];
+        hello.extend_from_slice(&MAGIC);
+        hello.extend_from_slice(&1u16.to_le_bytes());
+        assert_eq!(
+            Message::decode(&mut hello).unwrap(),
+            Some(Message::Hello {
+                version: 1,
+                handling: Handling::default(),
+            })
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! Rollback netcode for online versus.
+//!
+//! Both clients simulate the whole match, both boards and the garbage between
+//! them, from the seed given by the server. The actions of the local player
+//! are applied at once and sent to the opponent. Those of the opponent are
+//! predicted to be none, so it keeps holding what it held, until they arrive.
+//! A snapshot of the match is taken before each simulated frame. When the
+//! actions received for a frame differ from the prediction, the match is
+//! restored from the snapshot of that frame and simulated again up to the
+//! current one. Snapshots are dropped once the actions of the opponent for
+//! their frame are known, and the local player waits when it gets more than
+//! `MAX_ROLLBACK` frames ahead of the opponent.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+};
+
+use super::{connection::Connection, protocol::Message};
+use crate::game::{
+    engine::Engine,
+    garbage::GarbageState,
+    input::ActionEvent,
+    versus::{VersusMatch, VersusPlayer, PLAYERS},
+};
+
+/// Frames the local player may be ahead of the last known actions of the
+/// opponent, and so the most frames simulated again by a rollback.
+pub const MAX_ROLLBACK: u64 = 15;
+
+/// Where messages of the match go through.
+pub trait Transport {
+    fn send(&mut self, message: &Message) -> io::Result<()>;
+    /// Messages received since the last poll.
+    fn poll(&mut self) -> io::Result<Vec<Message>>;
+}
+
+impl Transport for Connection {
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        Connection::send(self, message)
+    }
+
+    fn poll(&mut self) -> io::Result<Vec<Message>> {
+        Connection::poll(self)
+    }
+}
+
+/// The whole state of the match before a frame.
+struct Snapshot {
+    versus: VersusMatch,
+    players: Vec<(GarbageState, Engine)>,
+}
+
+impl Snapshot {
+    fn take(versus: &VersusMatch, players: &[(&mut VersusPlayer, &mut Engine)]) -> Self {
+        Snapshot {
+            versus: versus.clone(),
+            players: players
+                .iter()
+                .map(|(player, engine)| (player.garbage.clone(), (*engine).clone()))
+                .collect(),
+        }
+    }
+
+    fn restore(&self, versus: &mut VersusMatch, players: &mut [(&mut VersusPlayer, &mut Engine)]) {
+        *versus = self.versus.clone();
+        for ((player, engine), (garbage, saved)) in players.iter_mut().zip(self.players.iter()) {
+            player.garbage = garbage.clone();
+            **engine = saved.clone();
+        }
+    }
+}
+
+/// Outcome of the match, as far as both players' actions confirm it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    Playing,
+    /// The match is over, `winner` is `None` for a draw.
+    Over {
+        winner: Option<usize>,
+    },
+}
+
+impl Progress {
+    fn of<'a>(engines: impl Iterator<Item = &'a Engine>) -> Self {
+        let standing: Vec<usize> = engines
+            .enumerate()
+            .filter(|(_, engine)| !engine.is_over())
+            .map(|(index, _)| index)
+            .collect();
+        match standing.len() {
+            PLAYERS => Progress::Playing,
+            _ => Progress::Over {
+                winner: standing.first().copied(),
+            },
+        }
+    }
+}
+
+/// Rollback session of an online match.
+pub struct Rollback {
+    /// Index of the local player.
+    local: usize,
+    /// Next frame to simulate.
+    frame: u64,
+    /// First frame whose actions of the opponent are still predicted.
+    confirmed: u64,
+    /// Snapshots before each frame from `confirmed` on.
+    snapshots: VecDeque<Snapshot>,
+    /// Actions of the local player for each frame from `confirmed` on.
+    local_actions: VecDeque<Vec<ActionEvent>>,
+    /// Actions of the opponent received for frames from `confirmed` on.
+    remote_actions: BTreeMap<u64, Vec<ActionEvent>>,
+    /// Earliest simulated frame whose prediction turned out wrong.
+    mispredicted: Option<u64>,
+    /// Frames simulated again so far.
+    resimulated: u64,
+}
+
+impl Rollback {
+    pub fn new(local: usize) -> Self {
+        Rollback {
+            local,
+            frame: 0,
+            confirmed: 0,
+            snapshots: VecDeque::new(),
+            local_actions: VecDeque::new(),
+            remote_actions: BTreeMap::new(),
+            mispredicted: None,
+            resimulated: 0,
+        }
+    }
+
+    /// Play one fixed frame: take in the actions of the opponent, roll back
+    /// if they were mispredicted, then simulate the next frame with the
+    /// actions of the local player unless too far ahead.
+    pub fn frame<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        versus: &mut VersusMatch,
+        players: &mut [(&mut VersusPlayer, &mut Engine)],
+    ) -> io::Result<Progress> {
+        // before a rollback overwrites them
+        let actions = std::mem::take(&mut players[self.local].0.actions);
+        for message in transport.poll()? {
+            match message {
+                Message::Inputs { frame, actions } => self.receive(frame, actions),
+                // the opponent left
+                Message::Result { winner } => {
+                    return Ok(Progress::Over {
+                        winner: winner.map(usize::from),
+                    })
+                }
+                _ => {}
+            }
+        }
+        if let Some(frame) = self.mispredicted.take() {
+            self.rewind(frame, versus, players);
+        }
+        if self.frame - self.confirmed < MAX_ROLLBACK {
+            transport.send(&Message::Inputs {
+                frame: self.frame,
+                actions: actions.clone(),
+            })?;
+            self.local_actions.push_back(actions);
+            self.snapshots.push_back(Snapshot::take(versus, players));
+            self.simulate(self.frame, versus, players);
+            self.frame += 1;
+        } else {
+            // wait for the opponent, the actions are played next frame
+            players[self.local].0.actions = actions;
+        }
+        self.confirm();
+
+        let progress = match self.snapshots.front() {
+            Some(snapshot) => Progress::of(snapshot.players.iter().map(|(_, engine)| engine)),
+            None => Progress::of(players.iter().map(|(_, engine)| &**engine)),
+        };
+        if let Progress::Over { winner } = progress {
+            transport.send(&Message::Result {
+                winner: winner.map(|w| w as u8),
+            })?;
+        }
+        Ok(progress)
+    }
+
+    fn receive(&mut self, frame: u64, actions: Vec<ActionEvent>) {
+        if frame < self.confirmed {
+            return;
+        }
+        // the frame was simulated with no actions of the opponent
+        if frame < self.frame && !actions.is_empty() {
+            self.mispredicted = Some(self.mispredicted.map_or(frame, |f| f.min(frame)));
+        }
+        self.remote_actions.insert(frame, actions);
+    }
+
+    /// Restore the match before `frame` and simulate it again up to the
+    /// current frame.
+    fn rewind(
+        &mut self,
+        frame: u64,
+        versus: &mut VersusMatch,
+        players: &mut [(&mut VersusPlayer, &mut Engine)],
+    ) {
+        self.snapshots[(frame - self.confirmed) as usize].restore(versus, players);
+        for frame in frame..self.frame {
+            let index = (frame - self.confirmed) as usize;
+            self.snapshots[index] = Snapshot::take(versus, players);
+            self.simulate(frame, versus, players);
+        }
+        self.resimulated += self.frame - frame;
+    }
+
+    fn simulate(
+        &self,
+        frame: u64,
+        versus: &mut VersusMatch,
+        players: &mut [(&mut VersusPlayer, &mut Engine)],
+    ) {
+        let remote = (self.local + 1) % PLAYERS;
+        players[self.local].0.actions =
+            self.local_actions[(frame - self.confirmed) as usize].clone();
+        players[remote].0.actions = self.remote_actions.get(&frame).cloned().unwrap_or_default();
+        // the match is only over once confirmed, see `frame`
+        versus.step(players);
+    }
+
+    /// Drop the snapshots of the frames whose actions are all known.
+    fn confirm(&mut self) {
+        while self.confirmed < self.frame && self.remote_actions.contains_key(&self.confirmed) {
+            self.remote_actions.remove(&self.confirmed);
+            self.snapshots.pop_front();
+            self.local_actions.pop_front();
+            self.confirmed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{Progress, Rollback, Transport, MAX_ROLLBACK};
+    use crate::game::{
+        ai::AiPlayer,
+        engine::{Engine, Ruleset},
+        garbage::GarbageSettings,
+        input::ActionEvent,
+        net::protocol::{Message, Wire},
+        versus::{VersusMatch, VersusPlayer},
+    };
+
+    /// Messages in flight, with the tick they arrive at.
+    type Link = Arc<Mutex<VecDeque<(u64, Vec<u8>)>>>;
+
+    /// One end of an in-memory transport delivering messages `latency` polls
+    /// after they were sent, through the wire format.
+    struct Loopback {
+        latency: u64,
+        tick: u64,
+        outgoing: Link,
+        incoming: Link,
+    }
+
+    impl Loopback {
+        fn pair(latency: u64) -> (Loopback, Loopback) {
+            let (a, b) = (Link::default(), Link::default());
+            let end = |outgoing: &Link, incoming: &Link| Loopback {
+                latency,
+                tick: 0,
+                outgoing: outgoing.clone(),
+                incoming: incoming.clone(),
+            };
+            (end(&a, &b), end(&b, &a))
+        }
+    }
+
+    impl Transport for Loopback {
+        fn send(&mut self, message: &Message) -> io::Result<()> {
+            let mut bytes = vec![];
+            message.encode(&mut bytes);
+            let arrival = self.tick + self.latency;
+            self.outgoing.lock().unwrap().push_back((arrival, bytes));
+            Ok(())
+        }
+
+        fn poll(&mut self) -> io::Result<Vec<Message>> {
+            self.tick += 1;
+            let mut incoming = self.incoming.lock().unwrap();
+            let mut messages = vec![];
+            while incoming
+                .front()
+                .is_some_and(|(arrival, _)| *arrival <= self.tick)
+            {
+                let (_, mut bytes) = incoming.pop_front().unwrap();
+                messages.extend(Message::decode(&mut bytes)?);
+            }
+            Ok(messages)
+        }
+    }
+
+    /// A client of the match: both players and their engines.
+    struct Client {
+        versus: VersusMatch,
+        players: Vec<(VersusPlayer, Engine)>,
+        rollback: Rollback,
+        ai: AiPlayer,
+        /// Actions of the local player by frame, as sent.
+        played: Vec<Vec<ActionEvent>>,
+    }
+
+    impl Client {
+        fn new(local: usize) -> Self {
+            let rules = GarbageSettings {
+                delay: 0,
+                ..Default::default()
+            };
+            Client {
+                versus: VersusMatch::seeded(3, rules),
+                players: (0..2)
+                    .map(|index| {
+                        let player = VersusPlayer {
+                            index,
+                            ..Default::default()
+                        };
+                        (player, Engine::new(3, Ruleset::default()))
+                    })
+                    .collect(),
+                rollback: Rollback::new(local),
+                ai: AiPlayer::new(),
+                played: vec![],
+            }
+        }
+
+        fn frame(&mut self, transport: &mut Loopback, playing: bool) -> Progress {
+            let local = self.rollback.local;
+            if playing && self.rollback.frame - self.rollback.confirmed < MAX_ROLLBACK {
+                let engine = &self.players[local].1;
+                self.players[local].0.actions = self.ai.next_actions(engine, 2);
+                self.played.push(self.players[local].0.actions.clone());
+            }
+            let mut players: Vec<_> = self.players.iter_mut().map(|(p, e)| (p, e)).collect();
+            self.rollback
+                .frame(transport, &mut self.versus, &mut players)
+                .unwrap()
+        }
+    }
+
+    /// Play a match between two clients `latency` polls apart, and check that
+    /// both end where a local match with the same actions is.
+    fn play(latency: u64) {
+        let (mut first_end, mut second_end) = Loopback::pair(latency);
+        let mut first = Client::new(0);
+        let mut second = Client::new(1);
+        for frame in 0..1550 {
+            // the last frames are played without actions, which is what the
+            // opponent predicts until they arrive
+            let playing = frame < 1500;
+            assert_eq!(first.frame(&mut first_end, playing), Progress::Playing);
+            assert_eq!(second.frame(&mut second_end, playing), Progress::Playing);
+        }
+        assert!(first.rollback.resimulated > 0);
+        assert!(second.rollback.resimulated > 0);
+        assert!(first
+            .players
+            .iter()
+            .any(|(player, _)| player.garbage.sent > 0));
+
+        for client in [&first, &second] {
+            let mut reference = Client::new(0);
+            for frame in 0..client.rollback.frame as usize {
+                for (index, played) in [&first.played, &second.played].into_iter().enumerate() {
+                    reference.players[index].0.actions =
+                        played.get(frame).cloned().unwrap_or_default();
+                }
+                let mut players: Vec<_> =
+                    reference.players.iter_mut().map(|(p, e)| (p, e)).collect();
+                reference.versus.step(&mut players);
+            }
+            for index in 0..2 {
+                assert_eq!(
+                    client.players[index].1.matrix.occupation,
+                    reference.players[index].1.matrix.occupation
+                );
+                assert_eq!(
+                    client.players[index].0.garbage.sent,
+                    reference.players[index].0.garbage.sent
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rollback_converges_with_latency() {
+        play(6);
+    }
+
+    #[test]
+    fn test_rollback_waits_for_a_late_opponent() {
+        play(2 * MAX_ROLLBACK);
+    }
+}
@@ -0,0 +1,325 @@
+//! Replay recording and playback.
+//!
+//! A replay stores everything needed to re-simulate a game: the seed, the
+//! ruleset and every action press and release with the frame it was applied in.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{Engine, Ruleset},
+    global::{get_data_dir, now_timestamp},
+    input::{ActionEvent, FrameInput, InputAction},
+    systems::hint::Coach,
+};
+
+pub const REPLAY_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedAction {
+    pub frame: u64,
+    pub event: ActionEvent,
+}
+
+/// Final results of a game, as claimed by the replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub score: i32,
+    pub lines: usize,
+    pub frames: u64,
+    pub pieces: usize,
+    /// The coach was shown during the game, which isn't ranked then.
+    #[serde(default)]
+    pub coached: bool,
+}
+
+impl From<&Engine> for ReplayResult {
+    fn from(engine: &Engine) -> Self {
+        ReplayResult {
+            score: engine.score.value,
+            lines: engine.total_lines,
+            frames: engine.frame,
+            pieces: engine.pieces,
+            coached: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Unix timestamp of the game start.
+    pub created: u64,
+    pub seed: u64,
+    pub ruleset: Ruleset,
+    pub inputs: Vec<RecordedAction>,
+    pub result: Option<ReplayResult>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, ruleset: Ruleset) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            created: now_timestamp(),
+            seed,
+            ruleset,
+            inputs: vec![],
+            result: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let content = fs::read_to_string(path)?;
+        let replay: Replay =
+            ron::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported replay version {}", replay.version),
+            ));
+        }
+        Ok(replay)
+    }
+
+    /// Save the replay in `dir`, the file name carries the date and the score.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let score = self.result.map(|r| r.score).unwrap_or_default();
+        let path = dir.join(format!("{}-{:07}.ron", self.created, score));
+        let content =
+            ron::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    /// Step `engine` one frame with the recorded actions, `cursor` is the index
+    /// of the next action to apply. Return the applied actions.
+    pub fn step(&self, engine: &mut Engine, cursor: &mut usize) -> Vec<ActionEvent> {
+        let mut actions = vec![];
+        while let Some(recorded) = self.inputs.get(*cursor) {
+            if recorded.frame > engine.frame {
+                break;
+            }
+            // actions of frames already simulated can't be applied anymore.
+            if recorded.frame == engine.frame {
+                actions.push(recorded.event);
+            }
+            *cursor += 1;
+        }
+        engine.step(&actions);
+        actions
+    }
+
+    /// Re-simulate the whole replay, stop at game over or after `max_frames`.
+    pub fn simulate(&self, max_frames: u64) -> Engine {
+        let mut engine = Engine::new(self.seed, self.ruleset);
+        let mut cursor = 0;
+        while !engine.is_over() && engine.frame < max_frames {
+            self.step(&mut engine, &mut cursor);
+        }
+        engine
+    }
+
+    /// Number of frames the replay lasts, if known.
+    pub fn frames(&self) -> Option<u64> {
+        self.result.map(|r| r.frames)
+    }
+}
+
+pub fn get_replay_dir() -> PathBuf {
+    get_data_dir().join("replays")
+}
+
+/// List saved replays, newest first.
+pub fn list_replays() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(get_replay_dir())
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+/// Replay of the game currently played.
+#[derive(Resource, Default)]
+pub struct ReplayRecorder {
+    pub replay: Option<Replay>,
+    /// Replay of the game just over, until it is saved.
+    pub finished: Option<Replay>,
+    /// Path of the last saved replay.
+    pub last_saved: Option<PathBuf>,
+}
+
+impl ReplayRecorder {
+    /// Save the replay of the game just over, once.
+    pub fn save_finished(&mut self) {
+        let Some(replay) = self.finished.take() else {
+            return;
+        };
+        match replay.save(&get_replay_dir()) {
+            Ok(path) => {
+                info!("replay saved to {}", path.display());
+                self.last_saved = Some(path);
+            }
+            Err(e) => warn!("failed to save replay: {}", e),
+        }
+    }
+}
+
+pub const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Replay currently watched, it feeds the engine instead of the keyboard.
+#[derive(Resource, Default)]
+pub struct ReplayPlayer {
+    pub replay: Option<Replay>,
+    /// Index of the next recorded action.
+    pub cursor: usize,
+    pub paused: bool,
+    pub speed: f32,
+    /// Frames to simulate while paused.
+    pub steps: u32,
+    /// Frame to jump to.
+    pub seek: Option<u64>,
+    /// Actions held down at the current frame.
+    pub held: HashSet<InputAction>,
+    pub elapsed: Duration,
+}
+
+impl ReplayPlayer {
+    pub fn start(&mut self, replay: Replay) {
+        *self = ReplayPlayer {
+            replay: Some(replay),
+            speed: 1.0,
+            ..Default::default()
+        };
+    }
+
+    pub fn change_speed(&mut self, faster: bool) {
+        let index = REPLAY_SPEEDS
+            .iter()
+            .position(|s| *s == self.speed)
+            .unwrap_or(2);
+        let index = if faster {
+            (index + 1).min(REPLAY_SPEEDS.len() - 1)
+        } else {
+            index.saturating_sub(1)
+        };
+        self.speed = REPLAY_SPEEDS[index];
+    }
+
+    /// Keep track of the held actions.
+    pub fn track(&mut self, actions: &[ActionEvent]) {
+        for ev in actions {
+            if ev.pressed {
+                self.held.insert(ev.action);
+            } else {
+                self.held.remove(&ev.action);
+            }
+        }
+    }
+}
+
+pub fn is_replaying(player: Res<ReplayPlayer>) -> bool {
+    player.replay.is_some()
+}
+
+pub fn is_not_replaying(player: Res<ReplayPlayer>) -> bool {
+    player.replay.is_none()
+}
+
+pub fn start_recording(engine: Res<Engine>, mut recorder: ResMut<ReplayRecorder>) {
+    recorder.replay = Some(Replay::new(engine.seed, engine.ruleset));
+    recorder.finished = None;
+}
+
+pub fn record_input_system(frame_input: Res<FrameInput>, mut recorder: ResMut<ReplayRecorder>) {
+    if let Some(replay) = recorder.replay.as_mut() {
+        replay
+            .inputs
+            .extend(frame_input.actions.iter().map(|event| RecordedAction {
+                frame: frame_input.frame,
+                event: *event,
+            }));
+    }
+}
+
+/// Keep the recorded replay with the final score when the game is over, it is
+/// saved from the results screen, or with a new high score.
+pub fn finish_recording(
+    engine: Res<Engine>,
+    coach: Res<Coach>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    recorder.last_saved = None;
+    recorder.finished = recorder.replay.take().map(|mut replay| {
+        replay.result = Some(ReplayResult {
+            coached: coach.used,
+            ..ReplayResult::from(engine.as_ref())
+        });
+        replay
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordedAction, Replay};
+    use crate::game::{
+        engine::{Engine, Ruleset},
+        input::{ActionEvent, InputAction},
+    };
+
+    #[test]
+    fn test_replay_roundtrip() {
+        let mut replay = Replay::new(3, Ruleset::default());
+        replay.inputs.push(RecordedAction {
+            frame: 12,
+            event: ActionEvent::press(InputAction::HardDrop),
+        });
+        let dir = std::env::temp_dir().join("tetris-replay-test");
+        let path = replay.save(&dir).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        assert_eq!(loaded.seed, 3);
+        assert_eq!(loaded.inputs, replay.inputs);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_resimulation() {
+        let mut engine = Engine::new(11, Ruleset::default());
+        let mut replay = Replay::new(11, Ruleset::default());
+        for frame in 0..3000u64 {
+            let actions = match frame % 40 {
+                5 => vec![ActionEvent::press(InputAction::RotateCW)],
+                9 => vec![ActionEvent::press(InputAction::MoveRight)],
+                30 => vec![ActionEvent::press(InputAction::HardDrop)],
+                _ => vec![],
+            };
+            replay
+                .inputs
+                .extend(actions.iter().map(|event| RecordedAction {
+                    frame: engine.frame,
+                    event: *event,
+                }));
+            engine.step(&actions);
+        }
+
+        let mut replayed = Engine::new(replay.seed, replay.ruleset);
+        let mut cursor = 0;
+        while replayed.frame < engine.frame {
+            replay.step(&mut replayed, &mut cursor);
+        }
+        assert_eq!(replayed.score.value, engine.score.value);
+        assert_eq!(replayed.matrix.occupation, engine.matrix.occupation);
+    }
+}
@@ -1,44 +1,31 @@
 //! Resources definitions.
 
-use crate::game::tetromino::TetrominoType;
-use bevy::prelude::{Plugin, Resource};
-use std::collections::{BTreeMap, HashMap, LinkedList};
-
-use super::{components::MatrixPosition, tetromino::Tetromino};
-
-#[derive(Resource)]
-pub struct ImagePathResources(BTreeMap<TetrominoType, &'static str>);
-
-pub struct ImageLoadPlugin;
-
-impl Plugin for ImageLoadPlugin {
-    fn build(&self, app: &mut bevy::prelude::App) {
-        let mut map = BTreeMap::new();
-        map.insert(TetrominoType::I, "red.png");
-        map.insert(TetrominoType::J, "orange.png");
-        map.insert(TetrominoType::L, "yellow.png");
-        map.insert(TetrominoType::S, "green.png");
-        map.insert(TetrominoType::T, "cyan.png");
-        map.insert(TetrominoType::Z, "blue.png");
-        map.insert(TetrominoType::O, "purple.png");
-        app.insert_resource(ImagePathResources(map));
-    }
-}
-
-impl ImagePathResources {
-    /// A function to get Image `Path` from resources
-    pub fn get_path(&self, ty: TetrominoType) -> &'static str {
-        self.0.get(&ty).unwrap()
-    }
-}
-
-#[derive(Resource, Default)]
+use bevy::prelude::Resource;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, LinkedList};
+
+use super::{
+    components::MatrixPosition,
+    global::calculate_score,
+    tetromino::{Tetromino, TetrominoType},
+};
+
+#[derive(Resource, Default, Clone)]
 pub struct Score {
     pub value: i32,
     pub cleared_lines: HashMap<ScoreAction, usize>,
 }
 
-#[derive(Clone)]
+impl Score {
+    /// Add the points of a scoring action at the given level.
+    pub fn apply(&mut self, level: usize, ev: &ScoreEvent) {
+        *self.cleared_lines.entry(ev.action).or_insert(0) += ev.cleared_lines;
+        self.value += calculate_score(level as i32, ev.action);
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct ScoreEvent {
     pub action: ScoreAction,
     pub cleared_lines: usize,
@@ -63,35 +50,108 @@ impl ScoreEvent {
 #[derive(Resource)]
 pub struct StartPosition(pub MatrixPosition);
 
-#[derive(Resource)]
+/// How the next pieces are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Randomizer {
+    /// Every piece drawn independently, the only one before randomizers could
+    /// be picked.
+    #[default]
+    Random,
+    /// The 7 pieces shuffled, then the 7 pieces again.
+    Bag,
+    /// Drawn again once when the same piece comes twice in a row.
+    Classic,
+}
+
+impl Randomizer {
+    pub const ALL: [Randomizer; 3] = [Randomizer::Bag, Randomizer::Classic, Randomizer::Random];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Randomizer::Random => "Any piece can come at any time, droughts included.",
+            Randomizer::Bag => "All 7 pieces in a random order, then all 7 again.",
+            Randomizer::Classic => "Random, but the same piece rarely comes twice in a row.",
+        }
+    }
+}
+
+#[derive(Resource, Clone)]
 pub struct HoldOnQueueResoure {
     pub start_pos: MatrixPosition,
     pub values: LinkedList<Tetromino>,
+    rng: StdRng,
+    /// `None` for a fixed list of pieces, the queue empties.
+    randomizer: Option<Randomizer>,
+    /// Pieces left in the current bag.
+    bag: Vec<TetrominoType>,
 }
 
 impl HoldOnQueueResoure {
-    pub fn new(start_pos: MatrixPosition) -> Self {
-        let mut values = LinkedList::new();
+    /// Create the queue, every piece it will ever hand out is determined by `seed`.
+    pub fn new(start_pos: MatrixPosition, seed: u64, randomizer: Randomizer) -> Self {
+        let mut queue = HoldOnQueueResoure {
+            start_pos,
+            values: LinkedList::new(),
+            rng: StdRng::seed_from_u64(seed),
+            randomizer: Some(randomizer),
+            bag: vec![],
+        };
         for _ in 0..5 {
-            let new = Tetromino::new();
-            values.push_front(new);
+            let new = Tetromino::new(queue.draw());
+            match randomizer {
+                // kept in the order of the replays recorded before the randomizers
+                Randomizer::Random => queue.values.push_front(new),
+                _ => queue.values.push_back(new),
+            }
         }
-        HoldOnQueueResoure { start_pos, values }
+        queue
     }
 
-    pub fn pop_push(&mut self) -> Tetromino {
-        let new = Tetromino::new();
-        let value = self.values.pop_front();
-        self.values.push_back(new);
-        value.unwrap()
+    /// A queue handing out `pieces` only.
+    pub fn fixed(start_pos: MatrixPosition, pieces: &[TetrominoType]) -> Self {
+        HoldOnQueueResoure {
+            start_pos,
+            values: pieces.iter().map(|ty| Tetromino::new(*ty)).collect(),
+            rng: StdRng::seed_from_u64(0),
+            randomizer: None,
+            bag: vec![],
+        }
     }
 
-    pub fn first(&self) -> Option<&Tetromino> {
-        self.values.front()
+    /// Next piece, `None` once a fixed queue is empty.
+    pub fn pop_push(&mut self) -> Option<Tetromino> {
+        if self.randomizer.is_some() {
+            let new = Tetromino::new(self.draw());
+            self.values.push_back(new);
+        }
+        self.values.pop_front()
+    }
+
+    fn draw(&mut self) -> TetrominoType {
+        match self.randomizer {
+            Some(Randomizer::Bag) => {
+                if self.bag.is_empty() {
+                    self.bag = (0..7).map(TetrominoType::from).collect();
+                    self.bag.shuffle(&mut self.rng);
+                }
+                self.bag.pop().unwrap()
+            }
+            Some(Randomizer::Classic) => {
+                let last = self.values.back().map(|t| t.ty);
+                // the 8th outcome, like a repeat, draws again
+                match self.rng.gen_range(0..8) {
+                    ty if ty < 7 && Some(TetrominoType::from(ty)) != last => {
+                        TetrominoType::from(ty)
+                    }
+                    _ => self.rng.gen(),
+                }
+            }
+            _ => self.rng.gen(),
+        }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ScoreAction {
     Single,
     Double,
@@ -119,3 +179,20 @@ impl From<usize> for ScoreAction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{HoldOnQueueResoure, Randomizer};
+    use crate::game::components::MatrixPosition;
+
+    #[test]
+    fn test_bag_hands_out_every_piece_once_per_bag() {
+        let mut queue = HoldOnQueueResoure::new(MatrixPosition { x: 0, y: 0 }, 9, Randomizer::Bag);
+        let pieces: Vec<_> = (0..70).map(|_| queue.pop_push().unwrap().ty).collect();
+        for bag in pieces.chunks(7) {
+            assert_eq!(bag.iter().collect::<HashSet<_>>().len(), 7);
+        }
+    }
+}
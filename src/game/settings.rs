@@ -0,0 +1,488 @@
+//! Player settings, saved as TOML in the user config directory.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{prelude::*, window::WindowMode};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::{Countdown, DelayCurve, Handling},
+    garbage::{GarbageSettings, HoleMode},
+    global::{get_config_dir, FRAME_DURATION},
+    input::{Bindings, Controls, GamepadControls, InputAction},
+    net::protocol::DEFAULT_PORT,
+    spectator::DEFAULT_FEED_PORT,
+    stats::Stat,
+    theme::Theme,
+};
+
+/// Most pieces shown in the next queue.
+pub const MAX_PREVIEWS: usize = 3;
+
+#[derive(Resource, Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub controls: Controls,
+    pub gamepad: ControllerSettings,
+    pub versus: VersusSettings,
+    pub handling: Handling,
+    pub delays: DelayCurve,
+    pub garbage: GarbageSettings,
+    pub audio: AudioSettings,
+    pub display: DisplaySettings,
+    pub spectator: SpectatorSettings,
+    pub gameplay: GameplaySettings,
+    pub stats: StatsSettings,
+}
+
+/// The stats panel and what it shows, see `stats`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsSettings {
+    pub show: bool,
+    pub shown: Vec<Stat>,
+}
+
+impl Default for StatsSettings {
+    fn default() -> Self {
+        StatsSettings {
+            show: false,
+            shown: Stat::ALL.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameplaySettings {
+    /// Restart on the seed of the game restarted, to practice the same pieces.
+    pub same_seed: bool,
+    /// "Ready... Go!" before a game and 3-2-1 after a pause, in frames.
+    pub countdown: Countdown,
+}
+
+impl Default for GameplaySettings {
+    fn default() -> Self {
+        GameplaySettings {
+            same_seed: false,
+            countdown: Countdown {
+                ready: 90,
+                resume: 180,
+            },
+        }
+    }
+}
+
+/// Gamepad buttons and left stick deadzone, in percent of the stick range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerSettings {
+    pub buttons: GamepadControls,
+    pub deadzone: u32,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        ControllerSettings {
+            buttons: GamepadControls::default(),
+            deadzone: 30,
+        }
+    }
+}
+
+impl ControllerSettings {
+    pub fn deadzone_ratio(&self) -> f32 {
+        self.deadzone as f32 / 100.0
+    }
+}
+
+/// Keys of the two players of a local versus match, sharing the keyboard.
+/// Player 1 plays on the left of the keyboard, player 2 around the arrows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VersusSettings {
+    pub players: [Controls; 2],
+    /// Address of the LAN server joined last, `host:port`.
+    pub server: String,
+}
+
+impl Default for VersusSettings {
+    fn default() -> Self {
+        use KeyCode::*;
+        let controls = |keys: [KeyCode; 8]| {
+            let actions = [
+                InputAction::MoveLeft,
+                InputAction::MoveRight,
+                InputAction::SoftDrop,
+                InputAction::HardDrop,
+                InputAction::RotateCW,
+                InputAction::RotateCCW,
+                InputAction::Rotate180,
+                InputAction::Hold,
+            ];
+            Bindings(BTreeMap::from_iter(
+                actions.into_iter().zip(keys.map(|key| vec![key])),
+            ))
+        };
+        VersusSettings {
+            players: [
+                controls([A, D, S, W, E, Q, Z, LShift]),
+                controls([Left, Right, Down, Up, Period, Comma, Slash, RShift]),
+            ],
+            server: format!("127.0.0.1:{}", DEFAULT_PORT),
+        }
+    }
+}
+
+/// Volumes in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: u32,
+    pub music: u32,
+    pub sfx: u32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master: 80,
+            music: 60,
+            sfx: 80,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// Gain of the music, the master volume included.
+    pub fn music_gain(&self) -> f32 {
+        (self.master * self.music) as f32 / 10_000.0
+    }
+
+    /// Gain of the sound effects, the master volume included.
+    pub fn sfx_gain(&self) -> f32 {
+        (self.master * self.sfx) as f32 / 10_000.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub ghost_piece: bool,
+    pub preview_count: usize,
+    /// Name of the block theme, see `theme`.
+    pub theme: String,
+    pub window_mode: DisplayMode,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            ghost_piece: true,
+            preview_count: 1,
+            theme: Theme::default().name,
+            window_mode: DisplayMode::default(),
+        }
+    }
+}
+
+/// Live feed of the game for spectators on localhost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectatorSettings {
+    pub publish: bool,
+    pub port: u16,
+}
+
+impl Default for SpectatorSettings {
+    fn default() -> Self {
+        SpectatorSettings {
+            publish: false,
+            port: DEFAULT_FEED_PORT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl DisplayMode {
+    const ALL: [DisplayMode; 3] = [
+        DisplayMode::Windowed,
+        DisplayMode::Borderless,
+        DisplayMode::Fullscreen,
+    ];
+}
+
+impl From<DisplayMode> for WindowMode {
+    fn from(mode: DisplayMode) -> Self {
+        match mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+/// Settings which can be changed in the settings menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Das,
+    Arr,
+    Sdf,
+    StickDeadzone,
+    GarbageDelay,
+    GarbageHoles,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    GhostPiece,
+    PreviewCount,
+    Theme,
+    WindowMode,
+    SpectatorFeed,
+    RestartSeed,
+    ReadyCountdown,
+    ResumeCountdown,
+    StatsPanel,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 18] = [
+        Setting::Das,
+        Setting::Arr,
+        Setting::Sdf,
+        Setting::StickDeadzone,
+        Setting::GarbageDelay,
+        Setting::GarbageHoles,
+        Setting::MasterVolume,
+        Setting::MusicVolume,
+        Setting::SfxVolume,
+        Setting::GhostPiece,
+        Setting::PreviewCount,
+        Setting::Theme,
+        Setting::WindowMode,
+        Setting::SpectatorFeed,
+        Setting::RestartSeed,
+        Setting::ReadyCountdown,
+        Setting::ResumeCountdown,
+        Setting::StatsPanel,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Setting::Das => "DAS",
+            Setting::Arr => "ARR",
+            Setting::Sdf => "Soft Drop",
+            Setting::StickDeadzone => "Stick Deadzone",
+            Setting::GarbageDelay => "Garbage Delay",
+            Setting::GarbageHoles => "Garbage Holes",
+            Setting::MasterVolume => "Master Volume",
+            Setting::MusicVolume => "Music Volume",
+            Setting::SfxVolume => "SFX Volume",
+            Setting::GhostPiece => "Ghost Piece",
+            Setting::PreviewCount => "Previews",
+            Setting::Theme => "Theme",
+            Setting::WindowMode => "Window",
+            Setting::SpectatorFeed => "Spectator Feed",
+            Setting::RestartSeed => "Restart Seed",
+            Setting::ReadyCountdown => "Ready Countdown",
+            Setting::ResumeCountdown => "Resume Countdown",
+            Setting::StatsPanel => "Stats Panel",
+        }
+    }
+}
+
+/// Frames the countdowns are changed by in the settings menu, half a second.
+const COUNTDOWN_STEP: i32 = 30;
+
+/// A countdown length as shown in the settings menu.
+fn format_countdown(frames: u32) -> String {
+    match frames {
+        0 => "Off".to_string(),
+        frames => format!("{:.1} s", (FRAME_DURATION * frames).as_secs_f32()),
+    }
+}
+
+/// Step `value` by `step` within `min..=max`.
+fn step_value(value: u32, step: i32, min: u32, max: u32) -> u32 {
+    (value as i64 + step as i64).clamp(min as i64, max as i64) as u32
+}
+
+/// Cycle through `values`.
+pub(crate) fn cycle<T: Copy + PartialEq>(values: &[T], current: T, step: i32) -> T {
+    let index = values.iter().position(|v| *v == current).unwrap_or(0) as i32;
+    values[(index + step).rem_euclid(values.len() as i32) as usize]
+}
+
+impl Settings {
+    /// Load the settings, use the defaults if there are none yet.
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let mut settings: Settings = toml::from_str(&content).unwrap_or_else(|e| {
+                    warn!("failed to parse settings {}: {}", path.display(), e);
+                    Settings::default()
+                });
+                settings.controls.add_missing_defaults();
+                settings.gamepad.buttons.add_missing_defaults();
+                settings
+            }
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, content)
+    }
+
+    /// Text shown for a setting.
+    pub fn value(&self, setting: Setting) -> String {
+        match setting {
+            Setting::Das => format!("{} frames", self.handling.das),
+            Setting::Arr => format!("{} frames", self.handling.arr),
+            Setting::Sdf => format!("x{}", self.handling.sdf),
+            Setting::StickDeadzone => format!("{}%", self.gamepad.deadzone),
+            Setting::GarbageDelay => format!("{} frames", self.garbage.delay),
+            Setting::GarbageHoles => format!("{:?}", self.garbage.holes),
+            Setting::MasterVolume => format!("{}%", self.audio.master),
+            Setting::MusicVolume => format!("{}%", self.audio.music),
+            Setting::SfxVolume => format!("{}%", self.audio.sfx),
+            Setting::GhostPiece => match self.display.ghost_piece {
+                true => "On".to_string(),
+                false => "Off".to_string(),
+            },
+            Setting::PreviewCount => self.display.preview_count.to_string(),
+            Setting::Theme => self.display.theme.clone(),
+            Setting::WindowMode => format!("{:?}", self.display.window_mode),
+            Setting::SpectatorFeed => match self.spectator.publish {
+                true => format!("Port {}", self.spectator.port),
+                false => "Off".to_string(),
+            },
+            Setting::RestartSeed => match self.gameplay.same_seed {
+                true => "Same".to_string(),
+                false => "New".to_string(),
+            },
+            Setting::ReadyCountdown => format_countdown(self.gameplay.countdown.ready),
+            Setting::ResumeCountdown => format_countdown(self.gameplay.countdown.resume),
+            Setting::StatsPanel => match self.stats.show {
+                true => "On".to_string(),
+                false => "Off".to_string(),
+            },
+        }
+    }
+
+    /// Increase (`step > 0`) or decrease a setting.
+    pub fn adjust(&mut self, setting: Setting, step: i32) {
+        let handling = &mut self.handling;
+        let gamepad = &mut self.gamepad;
+        let garbage = &mut self.garbage;
+        let audio = &mut self.audio;
+        let display = &mut self.display;
+        let countdown = &mut self.gameplay.countdown;
+        match setting {
+            Setting::Das => handling.das = step_value(handling.das, step, 0, 30),
+            Setting::Arr => handling.arr = step_value(handling.arr, step, 0, 10),
+            Setting::Sdf => handling.sdf = step_value(handling.sdf, step, 1, 40),
+            Setting::StickDeadzone => {
+                gamepad.deadzone = step_value(gamepad.deadzone, step * 5, 5, 90)
+            }
+            Setting::GarbageDelay => garbage.delay = step_value(garbage.delay, step * 10, 0, 120),
+            Setting::GarbageHoles => garbage.holes = cycle(&HoleMode::ALL, garbage.holes, step),
+            Setting::MasterVolume => audio.master = step_value(audio.master, step * 10, 0, 100),
+            Setting::MusicVolume => audio.music = step_value(audio.music, step * 10, 0, 100),
+            Setting::SfxVolume => audio.sfx = step_value(audio.sfx, step * 10, 0, 100),
+            Setting::GhostPiece => display.ghost_piece = !display.ghost_piece,
+            Setting::PreviewCount => {
+                display.preview_count =
+                    step_value(display.preview_count as u32, step, 0, MAX_PREVIEWS as u32) as usize
+            }
+            // the themes are only known once loaded, see `Themes::cycle`
+            Setting::Theme => {}
+            Setting::WindowMode => {
+                display.window_mode = cycle(&DisplayMode::ALL, display.window_mode, step)
+            }
+            Setting::SpectatorFeed => self.spectator.publish = !self.spectator.publish,
+            Setting::RestartSeed => self.gameplay.same_seed = !self.gameplay.same_seed,
+            Setting::ReadyCountdown => {
+                countdown.ready = step_value(countdown.ready, step * COUNTDOWN_STEP, 0, 300)
+            }
+            Setting::ResumeCountdown => {
+                countdown.resume = step_value(countdown.resume, step * COUNTDOWN_STEP, 0, 300)
+            }
+            Setting::StatsPanel => self.stats.show = !self.stats.show,
+        }
+    }
+}
+
+pub fn get_settings_path() -> PathBuf {
+    get_config_dir().join("settings.toml")
+}
+
+/// Switch the window mode when it is changed in the settings.
+pub fn apply_window_mode(settings: Res<Settings>, mut windows: Query<&mut Window>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let mode = WindowMode::from(settings.display.window_mode);
+    for mut window in windows.iter_mut() {
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+}
+
+/// Save the settings when the settings menu is closed.
+pub fn save_settings(settings: Res<Settings>) {
+    if let Err(e) = settings.save(&get_settings_path()) {
+        warn!("failed to save settings: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DisplayMode, Setting, Settings, MAX_PREVIEWS};
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let mut settings = Settings::default();
+        settings.adjust(Setting::Das, 2);
+        settings.adjust(Setting::WindowMode, -1);
+        let content = toml::to_string_pretty(&settings).unwrap();
+        let loaded: Settings = toml::from_str(&content).unwrap();
+        assert_eq!(loaded, settings);
+        assert_eq!(loaded.display.window_mode, DisplayMode::Fullscreen);
+
+        // missing entries keep their default
+        let partial: Settings = toml::from_str("[handling]\ndas = 4\n").unwrap();
+        assert_eq!(partial.handling.das, 4);
+        assert_eq!(partial.controls, Settings::default().controls);
+    }
+
+    #[test]
+    fn test_settings_adjust_stays_in_range() {
+        let mut settings = Settings::default();
+        for _ in 0..10 {
+            settings.adjust(Setting::PreviewCount, 1);
+            settings.adjust(Setting::MasterVolume, 1);
+        }
+        assert_eq!(settings.display.preview_count, MAX_PREVIEWS);
+        assert_eq!(settings.audio.master, 100);
+        settings.adjust(Setting::Sdf, -100);
+        assert_eq!(settings.handling.sdf, 1);
+    }
+}
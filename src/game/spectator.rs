@@ -0,0 +1,583 @@
+//! Live spectator feed of the game.
+//!
+//! When `publish` is on in the `[spectator]` settings, the game listens on
+//! localhost and streams the state of its board to every spectator which
+//! connects: the whole state first, then only what changed (cells of the
+//! matrix, the current piece, the queue and hold, score and level). `tetris
+//! spectate [host:port]` starts the game in spectator mode, which renders the
+//! feed read-only through the usual blocks and reconnects when it drops. F9
+//! also opens a second window showing the board of this game, to cast it on
+//! another screen.
+
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener},
+    time::Duration,
+};
+
+use bevy::{
+    prelude::*,
+    render::camera::RenderTarget,
+    window::{PrimaryWindow, WindowRef},
+};
+
+use super::{
+    components::MatrixPosition,
+    engine::Engine,
+    net::{
+        connection::Connection,
+        protocol::{invalid, Reader, Wire, MAGIC},
+    },
+    settings::Settings,
+    systems::minos::RefreshBlocks,
+    tetromino::{Rotation, Tetromino, TetrominoType},
+    GameState,
+};
+use crate::AppState;
+
+/// Version of the feed, spectators of another version are turned away.
+pub const FEED_VERSION: u16 = 1;
+/// Port the feed is published on by default.
+pub const DEFAULT_FEED_PORT: u16 = 7879;
+/// Pause between two attempts to reach the feed.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The current piece and where it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    pub ty: TetrominoType,
+    pub rotation: Rotation,
+    /// Cells of the rotated shape, as in `Tetromino::pieces_data`.
+    pub data: Vec<u8>,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Status {
+    pub score: i32,
+    pub level: u32,
+    pub lines: u32,
+    pub frame: u64,
+    pub over: bool,
+}
+
+/// What a spectator sees of the game.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct View {
+    pub cells: Vec<u8>,
+    pub piece: Option<Piece>,
+    pub next: Vec<TetrominoType>,
+    pub hold: Option<TetrominoType>,
+    pub status: Status,
+}
+
+/// A change of the view, streamed to the spectators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Update {
+    /// First message of the feed.
+    Hello {
+        version: u16,
+    },
+    /// Cells of the matrix which changed, as `(index, occupation)`.
+    Cells(Vec<(u16, u8)>),
+    Piece(Option<Piece>),
+    Queue {
+        next: Vec<TetrominoType>,
+        hold: Option<TetrominoType>,
+    },
+    Status(Status),
+}
+
+fn decode_type(byte: u8) -> io::Result<TetrominoType> {
+    match byte {
+        0..=6 => Ok(TetrominoType::from(byte as i32)),
+        _ => Err(invalid("unknown tetromino")),
+    }
+}
+
+impl Wire for Update {
+    fn encode_body(&self, body: &mut Vec<u8>) {
+        match self {
+            Update::Hello { version } => {
+                body.push(0);
+                body.extend_from_slice(&MAGIC);
+                body.extend_from_slice(&version.to_le_bytes());
+            }
+            Update::Cells(cells) => {
+                body.push(1);
+                body.extend_from_slice(&(cells.len() as u16).to_le_bytes());
+                for (index, cell) in cells {
+                    body.extend_from_slice(&index.to_le_bytes());
+                    body.push(*cell);
+                }
+            }
+            Update::Piece(piece) => {
+                body.push(2);
+                if let Some(piece) = piece {
+                    body.extend_from_slice(&[piece.ty as u8, piece.rotation as u8]);
+                    body.extend_from_slice(&(piece.x as u32).to_le_bytes());
+                    body.extend_from_slice(&(piece.y as u32).to_le_bytes());
+                    body.extend_from_slice(&piece.data);
+                }
+            }
+            Update::Queue { next, hold } => {
+                body.push(3);
+                body.push(hold.map_or(u8::MAX, |ty| ty as u8));
+                body.extend(next.iter().map(|ty| *ty as u8));
+            }
+            Update::Status(status) => {
+                body.push(4);
+                body.extend_from_slice(&(status.score as u32).to_le_bytes());
+                body.extend_from_slice(&status.level.to_le_bytes());
+                body.extend_from_slice(&status.lines.to_le_bytes());
+                body.extend_from_slice(&status.frame.to_le_bytes());
+                body.push(u8::from(status.over));
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> io::Result<Self> {
+        Ok(match reader.u8()? {
+            0 => {
+                if reader.take(MAGIC.len())? != MAGIC {
+                    return Err(invalid("not a tetris feed"));
+                }
+                Update::Hello {
+                    version: reader.u16()?,
+                }
+            }
+            1 => {
+                let count = reader.u16()? as usize;
+                let mut cells = Vec::with_capacity(count);
+                for _ in 0..count {
+                    cells.push((reader.u16()?, reader.u8()?));
+                }
+                Update::Cells(cells)
+            }
+            2 if reader.is_empty() => Update::Piece(None),
+            2 => {
+                let ty = decode_type(reader.u8()?)?;
+                let rotation = match reader.u8()? {
+                    0 => Rotation::R0,
+                    1 => Rotation::R1,
+                    2 => Rotation::R2,
+                    3 => Rotation::R3,
+                    _ => return Err(invalid("unknown rotation")),
+                };
+                let x = reader.u32()? as i32;
+                let y = reader.u32()? as i32;
+                Update::Piece(Some(Piece {
+                    ty,
+                    rotation,
+                    data: reader.rest().to_vec(),
+                    x,
+                    y,
+                }))
+            }
+            3 => {
+                let hold = match reader.u8()? {
+                    u8::MAX => None,
+                    byte => Some(decode_type(byte)?),
+                };
+                let next = reader
+                    .rest()
+                    .iter()
+                    .map(|byte| decode_type(*byte))
+                    .collect::<io::Result<_>>()?;
+                Update::Queue { next, hold }
+            }
+            4 => Update::Status(Status {
+                score: reader.u32()? as i32,
+                level: reader.u32()?,
+                lines: reader.u32()?,
+                frame: reader.u64()?,
+                over: reader.u8()? != 0,
+            }),
+            _ => return Err(invalid("unknown update")),
+        })
+    }
+}
+
+impl View {
+    pub fn of(engine: &Engine) -> Self {
+        let matrix = &engine.matrix;
+        View {
+            cells: matrix.occupation.clone(),
+            piece: engine.current.as_ref().map(|tetromino| Piece {
+                ty: tetromino.ty,
+                rotation: tetromino.rotation,
+                data: tetromino.pieces_data.clone(),
+                x: matrix.start_pos.x,
+                y: matrix.start_pos.y,
+            }),
+            next: engine.queue.values.iter().map(|t| t.ty).collect(),
+            hold: engine.hold,
+            status: Status {
+                score: engine.score.value,
+                level: matrix.level as u32,
+                lines: matrix.lines_cleared as u32,
+                frame: engine.frame,
+                over: engine.is_over(),
+            },
+        }
+    }
+
+    /// Updates turning `old` into this view, the whole view without `old`.
+    pub fn diff(&self, old: Option<&View>) -> Vec<Update> {
+        let mut updates = vec![];
+        let cells: Vec<(u16, u8)> = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(index, cell)| old.and_then(|old| old.cells.get(*index)) != Some(cell))
+            .map(|(index, cell)| (index as u16, *cell))
+            .collect();
+        if !cells.is_empty() {
+            updates.push(Update::Cells(cells));
+        }
+        if old.map(|old| &old.piece) != Some(&self.piece) {
+            updates.push(Update::Piece(self.piece.clone()));
+        }
+        if old.map(|old| (&old.next, old.hold)) != Some((&self.next, self.hold)) {
+            updates.push(Update::Queue {
+                next: self.next.clone(),
+                hold: self.hold,
+            });
+        }
+        if old.map(|old| old.status) != Some(self.status) {
+            updates.push(Update::Status(self.status));
+        }
+        updates
+    }
+
+    pub fn apply(&mut self, update: Update) {
+        match update {
+            Update::Hello { .. } => {}
+            Update::Cells(cells) => {
+                for (index, cell) in cells {
+                    let index = index as usize;
+                    if index >= self.cells.len() {
+                        self.cells.resize(index + 1, 0);
+                    }
+                    self.cells[index] = cell;
+                }
+            }
+            Update::Piece(piece) => self.piece = piece,
+            Update::Queue { next, hold } => {
+                self.next = next;
+                self.hold = hold;
+            }
+            Update::Status(status) => self.status = status,
+        }
+    }
+
+    /// Show the view on `engine`, which is not stepped while spectating.
+    pub fn write(&self, engine: &mut Engine) {
+        let matrix = &mut engine.matrix;
+        if self.cells.len() == matrix.occupation.len() {
+            matrix.occupation.clone_from(&self.cells);
+        }
+        if let Some(piece) = self.piece.as_ref() {
+            matrix.start_pos = MatrixPosition {
+                x: piece.x,
+                y: piece.y,
+            };
+        }
+        matrix.level = self.status.level as usize;
+        matrix.lines_cleared = self.status.lines as usize;
+        matrix.game_over = self.status.over;
+        engine.current = self.piece.as_ref().map(|piece| Tetromino {
+            ty: piece.ty,
+            rotation: piece.rotation,
+            pieces_data: piece.data.clone(),
+        });
+        engine.queue.values = self.next.iter().map(|ty| Tetromino::new(*ty)).collect();
+        engine.hold = self.hold;
+        engine.score.value = self.status.score;
+        engine.frame = self.status.frame;
+    }
+}
+
+/// The feed published by this game.
+#[derive(Resource, Default)]
+pub struct SpectatorFeed {
+    listener: Option<TcpListener>,
+    /// Port a listener was last tried on, not again until it changes.
+    port: Option<u16>,
+    spectators: Vec<Connection<Update>>,
+    /// The view the spectators have.
+    last: Option<View>,
+}
+
+impl SpectatorFeed {
+    /// Listen on `port` of localhost.
+    fn listen(&mut self, port: u16) -> io::Result<()> {
+        *self = SpectatorFeed {
+            port: Some(port),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        self.listener = Some(listener);
+        Ok(())
+    }
+
+    /// Take in the spectators which connected, and send them the whole view.
+    fn accept(&mut self, view: &View) -> io::Result<()> {
+        let Some(listener) = self.listener.as_ref() else {
+            return Ok(());
+        };
+        loop {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let mut spectator = Connection::new(stream)?;
+            let joined = std::iter::once(Update::Hello {
+                version: FEED_VERSION,
+            })
+            .chain(view.diff(None))
+            .try_for_each(|update| spectator.send(&update));
+            if joined.is_ok() {
+                self.spectators.push(spectator);
+            }
+        }
+    }
+
+    /// Send the changes of the view to every spectator, forget the ones gone.
+    fn publish(&mut self, view: View) {
+        let updates = view.diff(self.last.as_ref());
+        self.spectators
+            .retain_mut(|spectator| updates.iter().all(|update| spectator.send(update).is_ok()));
+        self.last = Some(view);
+    }
+}
+
+/// Publish the game to the spectators when the feed is on.
+pub fn publish_feed_system(
+    settings: Res<Settings>,
+    mut feed: ResMut<SpectatorFeed>,
+    engine: Res<Engine>,
+) {
+    let spectator = settings.spectator;
+    if !spectator.publish {
+        if feed.port.is_some() {
+            *feed = SpectatorFeed::default();
+        }
+        return;
+    }
+    if feed.port != Some(spectator.port) {
+        match feed.listen(spectator.port) {
+            Ok(()) => info!("spectator feed on port {}", spectator.port),
+            Err(e) => warn!("failed to publish on port {}: {}", spectator.port, e),
+        }
+    }
+    if engine.is_changed() || feed.last.is_none() {
+        feed.publish(View::of(&engine));
+    }
+    let view = feed.last.clone().unwrap_or_default();
+    if let Err(e) = feed.accept(&view) {
+        warn!("spectator feed: {}", e);
+    }
+}
+
+/// This game shows the feed of another one.
+#[derive(Resource, Default)]
+pub struct Spectator {
+    pub active: bool,
+    address: String,
+    connection: Option<Connection<Update>>,
+    view: View,
+    /// Time left before trying to reach the feed again.
+    retry: Timer,
+}
+
+impl Spectator {
+    pub fn watch(address: &str) -> Self {
+        Spectator {
+            active: true,
+            address: address.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn title(&self) -> String {
+        match self.connection {
+            Some(_) => format!("Tetris - watching {}", self.address),
+            None => format!("Tetris - waiting for {}", self.address),
+        }
+    }
+}
+
+pub fn is_spectating(spectator: Res<Spectator>) -> bool {
+    spectator.active
+}
+
+pub fn is_not_spectating(spectator: Res<Spectator>) -> bool {
+    !spectator.active
+}
+
+/// Go straight to the game page when started as a spectator.
+pub fn start_spectating(
+    spectator: Res<Spectator>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if spectator.active {
+        app_state.set(AppState::Game);
+        game_state.set(GameState::New);
+    }
+}
+
+/// Show the feed on the engine, reconnecting when it drops.
+pub fn spectate_system(
+    time: Res<Time>,
+    mut spectator: ResMut<Spectator>,
+    mut engine: ResMut<Engine>,
+    mut refresh: EventWriter<RefreshBlocks>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut titled: Local<bool>,
+) {
+    let spectator = spectator.as_mut();
+    let was_connected = spectator.connection.is_some();
+    if spectator.connection.is_none() && spectator.retry.tick(time.delta()).finished() {
+        spectator.retry = Timer::new(RETRY_INTERVAL, TimerMode::Once);
+        if let Ok(connection) = Connection::connect(&spectator.address) {
+            spectator.connection = Some(connection);
+            spectator.view = View::default();
+        }
+    }
+
+    if let Some(connection) = spectator.connection.as_mut() {
+        let updates = connection.poll().and_then(|updates| match updates.first() {
+            Some(Update::Hello { version }) if *version != FEED_VERSION => Err(invalid(&format!(
+                "feed version {} expected, got {}",
+                FEED_VERSION, version
+            ))),
+            _ => Ok(updates),
+        });
+        match updates {
+            Ok(updates) if updates.is_empty() => {}
+            Ok(updates) => {
+                for update in updates {
+                    spectator.view.apply(update);
+                }
+                spectator.view.write(&mut engine);
+                refresh.send(RefreshBlocks);
+            }
+            Err(e) => {
+                warn!("spectator feed: {}", e);
+                spectator.connection = None;
+            }
+        }
+    }
+
+    if spectator.connection.is_some() != was_connected || !*titled {
+        for mut window in windows.iter_mut() {
+            window.title = spectator.title();
+        }
+        *titled = true;
+    }
+}
+
+/// The second window casting the board, and its camera.
+#[derive(Component)]
+pub struct SpectatorWindow;
+
+/// Open or close the second window with F9.
+pub fn toggle_spectator_window_system(
+    mut commands: Commands,
+    key_code: Res<Input<KeyCode>>,
+    opened: Query<Entity, With<SpectatorWindow>>,
+) {
+    if !key_code.just_pressed(KeyCode::F9) {
+        return;
+    }
+    if !opened.is_empty() {
+        for entity in opened.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    let window = commands
+        .spawn((
+            Window {
+                title: "Tetris - Spectator".to_string(),
+                ..default()
+            },
+            SpectatorWindow,
+        ))
+        .id();
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target: RenderTarget::Window(WindowRef::Entity(window)),
+                ..default()
+            },
+            ..default()
+        },
+        // the menus and buttons stay on the main window
+        UiCameraConfig { show_ui: false },
+        SpectatorWindow,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Update, View};
+    use crate::game::{
+        engine::{Engine, Ruleset},
+        input::{ActionEvent, InputAction},
+        net::protocol::Wire,
+    };
+
+    /// Send `updates` through the wire format.
+    fn transmit(updates: Vec<Update>) -> Vec<Update> {
+        let mut bytes = vec![];
+        for update in updates.iter() {
+            update.encode(&mut bytes);
+        }
+        let mut received = vec![];
+        while let Some(update) = Update::decode(&mut bytes).unwrap() {
+            received.push(update);
+        }
+        assert!(bytes.is_empty());
+        received
+    }
+
+    #[test]
+    fn test_spectator_follows_the_diffs() {
+        let mut engine = Engine::new(11, Ruleset::default());
+        let mut published = View::of(&engine);
+        let mut spectator = View::default();
+        for update in transmit(published.diff(None)) {
+            spectator.apply(update);
+        }
+        assert_eq!(spectator, published);
+
+        let drops = [
+            ActionEvent::press(InputAction::HardDrop),
+            ActionEvent::release(InputAction::HardDrop),
+        ];
+        for frame in 0..600 {
+            engine.step(&drops[frame % 2..frame % 2 + 1]);
+            let view = View::of(&engine);
+            let updates = view.diff(Some(&published));
+            if view == published {
+                assert!(updates.is_empty());
+            }
+            for update in transmit(updates) {
+                spectator.apply(update);
+            }
+            published = view;
+            assert_eq!(spectator, published);
+        }
+
+        // the engine of the spectator shows the same game
+        let mut shown = Engine::new(0, Ruleset::default());
+        spectator.write(&mut shown);
+        assert_eq!(View::of(&shown), published);
+    }
+}
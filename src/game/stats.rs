@@ -0,0 +1,353 @@
+//! Statistics of the player's game, shown in the optional stats panel.
+//!
+//! They are gathered after every engine frame from its events and the actions
+//! it was stepped with. The attack of a clear is what it would send in versus,
+//! with the default `AttackTable`. A finesse fault is a piece placed with more
+//! keys than the fewest reaching the same cells, found by `find_moves` on the
+//! board from before the lock. Holding a shift or the soft drop counts as one
+//! key, so a run of shifts costs at most two: a tap or a DAS to the wall, and a
+//! tap back.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    components::MatrixPosition,
+    engine::{Clear, Engine, EngineEvent, Spin},
+    garbage::{AttackTable, GarbageState},
+    global::format_duration,
+    input::{ActionEvent, FrameInput, InputAction},
+    matrix::Matrix,
+    movegen::find_moves,
+    tetromino::{Tetromino, TetrominoType},
+};
+
+/// Statistics which can be shown in the panel, in the order of the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stat {
+    Time,
+    Pieces,
+    /// Pieces per second.
+    Pps,
+    /// Keys per piece.
+    Kpp,
+    /// Attack per minute.
+    Apm,
+    Finesse,
+    /// Pieces placed of each type.
+    Distribution,
+}
+
+impl Stat {
+    pub const ALL: [Stat; 7] = [
+        Stat::Time,
+        Stat::Pieces,
+        Stat::Pps,
+        Stat::Kpp,
+        Stat::Apm,
+        Stat::Finesse,
+        Stat::Distribution,
+    ];
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Stats {
+    pub pieces: usize,
+    /// Gameplay keys pressed.
+    pub keys: usize,
+    /// Garbage lines the clears are worth.
+    pub attack: usize,
+    pub finesse_faults: usize,
+    /// Pieces placed, indexed by `TetrominoType`.
+    pub distribution: [usize; 7],
+    /// Clears without a spin, indexed by the cleared lines minus one.
+    pub clears: [usize; 4],
+    /// T-spins and mini T-spins, with or without lines.
+    pub t_spins: usize,
+    /// Most consecutive clears minus one.
+    pub max_combo: usize,
+    /// Tetrises and T-spin clears following another one.
+    pub back_to_back: usize,
+    /// Consecutive clears minus one, `None` once a piece locks without a clear.
+    combo: Option<usize>,
+    /// The last clear was a tetris or a T-spin.
+    difficult: bool,
+    /// Type of the piece in play.
+    current: Option<TetrominoType>,
+    /// Keys pressed for the piece in play, hold excluded.
+    piece_keys: usize,
+    /// Combo and back-to-back of the attacks.
+    garbage: GarbageState,
+}
+
+impl Stats {
+    /// Account for a frame of `engine`, stepped with `actions` into `events`.
+    pub fn record(&mut self, engine: &Engine, events: &[EngineEvent], actions: &[ActionEvent]) {
+        let pressed = actions
+            .iter()
+            .filter(|ev| ev.pressed && ev.action.is_gameplay());
+        self.keys += pressed.clone().count();
+        // a piece spawns before the actions of its frame are applied
+        if events
+            .iter()
+            .any(|ev| matches!(ev, EngineEvent::Spawned(_)))
+        {
+            self.piece_keys = 0;
+        }
+        self.piece_keys += pressed.filter(|ev| ev.action != InputAction::Hold).count();
+
+        for ev in events {
+            match ev {
+                EngineEvent::Spawned(ty) => self.current = Some(*ty),
+                EngineEvent::Locked => {
+                    let Some(ty) = self.current.take() else {
+                        continue;
+                    };
+                    self.pieces += 1;
+                    self.distribution[ty as usize] += 1;
+                    let fewest = engine
+                        .locked
+                        .as_ref()
+                        .and_then(|locked| fewest_keys(&locked.board, ty, &locked.cells));
+                    if fewest.is_some_and(|fewest| self.piece_keys > fewest) {
+                        self.finesse_faults += 1;
+                    }
+                }
+                EngineEvent::Cleared(clear) => {
+                    self.attack += self.garbage.attack(&AttackTable::default(), clear);
+                    self.cleared(clear);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Count the kind of a clear, its combo and back-to-back.
+    fn cleared(&mut self, clear: &Clear) {
+        if clear.spin != Spin::None {
+            self.t_spins += 1;
+        }
+        if clear.lines == 0 {
+            self.combo = None;
+            return;
+        }
+        if clear.spin == Spin::None {
+            self.clears[clear.lines.min(4) - 1] += 1;
+        }
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        self.combo = Some(combo);
+        self.max_combo = self.max_combo.max(combo);
+        let difficult = clear.lines >= 4 || clear.spin != Spin::None;
+        if difficult && self.difficult {
+            self.back_to_back += 1;
+        }
+        self.difficult = difficult;
+    }
+
+    pub fn pps(&self, time: Duration) -> f32 {
+        match time.is_zero() {
+            true => 0.0,
+            false => self.pieces as f32 / time.as_secs_f32(),
+        }
+    }
+
+    pub fn kpp(&self) -> f32 {
+        match self.pieces {
+            0 => 0.0,
+            pieces => self.keys as f32 / pieces as f32,
+        }
+    }
+
+    pub fn apm(&self, time: Duration) -> f32 {
+        match time.is_zero() {
+            true => 0.0,
+            false => self.attack as f32 * 60.0 / time.as_secs_f32(),
+        }
+    }
+
+    /// Lines of the panel showing `shown`, after `time` of play.
+    pub fn lines(&self, shown: &[Stat], time: Duration) -> Vec<String> {
+        shown
+            .iter()
+            .map(|stat| match stat {
+                Stat::Time => format!("Time {}", format_duration(time)),
+                Stat::Pieces => format!("Pieces {}", self.pieces),
+                Stat::Pps => format!("PPS {:.2}", self.pps(time)),
+                Stat::Kpp => format!("KPP {:.2}", self.kpp()),
+                Stat::Apm => format!("APM {:.1}", self.apm(time)),
+                Stat::Finesse => format!("Finesse {}", self.finesse_faults),
+                Stat::Distribution => self
+                    .distribution
+                    .iter()
+                    .enumerate()
+                    .map(|(i, count)| format!("{:?} {}", TetrominoType::from(i as i32), count))
+                    .collect::<Vec<String>>()
+                    .join("  "),
+            })
+            .collect()
+    }
+}
+
+/// Keys of a path of `find_moves`, the held shifts and soft drops counted once.
+fn path_keys(path: &[InputAction]) -> usize {
+    path.chunk_by(|a, b| a == b)
+        .map(|run| match run[0] {
+            InputAction::MoveLeft | InputAction::MoveRight => run.len().min(2),
+            InputAction::SoftDrop => 1,
+            _ => run.len(),
+        })
+        .sum()
+}
+
+/// Fewest keys placing a `ty` piece on `cells` of `matrix` from its spawn
+/// position. `None` if it can't get there.
+pub fn fewest_keys(matrix: &Matrix, ty: TetrominoType, cells: &[MatrixPosition]) -> Option<usize> {
+    let mut board = matrix.clone();
+    board.reset_start_pos();
+    let mut target = cells.to_vec();
+    target.sort_by_key(|c| (c.y, c.x));
+    find_moves(&board, &Tetromino::new(ty), board.start_pos)
+        .into_iter()
+        .filter(|m| m.cells() == target)
+        .map(|m| path_keys(&m.path))
+        .min()
+}
+
+/// Start counting over for a new game.
+pub fn reset_stats(mut stats: ResMut<Stats>) {
+    *stats = Stats::default();
+}
+
+/// Account for the frame the engine was just stepped.
+pub fn record_stats_system(
+    mut stats: ResMut<Stats>,
+    engine: Res<Engine>,
+    frame_input: Res<FrameInput>,
+    mut events: EventReader<EngineEvent>,
+) {
+    let events: Vec<EngineEvent> = events.iter().cloned().collect();
+    stats.record(&engine, &events, &frame_input.actions);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{path_keys, Stat, Stats};
+    use crate::game::{
+        engine::{Clear, DelayCurve, Engine, EngineEvent, Ruleset, Spin},
+        input::{ActionEvent, InputAction},
+        tetromino::TetrominoType,
+    };
+
+    fn play(engine: &mut Engine, stats: &mut Stats, actions: &[ActionEvent]) {
+        engine.step(actions);
+        let events = engine.drain_events();
+        stats.record(engine, &events, actions);
+    }
+
+    /// Wait for the next piece, then press `keys` one frame apart.
+    fn place(engine: &mut Engine, stats: &mut Stats, keys: &[InputAction]) {
+        while engine.current.is_none() {
+            play(engine, stats, &[]);
+        }
+        for key in keys {
+            play(engine, stats, &[ActionEvent::press(*key)]);
+            play(engine, stats, &[ActionEvent::release(*key)]);
+        }
+    }
+
+    #[test]
+    fn test_held_moves_count_once() {
+        use InputAction::*;
+        assert_eq!(path_keys(&[MoveLeft, MoveLeft, MoveLeft, HardDrop]), 3);
+        assert_eq!(path_keys(&[RotateCW, MoveRight, HardDrop]), 3);
+        assert_eq!(path_keys(&[SoftDrop, SoftDrop, RotateCW, RotateCW]), 3);
+    }
+
+    #[test]
+    fn test_stats_of_placed_pieces() {
+        use InputAction::*;
+        let mut engine = Engine::new(3, Ruleset::default());
+        let mut stats = Stats::default();
+        place(&mut engine, &mut stats, &[HardDrop]);
+        place(&mut engine, &mut stats, &[RotateCW, RotateCCW, HardDrop]);
+        place(&mut engine, &mut stats, &[Hold]);
+        place(&mut engine, &mut stats, &[HardDrop]);
+
+        assert_eq!(stats.pieces, 3);
+        assert_eq!(stats.keys, 6);
+        assert_eq!(stats.kpp(), 2.0);
+        assert_eq!(stats.distribution.iter().sum::<usize>(), 3);
+        // rotating back and forth is the only waste
+        assert_eq!(stats.finesse_faults, 1);
+        assert_eq!(stats.attack, 0);
+
+        let time = Duration::from_secs(2);
+        assert_eq!(stats.pps(time), 1.5);
+        let lines = stats.lines(&[Stat::Pieces, Stat::Distribution], time);
+        assert_eq!(lines[0], "Pieces 3");
+        assert!(lines[1].starts_with(&format!("{:?} ", TetrominoType::I)));
+    }
+
+    #[test]
+    fn test_finesse_of_a_clear_without_delay() {
+        use InputAction::*;
+        let ruleset = Ruleset {
+            delays: DelayCurve::none(),
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(4, ruleset);
+        let mut stats = Stats::default();
+        play(&mut engine, &mut stats, &[]);
+        // the bottom row is full but for where the piece lands
+        let bottom = engine.matrix.field_height as i32 - 1;
+        let landing: Vec<i32> = engine
+            .ghost_blocks()
+            .iter()
+            .filter(|p| p.y == bottom)
+            .map(|p| p.x)
+            .collect();
+        let width = engine.matrix.field_width;
+        let len = engine.matrix.occupation.len();
+        for x in 0..width {
+            engine.matrix.occupation[len - width + x] = u8::from(!landing.contains(&(x as i32)));
+        }
+        place(&mut engine, &mut stats, &[RotateCW, RotateCCW, HardDrop]);
+
+        // the row collapsed in the frame of the lock
+        assert_eq!(engine.total_lines, 1);
+        assert_eq!(stats.pieces, 1);
+        assert_eq!(stats.finesse_faults, 1);
+    }
+
+    #[test]
+    fn test_clears_combo_and_back_to_back() {
+        let engine = Engine::new(1, Ruleset::default());
+        let mut stats = Stats::default();
+        let cleared = |lines, spin| {
+            EngineEvent::Cleared(Clear {
+                lines,
+                spin,
+                perfect: false,
+            })
+        };
+        for ev in [
+            cleared(4, Spin::None),
+            cleared(2, Spin::Full),
+            cleared(1, Spin::None),
+            cleared(0, Spin::None),
+            cleared(4, Spin::None),
+            cleared(0, Spin::Mini),
+        ] {
+            stats.record(&engine, &[ev], &[]);
+        }
+        assert_eq!(stats.clears, [1, 0, 0, 2]);
+        assert_eq!(stats.t_spins, 2);
+        assert_eq!(stats.max_combo, 2);
+        // the single breaks the chain, not the lock without a clear
+        assert_eq!(stats.back_to_back, 1);
+    }
+}
@@ -2,6 +2,14 @@ use bevy::prelude::*;
 
 pub(crate) const PAUSED_LAYOUT_BACKGROUND_COLOR: Color = Color::rgba(0.6, 0.6, 0.6, 0.8);
 
+/// Outline of the placement suggested by the coach.
+pub(crate) const HINT_COLOR: Color = Color::rgba(1.0, 0.85, 0.2, 0.9);
+
+/// Incoming garbage which rises on the next lock.
+pub(crate) const GARBAGE_READY_COLOR: Color = Color::rgb(0.9, 0.15, 0.15);
+/// Incoming garbage still waiting.
+pub(crate) const GARBAGE_DELAYED_COLOR: Color = Color::rgb(1.0, 0.6, 0.1);
+
 pub const TEXT_FONT_SIZE: f32 = 25.0;
 pub const TEXT_LABEL_FONT_SIZE: f32 = 32.0;
 
@@ -0,0 +1,144 @@
+//! "Ready... Go!" before a game and 3-2-1 when it resumes after a pause.
+//!
+//! The countdowns are frames the engine spends frozen, see `Countdown`, so
+//! replays freeze at the same frames. Resuming pushes a pause into the actions
+//! of the next frame, which starts the resume countdown and gets recorded.
+
+use bevy::prelude::*;
+
+use crate::game::{
+    components::{CountdownText, GameDisplay},
+    engine::Engine,
+    global::FRAME_DURATION,
+    input::{ActionEvent, ActionQueue, InputAction},
+    style::get_game_label_text_style,
+};
+
+/// Frames "Go!" stays on screen once the game started.
+const GO_FRAMES: u64 = 30;
+/// Size of the countdown over the label text.
+const COUNTDOWN_SCALE: f32 = 3.0;
+
+/// What the countdown shows, nothing once the game is under way.
+pub fn countdown_label(engine: &Engine) -> String {
+    match (engine.frozen, engine.resumed) {
+        (0, false) if engine.clock < GO_FRAMES && engine.ruleset.countdown.ready > 0 => {
+            "Go!".to_string()
+        }
+        (0, _) => String::new(),
+        (_, false) => "Ready".to_string(),
+        (frozen, true) => {
+            let seconds = (FRAME_DURATION * frozen).as_secs_f32().ceil();
+            format!("{}", seconds as u32)
+        }
+    }
+}
+
+pub fn spawn_countdown_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = get_game_label_text_style(&asset_server);
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            GameDisplay,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: style.font_size * COUNTDOWN_SCALE,
+                        ..style
+                    },
+                ),
+                CountdownText,
+            ));
+        });
+}
+
+pub fn update_countdown_system(
+    engine: Res<Engine>,
+    mut query: Query<&mut Text, With<CountdownText>>,
+) {
+    let label = countdown_label(&engine);
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+/// Freeze the engine for the resume countdown.
+pub fn start_resume_countdown(mut queue: ResMut<ActionQueue>) {
+    queue.0.push(ActionEvent::press(InputAction::Pause));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::countdown_label;
+    use crate::game::{
+        engine::{Countdown, Engine, Ruleset},
+        input::{ActionEvent, InputAction},
+    };
+
+    #[test]
+    fn test_countdown_labels() {
+        let ruleset = Ruleset {
+            countdown: Countdown {
+                ready: 60,
+                resume: 180,
+            },
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(1, ruleset);
+        assert_eq!(countdown_label(&engine), "Ready");
+        for _ in 0..61 {
+            engine.step(&[]);
+        }
+        assert_eq!(countdown_label(&engine), "Go!");
+        for _ in 0..30 {
+            engine.step(&[]);
+        }
+        assert_eq!(countdown_label(&engine), "");
+        engine.step(&[ActionEvent::press(InputAction::Pause)]);
+        assert_eq!(countdown_label(&engine), "3");
+        for _ in 0..120 {
+            engine.step(&[]);
+        }
+        assert_eq!(countdown_label(&engine), "1");
+        for _ in 0..60 {
+            engine.step(&[]);
+        }
+        assert_eq!(countdown_label(&engine), "");
+    }
+
+    #[test]
+    fn test_pause_during_ready_counts_down() {
+        let ruleset = Ruleset {
+            countdown: Countdown {
+                ready: 60,
+                resume: 120,
+            },
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(1, ruleset);
+        engine.step(&[]);
+        assert_eq!(countdown_label(&engine), "Ready");
+        engine.step(&[ActionEvent::press(InputAction::Pause)]);
+        assert_eq!(engine.clock, 0);
+        assert_eq!(countdown_label(&engine), "2");
+        for _ in 0..120 {
+            engine.step(&[]);
+        }
+        // no "Go!" after a resume
+        assert_eq!(countdown_label(&engine), "");
+    }
+}
@@ -0,0 +1,88 @@
+//! Meter of the incoming garbage beside the board.
+
+use bevy::prelude::*;
+
+use crate::game::{
+    components::{GameDisplay, GarbageMeter},
+    engine::Engine,
+    garbage::{GarbageState, Practice},
+    global::{BLOCK_SIZE, BLOCK_SPACE, BORDER_SIZE, RIGHT_WIDTH, SEPARATE},
+    style::{GARBAGE_DELAYED_COLOR, GARBAGE_READY_COLOR},
+    versus::VersusPlayer,
+};
+
+/// Spawn the two bars of a meter, centered on `x`.
+pub fn spawn_garbage_meter(
+    commands: &mut Commands,
+    player: Option<usize>,
+    x: f32,
+    bottom: f32,
+    width: f32,
+    row: f32,
+) {
+    for (ready, color) in [(true, GARBAGE_READY_COLOR), (false, GARBAGE_DELAYED_COLOR)] {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(width, 0.0)),
+                    anchor: bevy::sprite::Anchor::BottomCenter,
+                    ..default()
+                },
+                // above the border it covers
+                transform: Transform::from_xyz(x, bottom, 1.0),
+                ..default()
+            },
+            GarbageMeter {
+                player,
+                ready,
+                row,
+                bottom,
+            },
+            GameDisplay,
+        ));
+    }
+}
+
+/// The practice meter covers the left border of the board.
+pub fn spawn_practice_meter_system(mut commands: Commands, engine: Res<Engine>) {
+    let matrix = &engine.matrix;
+    let x = -(SEPARATE + RIGHT_WIDTH / 2.0 + BORDER_SIZE / 2.0 + matrix.width / 2.0);
+    spawn_garbage_meter(
+        &mut commands,
+        None,
+        x,
+        -matrix.height / 2.0,
+        BORDER_SIZE,
+        BLOCK_SIZE + BLOCK_SPACE,
+    );
+}
+
+/// Resize the bars to the incoming garbage, the delayed one on top.
+pub fn update_garbage_meter_system(
+    practice: Res<Practice>,
+    players: Query<&VersusPlayer>,
+    mut meters: Query<(&GarbageMeter, &mut Sprite, &mut Transform)>,
+) {
+    for (meter, mut sprite, mut transform) in meters.iter_mut() {
+        let garbage: Option<&GarbageState> = match meter.player {
+            None => Some(&practice.garbage),
+            Some(index) => players
+                .iter()
+                .find(|p| p.index == index)
+                .map(|p| &p.garbage),
+        };
+        let Some(garbage) = garbage else {
+            continue;
+        };
+        let (total, ready) = garbage.pending();
+        let (lines, below) = match meter.ready {
+            true => (ready, 0),
+            false => (total - ready, ready),
+        };
+        sprite.custom_size = sprite
+            .custom_size
+            .map(|size| Vec2::new(size.x, lines as f32 * meter.row));
+        transform.translation.y = meter.bottom + below as f32 * meter.row;
+    }
+}
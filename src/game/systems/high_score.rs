@@ -0,0 +1,161 @@
+//! Enter the name of a new high score on the game over page.
+use bevy::prelude::*;
+
+use crate::{
+    game::{
+        ai::AiPlayer,
+        components::{HighScoreTable, NameInput},
+        engine::{DelayCurve, Engine},
+        garbage::Practice,
+        leaderboard::{
+            get_leaderboard_path, Leaderboard, NewRecord, Ranking, ScoreEntry, MAX_NAME_LENGTH,
+        },
+        replay::ReplayRecorder,
+        systems::hint::Coach,
+        versus::VersusMatch,
+    },
+    ui::systems::layout::spawn_score_rows,
+};
+
+/// Check whether the finished game made it into the high scores of its variant,
+/// and save its replay if so.
+/// Games played by the AI, with the coach shown or with other delays than the
+/// default ones, versus matches and practice games don't count.
+#[allow(clippy::too_many_arguments)]
+pub fn check_new_record(
+    mut commands: Commands,
+    engine: Res<Engine>,
+    leaderboard: Res<Leaderboard>,
+    mut recorder: ResMut<ReplayRecorder>,
+    ai: Res<AiPlayer>,
+    coach: Res<Coach>,
+    versus: Res<VersusMatch>,
+    practice: Res<Practice>,
+) {
+    commands.remove_resource::<NewRecord>();
+    if ai.active || coach.used || versus.active || practice.active {
+        return;
+    }
+    // shorter delays make for faster games than the tables compare
+    if engine.ruleset.delays != DelayCurve::default() {
+        return;
+    }
+    let Some(rank) = leaderboard.rank_game(&engine) else {
+        return;
+    };
+    // the high score links to its replay
+    recorder.save_finished();
+    let replay = recorder
+        .last_saved
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned());
+    commands.insert_resource(NewRecord {
+        variant: engine.ruleset.variant(),
+        ranking: Ranking::of(engine.ruleset.mode),
+        entry: ScoreEntry {
+            name: leaderboard.last_name.clone(),
+            replay,
+            ..ScoreEntry::of(&engine)
+        },
+        rank,
+        confirmed: false,
+        saved: false,
+    });
+}
+
+/// Type the name of the record, Enter to confirm.
+pub fn name_input_system(
+    mut characters: EventReader<ReceivedCharacter>,
+    key_code: Res<Input<KeyCode>>,
+    record: Option<ResMut<NewRecord>>,
+    mut query: Query<&mut Text, With<NameInput>>,
+) {
+    let Some(mut record) = record else {
+        characters.clear();
+        return;
+    };
+    if record.confirmed {
+        characters.clear();
+        return;
+    }
+    let name = &mut record.entry.name;
+    for ev in characters.iter() {
+        let valid = ev.char.is_alphanumeric() || matches!(ev.char, ' ' | '-' | '_');
+        if valid && name.chars().count() < MAX_NAME_LENGTH {
+            name.push(ev.char);
+        }
+    }
+    if key_code.just_pressed(KeyCode::Back) {
+        name.pop();
+    }
+    if key_code.just_pressed(KeyCode::Return) && !name.trim().is_empty() {
+        record.confirmed = true;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Name: {}{}",
+            record.entry.name,
+            if record.confirmed { "" } else { "_" }
+        );
+        if record.confirmed {
+            text.sections[1].value = "  saved".to_string();
+        }
+    }
+}
+
+/// Store the record once its name is confirmed and highlight it in the table.
+pub fn save_record_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    record: Option<ResMut<NewRecord>>,
+    mut leaderboard: ResMut<Leaderboard>,
+    query: Query<Entity, With<HighScoreTable>>,
+) {
+    let Some(mut record) = record else {
+        return;
+    };
+    if !record.confirmed || record.saved {
+        return;
+    }
+    store_record(&mut record, &mut leaderboard);
+    for entity in query.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            spawn_score_rows(
+                parent,
+                leaderboard.table(&record.variant),
+                Some(record.rank),
+                &asset_server,
+            );
+        });
+    }
+}
+
+/// Keep the record with the proposed name if the page is left without confirming it.
+pub fn save_unconfirmed_record(
+    record: Option<ResMut<NewRecord>>,
+    mut leaderboard: ResMut<Leaderboard>,
+) {
+    if let Some(mut record) = record {
+        if !record.saved {
+            store_record(&mut record, &mut leaderboard);
+        }
+    }
+}
+
+fn store_record(record: &mut NewRecord, leaderboard: &mut Leaderboard) {
+    record.entry.name = record.entry.name.trim().to_string();
+    if record.entry.name.is_empty() {
+        record.entry.name = leaderboard.last_name.clone();
+    }
+    leaderboard.last_name = record.entry.name.clone();
+    if let Some(rank) = leaderboard.insert(&record.variant, record.ranking, record.entry.clone()) {
+        record.rank = rank;
+    }
+    record.saved = true;
+    if let Err(e) = leaderboard.save(&get_leaderboard_path()) {
+        warn!("failed to save high scores: {}", e);
+    }
+}
@@ -0,0 +1,147 @@
+//! Coach overlay: outline of the placement the AI would pick for the current
+//! piece.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::game::{
+    ai::Ai,
+    components::{HintBlock, MatrixPosition},
+    engine::{Engine, EngineEvent},
+    global::{BLOCK_SIZE, BLOCK_SPACE},
+    input::{ActionInput, InputAction},
+    style::HINT_COLOR,
+};
+
+/// Width of the hint outline.
+const HINT_THICKNESS: f32 = 4.0;
+
+/// Whether the coach is shown. Games where it was shown aren't ranked.
+#[derive(Resource, Default)]
+pub struct Coach {
+    pub enabled: bool,
+    /// The coach was shown during the running game.
+    pub used: bool,
+}
+
+/// A game started with the coach on is a coached game.
+pub fn start_coaching(mut coach: ResMut<Coach>) {
+    coach.used = coach.enabled;
+}
+
+/// Show or hide the coach with the hint key.
+pub fn toggle_hint_system(input: ActionInput, mut coach: ResMut<Coach>) {
+    if input.just_pressed(InputAction::Hint) {
+        coach.enabled = !coach.enabled;
+        coach.used |= coach.enabled;
+    }
+}
+
+/// Outline the suggested placement when a piece spawns or is held. The search
+/// runs once per piece, not every frame.
+pub fn update_hint_system(
+    mut commands: Commands,
+    mut events: EventReader<EngineEvent>,
+    coach: Res<Coach>,
+    engine: Res<Engine>,
+    hints: Query<Entity, With<HintBlock>>,
+) {
+    let mut recompute = coach.is_changed();
+    let mut clear = false;
+    for ev in events.iter() {
+        match ev {
+            EngineEvent::Spawned(_) | EngineEvent::Held => recompute = true,
+            EngineEvent::Locked | EngineEvent::GameOver | EngineEvent::Finished => clear = true,
+            _ => {}
+        }
+    }
+    if !recompute && !clear {
+        return;
+    }
+    for entity in hints.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(tetromino) = engine.current.as_ref() else {
+        return;
+    };
+    if !coach.enabled || engine.is_over() {
+        return;
+    }
+    let matrix = &engine.matrix;
+    let Some(mv) = Ai::default().best_move(matrix, tetromino, matrix.start_pos) else {
+        return;
+    };
+    let (left, top) = matrix.get_translation(MatrixPosition { x: 0, y: 0 });
+    for (x, y, width, height) in outline(&mv.cells()) {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: HINT_COLOR,
+                    custom_size: Some(Vec2::new(width, height)),
+                    anchor: bevy::sprite::Anchor::TopLeft,
+                    ..default()
+                },
+                // above the blocks
+                transform: Transform::from_xyz(left + x, top - y, 1.0),
+                ..default()
+            },
+            HintBlock,
+        ));
+    }
+}
+
+/// Edges of `cells` not shared with another cell, as `(x, y, width, height)`
+/// rectangles relative to the top-left corner of the matrix, y downwards.
+fn outline(cells: &[MatrixPosition]) -> Vec<(f32, f32, f32, f32)> {
+    let filled: HashSet<MatrixPosition> = cells.iter().copied().collect();
+    let mut edges = vec![];
+    for cell in cells {
+        let x = cell.x as f32 * (BLOCK_SIZE + BLOCK_SPACE);
+        let y = cell.y as f32 * (BLOCK_SIZE + BLOCK_SPACE);
+        let free = |dx, dy| {
+            !filled.contains(&MatrixPosition {
+                x: cell.x + dx,
+                y: cell.y + dy,
+            })
+        };
+        if free(0, -1) {
+            edges.push((x, y, BLOCK_SIZE, HINT_THICKNESS));
+        }
+        if free(0, 1) {
+            edges.push((
+                x,
+                y + BLOCK_SIZE - HINT_THICKNESS,
+                BLOCK_SIZE,
+                HINT_THICKNESS,
+            ));
+        }
+        if free(-1, 0) {
+            edges.push((x, y, HINT_THICKNESS, BLOCK_SIZE));
+        }
+        if free(1, 0) {
+            edges.push((
+                x + BLOCK_SIZE - HINT_THICKNESS,
+                y,
+                HINT_THICKNESS,
+                BLOCK_SIZE,
+            ));
+        }
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::outline;
+    use crate::game::components::MatrixPosition;
+
+    #[test]
+    fn test_outline_skips_shared_edges() {
+        // an I piece lying flat: 2 ends, 4 tops and 4 bottoms
+        let cells: Vec<MatrixPosition> = (0..4).map(|x| MatrixPosition { x, y: 5 }).collect();
+        assert_eq!(outline(&cells).len(), 2 + 4 + 4);
+        // a lone cell is outlined on every side
+        assert_eq!(outline(&cells[..1]).len(), 4);
+    }
+}
@@ -1,7 +1,6 @@
 use crate::game::components::{GameOverButtonAction, PausedButtonAction};
-use crate::game::matrix::Matrix;
-
-use crate::game::resources::Score;
+use crate::game::replay::ReplayRecorder;
+use crate::ui::components::SettingsState;
 use crate::{AppState, GameState};
 use bevy::app::AppExit;
 use bevy::prelude::*;
@@ -10,6 +9,7 @@ pub fn paused_button_actions(
     query: Query<(&Interaction, &PausedButtonAction), (Changed<Interaction>, With<Button>)>,
     mut app_state: ResMut<NextState<AppState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut settings_state: ResMut<NextState<SettingsState>>,
     mut writer: EventWriter<AppExit>,
 ) {
     for (interaction, action) in query.iter() {
@@ -18,6 +18,7 @@ pub fn paused_button_actions(
                 PausedButtonAction::Continue => {
                     game_state.set(GameState::Resume);
                 }
+                PausedButtonAction::Settings => settings_state.set(SettingsState::Open),
                 PausedButtonAction::Renew => {
                     app_state.set(AppState::MainMenu);
                     game_state.set(GameState::None);
@@ -29,23 +30,32 @@ pub fn paused_button_actions(
 }
 
 pub fn game_over_button_actions(
-    query: Query<(&Interaction, &GameOverButtonAction), (Changed<Interaction>, With<Button>)>,
+    query: Query<
+        (&Interaction, &GameOverButtonAction, &Children),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut texts: Query<&mut Text>,
+    mut recorder: ResMut<ReplayRecorder>,
     mut app_state: ResMut<NextState<AppState>>,
     mut game_state: ResMut<NextState<GameState>>,
     mut writer: EventWriter<AppExit>,
-    mut matrix: ResMut<Matrix>,
-    mut score: ResMut<Score>,
 ) {
-    for (interaction, action) in query.iter() {
+    for (interaction, action, children) in query.iter() {
         if *interaction == Interaction::Clicked {
             match *action {
-                GameOverButtonAction::Renew => {
-                    matrix.renew();
-                    *score = Score::default();
+                GameOverButtonAction::Retry => {
                     game_state.set(GameState::New);
                 }
+                GameOverButtonAction::SaveReplay => {
+                    recorder.save_finished();
+                    if recorder.last_saved.is_some() {
+                        let mut texts = texts.iter_many_mut(children);
+                        while let Some(mut text) = texts.fetch_next() {
+                            text.sections[0].value = "Saved".to_string();
+                        }
+                    }
+                }
                 GameOverButtonAction::MainMenu => {
-                    matrix.renew();
                     app_state.set(AppState::MainMenu);
                     game_state.set(GameState::None);
                 }
@@ -3,26 +3,42 @@ use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 
 use crate::game::components::{
-    GameArea, GameOverButtonAction, GameOverLayout, PausedButtonAction, PausedLayout,
+    GameArea, GameDisplay, GameOverButtonAction, GameOverLayout, HighScoreTable, NameInput,
+    PausedButtonAction, PausedLayout, ReplayHud,
 };
+use crate::game::engine::Engine;
 use crate::game::global::{
     BLOCK_SIZE, BLOCK_SPACE, BORDER_SIZE, RIGHT_WIDTH, SEPARATE, WHITESPACE_WIDTH,
 };
-use crate::game::matrix::Matrix;
+use crate::game::leaderboard::{Leaderboard, NewRecord, Ranking, TABLE_SIZE};
+use crate::game::net::NetMatch;
+use crate::game::replay::ReplayRecorder;
+use crate::game::stats::Stats;
 use crate::game::style::{
     get_game_label_text_style, get_game_text_style, PAUSED_LAYOUT_BACKGROUND_COLOR, TEXT_FONT_SIZE,
 };
+use crate::game::systems::results::{results_lines, results_title};
+use crate::game::theme::ActiveTheme;
+use crate::game::versus::{VersusMatch, VersusPlayer};
+use crate::ui::components::BackButton;
 
-use crate::ui::style::{get_text_style, get_title_text_style, BUTTON_STYLE, NORMAL_BUTTON};
+use crate::ui::style::{
+    get_list_text_style, get_text_style, get_title_text_style, BUTTON_STYLE, HIGHLIGHTED_TEXT,
+    NORMAL_BUTTON,
+};
+use crate::ui::systems::layout::spawn_score_rows;
 
 /// Spawn game background area.
 pub fn spawn_board_system(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    matrix: Res<Matrix>,
+    engine: Res<Engine>,
+    theme: Res<ActiveTheme>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
+    let matrix = &engine.matrix;
+    let colors = theme.theme.colors;
     let _bg: Handle<Image> = asset_server.load("bg.png");
     // commands.spawn((
     //     SpriteBundle {
@@ -46,7 +62,7 @@ pub fn spawn_board_system(
                     matrix.height,
                 ))))
                 .into(),
-            material: materials.add(ColorMaterial::from(Color::DARK_GRAY)),
+            material: materials.add(ColorMaterial::from(colors.board)),
             transform: Transform::from_xyz(-(SEPARATE + RIGHT_WIDTH / 2.0), 0.0, 0.0),
             ..Default::default()
         },
@@ -58,7 +74,7 @@ pub fn spawn_board_system(
         SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(Vec2::new(BORDER_SIZE, matrix.height + 2.0 * BORDER_SIZE)),
-                color: Color::DARK_GRAY,
+                color: colors.border,
                 ..default()
             },
             transform: Transform::from_xyz(
@@ -75,7 +91,7 @@ pub fn spawn_board_system(
         SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(Vec2::new(BORDER_SIZE, matrix.height + 2.0 * BORDER_SIZE)),
-                color: Color::DARK_GRAY,
+                color: colors.border,
                 ..default()
             },
             transform: Transform::from_xyz(
@@ -93,7 +109,7 @@ pub fn spawn_board_system(
         SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(Vec2::new(matrix.width + 2.0 * BORDER_SIZE, BORDER_SIZE)),
-                color: Color::DARK_GRAY,
+                color: colors.border,
                 ..default()
             },
             transform: Transform::from_xyz(
@@ -111,7 +127,7 @@ pub fn spawn_board_system(
         SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(Vec2::new(matrix.width + BORDER_SIZE * 2.0, BORDER_SIZE)),
-                color: Color::DARK_GRAY,
+                color: colors.border,
                 ..default()
             },
             transform: Transform::from_xyz(
@@ -125,31 +141,22 @@ pub fn spawn_board_system(
     ));
 }
 
-pub fn spawn_bg_block_system(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    matrix: Res<Matrix>,
-    _texture_atlas: ResMut<Assets<TextureAtlas>>,
-) {
-    let block_bg = asset_server.load("black.png");
-    // let ta = TextureAtlas::from_grid(
-    //     block_bg,
-    //     Vec2::new(BLOCK_SIZE, BLOCK_SIZE),
-    //     1,
-    //     1,
-    //     None,
-    //     None,
-    // );
+/// Spawn the empty cells of the board.
+pub fn spawn_bg_block_system(mut commands: Commands, engine: Res<Engine>, theme: Res<ActiveTheme>) {
+    let matrix = &engine.matrix;
+    let empty = theme.theme.empty;
     let mut bundles = vec![];
     for i in 0..matrix.field_width {
         for j in 0..matrix.field_height {
             bundles.push((
-                SpriteBundle {
-                    sprite: Sprite {
+                SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        index: empty.index,
+                        color: empty.tint,
                         custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_SIZE)),
                         ..default()
                     },
-                    texture: block_bg.clone(),
+                    texture_atlas: theme.atlas.clone(),
                     transform: Transform::from_xyz(
                         -(SEPARATE + RIGHT_WIDTH / 2.0) - matrix.width / 2.0
                             + i as f32 * (BLOCK_SIZE + BLOCK_SPACE)
@@ -163,35 +170,82 @@ pub fn spawn_bg_block_system(
                 },
                 GameArea::Block,
             ));
-            // bundles.push(SpriteSheetBundle {
-            //     sprite: TextureAtlasSprite {
-            //         custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_SIZE)),
-            //         ..default()
-            //     },
-            //     texture_atlas: texture_atlas.add(ta.clone()).into(),
-            //     transform: Transform::from_xyz(
-            //         -(SEPARATE + RIGHT_WIDTH / 2.0) - matrix.width / 2.0
-            //             + i as f32 * (BLOCK_SIZE + BLOCK_SPACE)
-            //             + BLOCK_SIZE / 2.0,
-            //         matrix.height / 2.0 - j as f32 * (BLOCK_SIZE + BLOCK_SPACE) - BLOCK_SIZE / 2.0,
-            //         0.0,
-            //     ),
-            //     ..default()
-            // });
         }
     }
     commands.spawn_batch(bundles.into_iter());
 }
 
+/// Restyle the board and the panels when the theme changes during a game.
+pub fn restyle_board_system(
+    theme: Res<ActiveTheme>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut borders: Query<(&GameArea, &mut Sprite)>,
+    mut cells: Query<(
+        &GameArea,
+        &mut TextureAtlasSprite,
+        &mut Handle<TextureAtlas>,
+    )>,
+    panels: Query<(&GameArea, &Handle<ColorMaterial>)>,
+) {
+    if !theme.is_changed() || theme.is_added() {
+        return;
+    }
+    let colors = theme.theme.colors;
+    for (area, mut sprite) in borders.iter_mut() {
+        if matches!(
+            area,
+            GameArea::LeftBorder
+                | GameArea::RightBorder
+                | GameArea::TopBorder
+                | GameArea::BottomBorder
+        ) {
+            sprite.color = colors.border;
+        }
+    }
+    let empty = theme.theme.empty;
+    for (area, mut sprite, mut atlas) in cells.iter_mut() {
+        if *area == GameArea::Block {
+            sprite.index = empty.index;
+            sprite.color = empty.tint;
+            *atlas = theme.atlas.clone();
+        }
+    }
+    let background = panel_background(&theme, &asset_server);
+    for (area, material) in panels.iter() {
+        let Some(material) = materials.get_mut(material) else {
+            continue;
+        };
+        match area {
+            GameArea::Left => material.color = colors.board,
+            _ => {
+                material.color = colors.panel;
+                material.texture = background.clone();
+            }
+        }
+    }
+}
+
+fn panel_background(theme: &ActiveTheme, asset_server: &AssetServer) -> Option<Handle<Image>> {
+    theme
+        .theme
+        .background
+        .as_ref()
+        .map(|path| asset_server.load(path.as_str()))
+}
+
 /// Spawn game background area.
 pub fn spawn_right_system(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    matrix: Res<Matrix>,
+    engine: Res<Engine>,
+    theme: Res<ActiveTheme>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let bg: Handle<Image> = asset_server.load("bg.png");
+    let matrix = &engine.matrix;
+    let bg = panel_background(&theme, &asset_server);
+    let panel = theme.theme.colors.panel;
 
     // 1. Hold on queue Area
     commands
@@ -203,8 +257,8 @@ pub fn spawn_right_system(
                 ))))
                 .into(),
             material: materials.add(ColorMaterial {
-                texture: Some(bg.clone()),
-                ..default()
+                color: panel,
+                texture: bg.clone(),
             }),
             transform: Transform::from_xyz(
                 SEPARATE + RIGHT_WIDTH / 2.0 + WHITESPACE_WIDTH,
@@ -225,8 +279,8 @@ pub fn spawn_right_system(
                 ))))
                 .into(),
             material: materials.add(ColorMaterial {
-                texture: Some(bg.clone()),
-                ..default()
+                color: panel,
+                texture: bg.clone(),
             }),
             transform: Transform::from_xyz(
                 SEPARATE + RIGHT_WIDTH / 2.0 + WHITESPACE_WIDTH,
@@ -277,8 +331,8 @@ pub fn spawn_right_system(
                 ))))
                 .into(),
             material: materials.add(ColorMaterial {
-                texture: Some(bg.clone()),
-                ..default()
+                color: panel,
+                texture: bg.clone(),
             }),
             transform: Transform::from_xyz(
                 SEPARATE + RIGHT_WIDTH / 2.0 + WHITESPACE_WIDTH,
@@ -327,8 +381,8 @@ pub fn spawn_right_system(
                 ))))
                 .into(),
             material: materials.add(ColorMaterial {
-                texture: Some(bg),
-                ..default()
+                color: panel,
+                texture: bg,
             }),
             transform: Transform::from_xyz(
                 SEPARATE + RIGHT_WIDTH / 2.0 + WHITESPACE_WIDTH,
@@ -404,6 +458,7 @@ pub fn spawn_pause_layout_system(mut commands: Commands, asset_server: Res<Asset
                         ..Default::default()
                     },
                     PausedButtonAction::Continue,
+                    BackButton,
                 ))
                 .with_children(|parent| {
                     parent.spawn(TextBundle {
@@ -418,6 +473,29 @@ pub fn spawn_pause_layout_system(mut commands: Commands, asset_server: Res<Asset
                     });
                 });
         })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    PausedButtonAction::Settings,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new(
+                                "Settings",
+                                get_text_style(&asset_server),
+                            )],
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        })
         .with_children(|parent| {
             parent
                 .spawn((
@@ -463,7 +541,83 @@ pub fn spawn_pause_layout_system(mut commands: Commands, asset_server: Res<Asset
         });
 }
 
-pub fn spawn_game_over_layout_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// A button of the results screen.
+fn spawn_game_over_button(
+    parent: &mut ChildBuilder,
+    action: impl Bundle,
+    label: &str,
+    asset_server: &Res<AssetServer>,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    margin: UiRect::new(Val::Px(10.0), Val::Px(10.0), Val::Px(20.0), Val::Px(0.0)),
+                    ..BUTTON_STYLE
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..Default::default()
+            },
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                get_text_style(asset_server),
+            ));
+        });
+}
+
+/// Results screen: the breakdown of the game or the versus summary, the high
+/// scores and what to do next.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_game_over_layout_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    engine: Res<Engine>,
+    stats: Res<Stats>,
+    leaderboard: Res<Leaderboard>,
+    record: Option<Res<NewRecord>>,
+    recorder: Res<ReplayRecorder>,
+    versus: Res<VersusMatch>,
+    players: Query<(&VersusPlayer, &Engine)>,
+    net: Res<NetMatch>,
+) {
+    let variant = engine.ruleset.variant();
+    let (title, summary) = if versus.active {
+        let title = match (versus.winner, net.active) {
+            (Some(index), true) if index == net.player => "You Win".to_string(),
+            (Some(_), true) => "You Lose".to_string(),
+            (Some(index), false) => format!("Player {} Wins", index + 1),
+            (None, _) => "Draw".to_string(),
+        };
+        let mut players: Vec<_> = players.iter().collect();
+        players.sort_by_key(|(player, _)| player.index);
+        let summary = players
+            .iter()
+            .map(|(player, engine)| {
+                format!(
+                    "Player {}   Lines {}   Sent {}",
+                    player.index + 1,
+                    engine.total_lines,
+                    player.garbage.sent
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        (title, summary)
+    } else {
+        (
+            results_title(&engine).to_string(),
+            results_lines(&engine, &stats).join("\n"),
+        )
+    };
+    // the best game of the variant before this one, by the measure it ranks on
+    let ranking = Ranking::of(engine.ruleset.mode);
+    let best = leaderboard
+        .table(&variant)
+        .first()
+        .map(|entry| ranking.format(entry));
     commands
         .spawn((
             NodeBundle {
@@ -481,11 +635,19 @@ pub fn spawn_game_over_layout_system(mut commands: Commands, asset_server: Res<A
             GameOverLayout,
         ))
         .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    sections: vec![TextSection::new(title, get_title_text_style(&asset_server))],
+                    alignment: TextAlignment::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
             parent.spawn(TextBundle {
                 text: Text {
                     sections: vec![TextSection::new(
-                        "Your Game is Over",
-                        get_title_text_style(&asset_server),
+                        summary,
+                        get_list_text_style(&asset_server),
                     )],
                     alignment: TextAlignment::Center,
                     ..Default::default()
@@ -494,69 +656,148 @@ pub fn spawn_game_over_layout_system(mut commands: Commands, asset_server: Res<A
             });
         })
         .with_children(|parent| {
-            parent
-                .spawn((
-                    ButtonBundle {
-                        style: BUTTON_STYLE,
-                        background_color: NORMAL_BUTTON.into(),
-                        ..Default::default()
-                    },
-                    GameOverButtonAction::Renew,
-                ))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            sections: vec![TextSection::new(
-                                "Renew",
-                                get_text_style(&asset_server),
-                            )],
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    });
-                });
+            if versus.active {
+                return;
+            }
+            let text = match (record.as_ref().map(|r| r.rank), best) {
+                (Some(0), _) => "New Personal Best!".to_string(),
+                (_, Some(best)) => format!("Personal Best {}", best),
+                (_, None) => return,
+            };
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    color: HIGHLIGHTED_TEXT,
+                    ..get_list_text_style(&asset_server)
+                },
+            ));
+        })
+        .with_children(|parent| {
+            // name prompt of a new high score
+            let Some(record) = record.as_ref() else {
+                return;
+            };
+            parent.spawn(TextBundle::from_section(
+                format!("New Record! #{}", record.rank + 1),
+                TextStyle {
+                    color: HIGHLIGHTED_TEXT,
+                    ..get_text_style(&asset_server)
+                },
+            ));
+            parent.spawn((
+                TextBundle::from_sections([
+                    TextSection::new(
+                        format!("Name: {}_", record.entry.name),
+                        get_list_text_style(&asset_server),
+                    ),
+                    TextSection::new("  Enter to save", get_list_text_style(&asset_server)),
+                ]),
+                NameInput,
+            ));
         })
         .with_children(|parent| {
+            // the table shows where the record would rank until it is saved
+            if versus.active {
+                return;
+            }
+            let mut table = leaderboard.table(&variant).to_vec();
+            if let Some(record) = record.as_ref() {
+                table.insert(record.rank, record.entry.clone());
+                table.truncate(TABLE_SIZE);
+            }
             parent
                 .spawn((
-                    ButtonBundle {
-                        style: BUTTON_STYLE,
-                        background_color: NORMAL_BUTTON.into(),
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            margin: UiRect::vertical(Val::Px(12.0)),
+                            ..Default::default()
+                        },
                         ..Default::default()
                     },
-                    GameOverButtonAction::MainMenu,
+                    HighScoreTable,
                 ))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            sections: vec![TextSection::new(
-                                "MainMenu",
-                                get_text_style(&asset_server),
-                            )],
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    });
+                    spawn_score_rows(
+                        parent,
+                        &table,
+                        record.as_ref().map(|r| r.rank),
+                        &asset_server,
+                    );
                 });
         })
         .with_children(|parent| {
             parent
-                .spawn((
-                    ButtonBundle {
-                        style: BUTTON_STYLE,
-                        background_color: NORMAL_BUTTON.into(),
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
                         ..Default::default()
                     },
-                    GameOverButtonAction::Exit,
-                ))
+                    ..Default::default()
+                })
                 .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text {
-                            sections: vec![TextSection::new("Exit", get_text_style(&asset_server))],
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    });
+                    // the server ends the connection with the match
+                    if !net.active {
+                        spawn_game_over_button(
+                            parent,
+                            GameOverButtonAction::Retry,
+                            "Retry",
+                            &asset_server,
+                        );
+                    }
+                    // a replay with a new record is already saved
+                    if recorder.finished.is_some() {
+                        spawn_game_over_button(
+                            parent,
+                            GameOverButtonAction::SaveReplay,
+                            "Save Replay",
+                            &asset_server,
+                        );
+                    }
+                    spawn_game_over_button(
+                        parent,
+                        (GameOverButtonAction::MainMenu, BackButton),
+                        "Main Menu",
+                        &asset_server,
+                    );
+                    spawn_game_over_button(
+                        parent,
+                        GameOverButtonAction::Exit,
+                        "Exit",
+                        &asset_server,
+                    );
                 });
         });
 }
+
+/// Spawn the replay viewer status, inputs and controls help.
+pub fn spawn_replay_hud_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = get_game_text_style(&asset_server);
+    commands.spawn((
+        TextBundle {
+            text: Text::from_sections([
+                TextSection::new("", style.clone()),
+                TextSection::new("", style.clone()),
+                TextSection::new(
+                    "Space: play/pause  ,/.: step  Left/Right: seek  Up/Down: speed  Esc: back",
+                    TextStyle {
+                        font_size: TEXT_FONT_SIZE * 0.6,
+                        ..style
+                    },
+                ),
+            ]),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        },
+        ReplayHud,
+        GameDisplay,
+    ));
+}
@@ -1,167 +1,224 @@
-//! Spawn current Tetromino
+//! Keep tetromino blocks in sync with the engine
 
-use std::time::Duration;
-
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 
 use crate::game::{
     components::{
-        Block, BlockBundle, CurrentTetromino, GameArea, HoldQueueTetromino, LockedDownBlock,
-        MatrixPosition, UpdateBlock,
+        Block, BlockBundle, CurrentTetromino, GameArea, GhostBlock, HeldTetromino,
+        HoldQueueTetromino, LockedDownBlock, MatrixPosition, UpdateBlock,
     },
-    global::get_falling_speed,
+    engine::{Engine, EngineEvent, Phase},
+    global::{BLOCK_SIZE, BLOCK_SPACE, BORDER_SIZE},
     matrix::Matrix,
-    resources::{HoldOnQueueResoure, ImagePathResources, ScoreAction, ScoreEvent},
-    timer::SoftDropTimer,
+    settings::Settings,
+    tetromino::{Tetromino, TetrominoType},
+    theme::ActiveTheme,
 };
 
-/// A function to spawn current tetromino shape.
-pub fn spawn_current_tetromino(
+/// Size of the previews after the first one.
+const SMALL_PREVIEW_SCALE: f32 = 0.5;
+/// Size of the hold tetromino, drawn left of the board.
+const HOLD_SCALE: f32 = 0.45;
+/// Frames the cleared rows stay lit, then dimmed, while they flash.
+const FLASH_FRAMES: u32 = 4;
+/// Last frames of the line clear delay, during which the rows fade out.
+const FADE_FRAMES: u32 = 10;
+
+/// Rebuild all the blocks from the engine state, e.g. after seeking in a replay.
+pub struct RefreshBlocks;
+
+/// Resources needed to build a `BlockBundle`.
+#[derive(SystemParam)]
+pub struct BlockAssets<'w> {
+    theme: Res<'w, ActiveTheme>,
+    settings: Res<'w, Settings>,
+}
+
+impl<'w> BlockAssets<'w> {
+    pub fn bundle(
+        &self,
+        start_position: MatrixPosition,
+        rel_position: MatrixPosition,
+        matrix: &Matrix,
+        ty: TetrominoType,
+    ) -> BlockBundle {
+        let tile = self.theme.theme.pieces.get(ty);
+        BlockBundle::new(
+            start_position,
+            rel_position,
+            matrix,
+            self.theme.atlas.clone(),
+            tile,
+        )
+    }
+
+    /// A block of the heap or of the ghost, from the value of its cell.
+    pub fn cell(
+        &self,
+        position: MatrixPosition,
+        matrix: &Matrix,
+        value: u8,
+        ghost: bool,
+    ) -> BlockBundle {
+        let tile = self.theme.theme.cell(value, ghost);
+        BlockBundle::new(
+            position,
+            MatrixPosition { x: 0, y: 0 },
+            matrix,
+            self.theme.atlas.clone(),
+            tile,
+        )
+    }
+}
+
+/// Spawn, move and despawn block entities according to the engine events.
+#[allow(clippy::too_many_arguments)]
+pub fn sync_blocks_system(
     mut commands: Commands,
-    image_resource: Res<ImagePathResources>,
-    mut matrix: ResMut<Matrix>,
-    mut soft_drop_timer: ResMut<SoftDropTimer>,
-    mut hold_on_queue: ResMut<HoldOnQueueResoure>,
-    asset_server: Res<AssetServer>,
-    mut texture_atlas: ResMut<Assets<TextureAtlas>>,
+    mut events: EventReader<EngineEvent>,
+    mut refresh: EventReader<RefreshBlocks>,
+    engine: Res<Engine>,
+    assets: BlockAssets,
+    mut current_minos: Query<(Entity, &mut Block), With<CurrentTetromino>>,
+    heap_blocks: Query<Entity, With<LockedDownBlock>>,
+    ghost_blocks: Query<Entity, With<GhostBlock>>,
     query_hold_on_queue: Query<Entity, With<HoldQueueTetromino>>,
-    mut heap_blocks: Query<(Entity, &mut Block), With<LockedDownBlock>>,
-    mut score_writer: EventWriter<ScoreEvent>,
+    held_blocks: Query<Entity, With<HeldTetromino>>,
 ) {
-    if !matrix.create || matrix.game_over {
-        return;
-    }
-    matrix.create = false;
-
-    // From last line to first
-    let mut y = matrix.field_height - 1;
-    let mut full_rows = vec![];
-    while y > 0 {
-        let mut full_row = true;
-        for i in 0..matrix.field_width {
-            let index = y * matrix.field_width + i;
-            if matrix.occupation[index] == 0 {
-                full_row = false;
+    let mut spawned = false;
+    let mut moved = false;
+    let mut locked = false;
+    let mut held = false;
+    for ev in events.iter() {
+        match ev {
+            EngineEvent::Spawned(_) => spawned = true,
+            EngineEvent::Moved | EngineEvent::Rotated => moved = true,
+            EngineEvent::Locked | EngineEvent::LinesCleared | EngineEvent::GarbageReceived => {
+                locked = true
             }
+            EngineEvent::Held => held = true,
+            _ => {}
         }
-
-        if full_row {
-            full_rows.push(y);
-        }
-        y -= 1;
     }
+    if refresh.iter().count() > 0 {
+        spawned = true;
+        locked = true;
+        held = true;
+    }
+    let matrix = &engine.matrix;
 
-    if full_rows.len() > 0 {
-        // clear line
-        let max_row = full_rows.iter().max().unwrap();
-        for _ in full_rows.iter() {
-            for (entity, mut block) in heap_blocks.iter_mut() {
-                match (*max_row as i32).cmp(&block.position.y) {
-                    // top -1
-                    std::cmp::Ordering::Less => {}
-                    // clear
-                    std::cmp::Ordering::Equal => {
-                        if block.position.x != -1 {
-                            block.position.x = -1;
-                            #[cfg(debug_assertions)]
-                            {
-                                println!("-------------------------");
-                                println!(
-                                    "pos:{}, {}, entity id: {:?}",
-                                    block.position.x,
-                                    block.position.y,
-                                    commands.entity(entity).id()
-                                );
-                                println!("-------------------------");
-                            }
-                            commands.entity(entity).despawn_recursive();
-                        }
-                    }
-                    std::cmp::Ordering::Greater => {
-                        block.position.y += 1;
-                        commands.entity(entity).insert(UpdateBlock);
-                    }
-                }
+    if spawned || locked {
+        for (entity, _) in current_minos.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if let Some(tetromino) = engine.current.as_ref() {
+            for mp in tetromino.get_blocks_position().into_iter() {
+                commands
+                    .spawn(assets.bundle(matrix.start_pos, mp, matrix, tetromino.ty))
+                    .insert(CurrentTetromino);
             }
         }
-        let min_y = full_rows.iter().min().unwrap() - 1;
-        // clear occupation data
-        for j in (1..=min_y).rev() {
-            for i in 0..matrix.field_width {
-                let down_row_index = (j + full_rows.len()) * matrix.field_width + i;
-                let up_row_index = j * matrix.field_width + i;
-                matrix.occupation[down_row_index] = matrix.occupation[up_row_index];
+    } else if moved {
+        let positions = engine.current_blocks();
+        for (index, (entity, mut block)) in current_minos.iter_mut().enumerate() {
+            if let Some(pos) = positions.get(index) {
+                block.position = *pos;
+                commands.entity(entity).insert(UpdateBlock);
             }
         }
+    }
 
-        // make sure the first line is cleared.
-        for j in 0..full_rows.len() {
-            for i in 0..matrix.field_width {
-                let index = j * matrix.field_width + i;
-                if matrix.occupation[index] == 1 {
-                    matrix.occupation[index] = 0;
-                }
+    if spawned || moved || locked {
+        for entity in ghost_blocks.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if let (true, Some(tetromino)) =
+            (assets.settings.display.ghost_piece, engine.current.as_ref())
+        {
+            for position in engine.ghost_blocks() {
+                commands
+                    .spawn(assets.cell(position, matrix, tetromino.ty as u8 + 1, true))
+                    .insert(GhostBlock);
             }
         }
-
-        score_writer.send(ScoreEvent {
-            cleared_lines: full_rows.len(),
-            action: ScoreAction::from(full_rows.len()),
-        });
-        matrix.lines_cleared += full_rows.len();
-    }
-    if matrix.hard_dropping {
-        score_writer.send(ScoreEvent::hard_drop(1));
     }
 
-    let falling_speed = get_falling_speed(matrix.level);
-
-    soft_drop_timer
-        .timer
-        .set_duration(Duration::from_secs_f32(falling_speed));
-    soft_drop_timer.timer.reset();
-    soft_drop_timer
-        .timer
-        .set_elapsed(Duration::from_secs_f32(falling_speed));
-
-    // spawn current tetromino
-    let tetromino = hold_on_queue.pop_push();
-
-    for mp in tetromino.get_blocks_position().into_iter() {
-        commands
-            .spawn(BlockBundle::new(
-                matrix.start_pos,
-                mp,
-                &matrix,
-                image_resource.get_path(tetromino.ty),
-                asset_server.as_ref(),
-                texture_atlas.as_mut(),
-            ))
-            .insert(CurrentTetromino);
+    // rebuild the heap from the matrix occupation, rows may have been cleared.
+    if locked {
+        for entity in heap_blocks.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        for (index, cell) in matrix.occupation.iter().enumerate() {
+            if *cell == 0 {
+                continue;
+            }
+            let position = MatrixPosition {
+                x: (index % matrix.field_width) as i32,
+                y: (index / matrix.field_width) as i32,
+            };
+            commands
+                .spawn(assets.cell(position, matrix, *cell, false))
+                .insert(LockedDownBlock);
+        }
     }
-    // spawn current tetromino
-    commands.spawn(tetromino).insert(CurrentTetromino);
 
-    // despawn poped tetromino in the hold_on_queue.
-    for entity in query_hold_on_queue.iter() {
-        commands.entity(entity).despawn_recursive();
+    if held {
+        for entity in held_blocks.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if let Some(ty) = engine.hold {
+            let tetromino = Tetromino::new(ty);
+            let (x, y) = matrix.get_translation(MatrixPosition { x: 0, y: 1 });
+            let cell = (BLOCK_SIZE + BLOCK_SPACE) * HOLD_SCALE;
+            let left = x - BORDER_SIZE - 4.0 * cell;
+            for mp in tetromino.get_blocks_position().into_iter() {
+                let translation = Vec3::new(left + mp.x as f32 * cell, y - mp.y as f32 * cell, 0.0);
+                commands
+                    .spawn(
+                        assets
+                            .bundle(MatrixPosition { x: 0, y: 1 }, mp, matrix, ty)
+                            .with_scale(translation, HOLD_SCALE),
+                    )
+                    .insert(HeldTetromino)
+                    .insert(GameArea::HoldOnQueue);
+            }
+        }
     }
 
-    // spawn next tetromino
-    let next_tetromino = hold_on_queue.first();
-    if let Some(next_tetromino) = next_tetromino {
-        for mp in next_tetromino.get_blocks_position().iter() {
-            commands
-                .spawn(BlockBundle::new(
+    if spawned {
+        // despawn poped tetromino in the hold_on_queue.
+        for entity in query_hold_on_queue.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        // spawn next tetrominos, the first one in the hold on queue area and
+        // the others smaller below it.
+        let (x, y) = matrix.get_translation(MatrixPosition { x: 12, y: 5 });
+        let cell = (BLOCK_SIZE + BLOCK_SPACE) * SMALL_PREVIEW_SCALE;
+        let previews = engine
+            .queue
+            .values
+            .iter()
+            .take(assets.settings.display.preview_count);
+        for (index, next_tetromino) in previews.enumerate() {
+            for mp in next_tetromino.get_blocks_position().into_iter() {
+                let mut bundle = assets.bundle(
                     MatrixPosition { x: 12, y: 1 },
-                    *mp,
-                    matrix.as_ref(),
-                    image_resource.get_path(next_tetromino.ty),
-                    asset_server.as_ref(),
-                    texture_atlas.as_mut(),
-                ))
-                .insert(HoldQueueTetromino)
-                .insert(GameArea::HoldOnQueue);
+                    mp,
+                    matrix,
+                    next_tetromino.ty,
+                );
+                if index > 0 {
+                    let top = y - cell / 2.0 - (index - 1) as f32 * 3.0 * cell;
+                    let translation =
+                        Vec3::new(x + mp.x as f32 * cell, top - mp.y as f32 * cell, 0.0);
+                    bundle = bundle.with_scale(translation, SMALL_PREVIEW_SCALE);
+                }
+                commands
+                    .spawn(bundle)
+                    .insert(HoldQueueTetromino)
+                    .insert(GameArea::HoldOnQueue);
+            }
         }
     }
 }
@@ -169,11 +226,41 @@ pub fn spawn_current_tetromino(
 pub fn update_block_system(
     mut commands: Commands,
     mut query: Query<(Entity, &mut Transform, &Block), With<UpdateBlock>>,
-    matrix: Res<Matrix>,
+    engine: Res<Engine>,
 ) {
     for (entity, mut transform, block) in query.iter_mut() {
-        let (x, y) = matrix.get_translation(block.position);
+        let (x, y) = engine.matrix.get_translation(block.position);
         transform.translation = Vec3::new(x, y, 0.0);
         commands.entity(entity).remove::<UpdateBlock>();
     }
 }
+
+/// Flash the cleared rows, then fade them out until they collapse.
+pub fn clear_animation_system(
+    engine: Res<Engine>,
+    mut heap_blocks: Query<(&Block, &mut TextureAtlasSprite), With<LockedDownBlock>>,
+) {
+    let Phase::Clearing { rows, frames } = &engine.phase else {
+        return;
+    };
+    let flash = match (frames / FLASH_FRAMES) % 2 {
+        0 => 1.0,
+        _ => 0.4,
+    };
+    let alpha = flash * (*frames as f32 / FADE_FRAMES as f32).min(1.0);
+    for (block, mut sprite) in heap_blocks.iter_mut() {
+        if rows.contains(&(block.position.y as usize)) {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+/// Redraw the blocks when the settings are changed during a game.
+pub fn refresh_on_settings_change(
+    settings: Res<Settings>,
+    mut refresh: EventWriter<RefreshBlocks>,
+) {
+    if settings.is_changed() && !settings.is_added() {
+        refresh.send(RefreshBlocks);
+    }
+}
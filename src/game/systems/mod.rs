@@ -1,7 +1,16 @@
+pub(crate) mod countdown;
+pub(crate) mod garbage_meter;
+pub(crate) mod high_score;
+pub(crate) mod hint;
 pub(crate) mod interactions;
 pub(crate) mod layout;
 pub(crate) mod minos;
 pub(crate) mod movement;
 pub(crate) mod paused;
+pub(crate) mod playback;
+pub(crate) mod results;
+pub(crate) mod scaling;
 pub(crate) mod score;
 pub(crate) mod setup_game;
+pub(crate) mod stats_panel;
+pub(crate) mod versus_board;
@@ -1,13 +1,23 @@
-use bevy::prelude::*;
+use bevy::{
+    input::gamepad::{GamepadConnection, GamepadConnectionEvent},
+    prelude::*,
+};
 
-use crate::{game::GameState, AppState};
+use crate::{
+    game::{
+        input::{ActionInput, InputAction},
+        replay::ReplayPlayer,
+        GameState,
+    },
+    AppState,
+};
 
 pub fn pause_resume_system(
-    key_code: Res<Input<KeyCode>>,
+    input: ActionInput,
     current_game_state: Res<State<GameState>>,
     mut game_state: ResMut<NextState<GameState>>,
 ) {
-    if key_code.just_pressed(KeyCode::Escape) || key_code.just_pressed(KeyCode::P) {
+    if input.just_pressed(InputAction::Pause) {
         match current_game_state.0 {
             GameState::Paused => game_state.set(GameState::Resume),
             GameState::New => game_state.set(GameState::Paused),
@@ -19,6 +29,31 @@ pub fn pause_resume_system(
     }
 }
 
+/// Log gamepads coming and going, and pause the game when one is unplugged
+/// mid-game.
+pub fn gamepad_connection_system(
+    mut events: EventReader<GamepadConnectionEvent>,
+    current_app_state: Res<State<AppState>>,
+    current_game_state: Res<State<GameState>>,
+    player: Res<ReplayPlayer>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    for event in events.iter() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => {
+                info!("gamepad {} connected: {}", event.gamepad.id, info.name)
+            }
+            GamepadConnection::Disconnected => {
+                info!("gamepad {} disconnected", event.gamepad.id);
+                let playing = matches!(current_game_state.0, GameState::New | GameState::Resume);
+                if current_app_state.0 == AppState::Game && playing && player.replay.is_none() {
+                    game_state.set(GameState::Paused);
+                }
+            }
+        }
+    }
+}
+
 pub fn is_game_resumed_or_new(
     current_app_state: Res<State<AppState>>,
     current_game_state: Res<State<GameState>>,
@@ -0,0 +1,141 @@
+//! Replay viewer: feed recorded actions into the engine.
+use bevy::prelude::*;
+
+use crate::{
+    game::{
+        components::ReplayHud,
+        engine::{Engine, EngineEvent},
+        global::{format_duration, FRAME_DURATION},
+        replay::ReplayPlayer,
+        GameState,
+    },
+    AppState,
+};
+
+use super::minos::RefreshBlocks;
+
+/// Seconds skipped by a seek.
+const SEEK_SECONDS: u64 = 5;
+
+/// Step the engine with the replay according to the viewer speed, pause, steps and seek.
+pub fn replay_playback_system(
+    time: Res<Time>,
+    mut player: ResMut<ReplayPlayer>,
+    mut engine: ResMut<Engine>,
+    mut engine_events: EventWriter<EngineEvent>,
+    mut refresh: EventWriter<RefreshBlocks>,
+) {
+    let player = player.as_mut();
+    let Some(replay) = player.replay.take() else {
+        return;
+    };
+
+    if let Some(target) = player.seek.take() {
+        // re-simulate from the start when seeking backward.
+        if target < engine.frame {
+            *engine = Engine::new(replay.seed, replay.ruleset);
+            player.cursor = 0;
+            player.held.clear();
+        }
+        while engine.frame < target && !engine.is_over() {
+            let actions = replay.step(&mut engine, &mut player.cursor);
+            player.track(&actions);
+        }
+        engine.drain_events();
+        refresh.send(RefreshBlocks);
+    } else {
+        let mut frames = 0;
+        if player.paused {
+            frames = std::mem::take(&mut player.steps);
+        } else {
+            player.elapsed += time.delta().mul_f32(player.speed);
+            while player.elapsed >= FRAME_DURATION {
+                player.elapsed -= FRAME_DURATION;
+                frames += 1;
+            }
+        }
+
+        for _ in 0..frames {
+            if engine.is_over() {
+                player.paused = true;
+                break;
+            }
+            let actions = replay.step(&mut engine, &mut player.cursor);
+            player.track(&actions);
+            for ev in engine.drain_events() {
+                engine_events.send(ev);
+            }
+        }
+    }
+    player.replay = Some(replay);
+}
+
+/// Keyboard controls of the replay viewer.
+pub fn replay_control_system(
+    key_code: Res<Input<KeyCode>>,
+    engine: Res<Engine>,
+    mut player: ResMut<ReplayPlayer>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let seek = SEEK_SECONDS * (1.0 / FRAME_DURATION.as_secs_f64()).round() as u64;
+
+    if key_code.just_pressed(KeyCode::Space) || key_code.just_pressed(KeyCode::P) {
+        player.paused = !player.paused;
+    }
+    if key_code.just_pressed(KeyCode::Period) && player.paused {
+        player.steps += 1;
+    }
+    if key_code.just_pressed(KeyCode::Comma) && player.paused {
+        player.seek = Some(engine.frame.saturating_sub(1));
+    }
+    if key_code.just_pressed(KeyCode::Right) {
+        player.seek = Some(engine.frame + seek);
+    }
+    if key_code.just_pressed(KeyCode::Left) {
+        player.seek = Some(engine.frame.saturating_sub(seek));
+    }
+    if key_code.just_pressed(KeyCode::Up) {
+        player.change_speed(true);
+    }
+    if key_code.just_pressed(KeyCode::Down) {
+        player.change_speed(false);
+    }
+    if key_code.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::MainMenu);
+        game_state.set(GameState::None);
+    }
+}
+
+/// Stop watching the replay when leaving the game page.
+pub fn stop_replay(mut player: ResMut<ReplayPlayer>) {
+    player.replay = None;
+}
+
+/// Show the replay progress and the inputs being pressed.
+pub fn update_replay_hud(
+    engine: Res<Engine>,
+    player: Res<ReplayPlayer>,
+    mut query: Query<&mut Text, With<ReplayHud>>,
+) {
+    let Some(replay) = player.replay.as_ref() else {
+        return;
+    };
+    let total = replay
+        .frames()
+        .map(|frames| format_duration(FRAME_DURATION * frames as u32))
+        .unwrap_or_else(|| "--:--.--".to_string());
+    let mut held: Vec<String> = player.held.iter().map(|a| format!("{:?}", a)).collect();
+    held.sort();
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Replay {} / {}  x{}{}\n",
+            format_duration(FRAME_DURATION * engine.frame as u32),
+            total,
+            player.speed,
+            if player.paused { "  (paused)" } else { "" },
+        );
+        text.sections[1].value = format!("Inputs: {}\n", held.join(" "));
+    }
+}
@@ -0,0 +1,78 @@
+//! Breakdown of a finished game, shown on the results screen.
+
+use crate::game::{
+    engine::{Engine, GameMode},
+    global::format_duration,
+    stats::Stats,
+};
+
+/// Heading of the results, a goal reached or a top out.
+pub fn results_title(engine: &Engine) -> &'static str {
+    match (engine.finished, engine.ruleset.mode) {
+        (false, _) => "Your Game is Over",
+        (true, GameMode::Sprint) => "Sprint Complete",
+        (true, GameMode::Ultra) => "Time's Up",
+        (true, GameMode::Dig) => "Garbage Cleared",
+        (true, GameMode::Puzzle) => "Puzzle Solved",
+        (true, _) => "Game Complete",
+    }
+}
+
+/// Lines of the breakdown of the game.
+pub fn results_lines(engine: &Engine, stats: &Stats) -> Vec<String> {
+    let time = engine.time();
+    let [singles, doubles, triples, tetrises] = stats.clears;
+    vec![
+        format!(
+            "{}   Score {}   Time {}",
+            engine.ruleset.variant(),
+            engine.score.value,
+            format_duration(time)
+        ),
+        format!(
+            "Lines {}   Level {}   PPS {:.2}",
+            engine.total_lines,
+            engine.matrix.level,
+            stats.pps(time)
+        ),
+        format!(
+            "Singles {}   Doubles {}   Triples {}   Tetrises {}   T-Spins {}",
+            singles, doubles, triples, tetrises, stats.t_spins
+        ),
+        format!(
+            "Max Combo {}   Back-to-Back {}",
+            stats.max_combo, stats.back_to_back
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{results_lines, results_title};
+    use crate::game::{
+        engine::{Engine, GameMode, Ruleset},
+        stats::Stats,
+    };
+
+    #[test]
+    fn test_goals_get_their_own_heading() {
+        let ruleset = Ruleset {
+            mode: GameMode::Sprint,
+            ..Ruleset::default()
+        };
+        let mut engine = Engine::new(1, ruleset);
+        assert_eq!(results_title(&engine), "Your Game is Over");
+        engine.finished = true;
+        assert_eq!(results_title(&engine), "Sprint Complete");
+
+        let mut stats = Stats::default();
+        stats.clears = [3, 2, 1, 4];
+        stats.t_spins = 2;
+        let lines = results_lines(&engine, &stats);
+        assert!(lines[0].starts_with("Sprint"));
+        assert_eq!(
+            lines[2],
+            "Singles 3   Doubles 2   Triples 1   Tetrises 4   T-Spins 2"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Scale the game to the window.
+//!
+//! The board and the panels are laid out in world units for a window of
+//! `get_game_window_min_size`, or `get_versus_window_min_size` in versus. Each
+//! camera zooms so that this layout fits its window whatever its size and
+//! aspect ratio, with a whole number of pixels per cell and the cells on pixel
+//! boundaries so that the blocks stay sharp.
+
+use bevy::{
+    prelude::*,
+    render::camera::RenderTarget,
+    window::{PrimaryWindow, WindowRef},
+};
+
+use crate::game::{
+    components::{MatrixPosition, VersusArea},
+    global::{
+        get_game_window_min_size, get_versus_window_min_size, BLOCK_SIZE, BLOCK_SPACE, VERSUS_SCALE,
+    },
+    matrix::Matrix,
+    systems::versus_board::area_origin,
+    versus::VersusMatch,
+};
+
+/// World units per pixel fitting `layout` in `window` pixels, rounded so that a
+/// cell of `pitch` world units covers a whole number of pixels.
+pub fn fit_scale(layout: Vec2, window: Vec2, pitch: f32) -> f32 {
+    let fit = (layout.x / window.x).max(layout.y / window.y);
+    let pixels = (pitch / fit).floor().max(1.0);
+    pitch / pixels
+}
+
+/// Camera position along one axis putting `anchor` on a pixel boundary of a
+/// window `pixels` wide, as close to the origin as possible.
+pub fn snap(anchor: f32, scale: f32, pixels: f32) -> f32 {
+    let screen = anchor / scale + pixels / 2.0;
+    anchor - scale * (screen.round() - pixels / 2.0)
+}
+
+/// Zoom the cameras to fit the layout to their window.
+pub fn fit_cameras_system(
+    windows: Query<&Window>,
+    primary: Query<Entity, With<PrimaryWindow>>,
+    mut cameras: Query<(&Camera, &mut OrthographicProjection, &mut Transform)>,
+    versus: Res<VersusMatch>,
+) {
+    let (layout, pitch, anchor) = match versus.active {
+        true => {
+            let (width, height) = get_versus_window_min_size();
+            let origin = area_origin(0, VersusArea::Board);
+            let pitch = (BLOCK_SIZE + BLOCK_SPACE) * VERSUS_SCALE;
+            (Vec2::new(width, height), pitch, origin)
+        }
+        false => {
+            let (width, height) = get_game_window_min_size();
+            let (x, y) = Matrix::default().get_translation(MatrixPosition { x: 0, y: 0 });
+            (
+                Vec2::new(width, height),
+                BLOCK_SIZE + BLOCK_SPACE,
+                Vec2::new(x, y),
+            )
+        }
+    };
+    for (camera, mut projection, mut transform) in cameras.iter_mut() {
+        let RenderTarget::Window(target) = camera.target else {
+            continue;
+        };
+        let entity = match target {
+            WindowRef::Primary => primary.get_single().ok(),
+            WindowRef::Entity(entity) => Some(entity),
+        };
+        let Some(window) = entity.and_then(|entity| windows.get(entity).ok()) else {
+            continue;
+        };
+        let pixels = Vec2::new(
+            window.physical_width() as f32,
+            window.physical_height() as f32,
+        );
+        if pixels.x < 1.0 || pixels.y < 1.0 {
+            continue;
+        }
+        // the projection scale is in world units per logical pixel
+        let scale = fit_scale(layout, pixels, pitch);
+        let zoom = scale * window.scale_factor() as f32;
+        let x = snap(anchor.x, scale, pixels.x);
+        let y = -snap(-anchor.y, scale, pixels.y);
+        if projection.scale != zoom {
+            projection.scale = zoom;
+        }
+        if transform.translation.x != x || transform.translation.y != y {
+            transform.translation.x = x;
+            transform.translation.y = y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec2;
+
+    use super::{fit_scale, snap};
+
+    #[test]
+    fn test_layout_fits_with_whole_pixels_per_cell() {
+        let layout = Vec2::new(800.0, 1000.0);
+        for window in [
+            Vec2::new(1280.0, 720.0),
+            Vec2::new(2560.0, 1080.0),
+            Vec2::new(600.0, 1400.0),
+            Vec2::new(401.0, 503.0),
+        ] {
+            let scale = fit_scale(layout, window, 41.0);
+            assert!(layout.x / scale <= window.x);
+            assert!(layout.y / scale <= window.y);
+            let pixels = 41.0 / scale;
+            assert!((pixels - pixels.round()).abs() < 1e-4);
+        }
+        // a larger window never gets a smaller layout
+        let small = fit_scale(layout, Vec2::new(1280.0, 720.0), 41.0);
+        let large = fit_scale(layout, Vec2::new(1920.0, 1080.0), 41.0);
+        assert!(large <= small);
+    }
+
+    #[test]
+    fn test_snap_puts_the_anchor_on_a_pixel() {
+        for (anchor, scale, pixels) in [(-431.5, 1.4, 1281.0), (410.0, 0.75, 720.0)] {
+            let camera = snap(anchor, scale, pixels);
+            let screen = (anchor - camera) / scale + pixels / 2.0;
+            assert!((screen - screen.round()).abs() < 1e-3);
+            assert!(camera.abs() <= scale);
+        }
+    }
+}
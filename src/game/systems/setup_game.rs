@@ -1,17 +1,94 @@
 //! Used to setup game page.
 
 use crate::game::{
-    global::get_game_window_min_size,
+    ai::AiPlayer,
+    components::{Block, GameArea, GameDisplay, HintBlock},
+    config::GameConfig,
+    engine::{Engine, Ruleset},
+    global::{get_game_window_min_size, MIN_LAYOUT_SCALE},
+    input::{ActionInput, ActionQueue, InputAction},
+    replay::ReplayPlayer,
+    settings::Settings,
+    systems::minos::RefreshBlocks,
+    GameState,
 };
 use bevy::{prelude::*, window::PrimaryWindow};
 
 pub fn setup_game(_commands: Commands, mut window: Query<&mut Window, With<PrimaryWindow>>) {
     let mut window = window.single_mut();
     let (window_width, window_height) = get_game_window_min_size();
-    // window should resize to defined width and height
+    // the layout scales with the window, down to a minimum
     window.resize_constraints = WindowResizeConstraints {
-        min_height: window_height,
-        min_width: window_width,
+        min_height: window_height * MIN_LAYOUT_SCALE,
+        min_width: window_width * MIN_LAYOUT_SCALE,
         ..Default::default()
     };
 }
+
+/// Seed of the next game, a new one when `None`.
+#[derive(Resource, Default)]
+pub struct NextSeed(pub Option<u64>);
+
+/// Start a fresh engine with a new seed and the options of the new game
+/// screen, or with the seed and rules of the watched replay. The AI plays by
+/// the default rules.
+#[allow(clippy::too_many_arguments)]
+pub fn reset_engine(
+    mut engine: ResMut<Engine>,
+    mut action_queue: ResMut<ActionQueue>,
+    mut refresh: EventWriter<RefreshBlocks>,
+    mut next_seed: ResMut<NextSeed>,
+    player: Res<ReplayPlayer>,
+    ai: Res<AiPlayer>,
+    config: Res<GameConfig>,
+    settings: Res<Settings>,
+) {
+    let seed = next_seed.0.take().unwrap_or_else(rand::random);
+    *engine = match (player.replay.as_ref(), ai.active) {
+        (Some(replay), _) => Engine::new(replay.seed, replay.ruleset),
+        (None, true) => Engine::new(
+            seed,
+            Ruleset {
+                handling: settings.handling,
+                delays: settings.delays,
+                ..Ruleset::default()
+            },
+        ),
+        (None, false) => Engine::new(seed, config.ruleset(&settings)),
+    };
+    action_queue.0.clear();
+    // dig and puzzle games start with a stack
+    refresh.send(RefreshBlocks);
+}
+
+/// Entities of the board, spawned again by a new game.
+type GameEntities = Or<(
+    With<GameDisplay>,
+    With<GameArea>,
+    With<Block>,
+    With<HintBlock>,
+)>;
+
+/// Throw the game away and start over at once when Restart is pressed, on a
+/// new seed or on the same one depending on the settings.
+pub fn restart_system(
+    mut commands: Commands,
+    input: ActionInput,
+    engine: Res<Engine>,
+    settings: Res<Settings>,
+    mut next_seed: ResMut<NextSeed>,
+    mut game_state: ResMut<NextState<GameState>>,
+    entities: Query<Entity, GameEntities>,
+) {
+    if !input.just_pressed(InputAction::Restart) {
+        return;
+    }
+    // the board is spawned again when entering `GameState::New`
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if settings.gameplay.same_seed {
+        next_seed.0 = Some(engine.seed);
+    }
+    game_state.set(GameState::New);
+}
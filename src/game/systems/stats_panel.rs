@@ -0,0 +1,59 @@
+//! Panel of the live stats, in the bottom left corner.
+
+use bevy::prelude::*;
+
+use crate::game::{
+    components::{GameDisplay, StatsPanel},
+    engine::Engine,
+    settings::Settings,
+    stats::Stats,
+    style::{get_game_text_style, TEXT_FONT_SIZE},
+};
+
+/// Size of the stats over the game text.
+const STATS_SCALE: f32 = 0.6;
+
+pub fn spawn_stats_panel_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = get_game_text_style(&asset_server);
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: TEXT_FONT_SIZE * STATS_SCALE,
+                    ..style
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        },
+        StatsPanel,
+        GameDisplay,
+    ));
+}
+
+/// Show the stats chosen in the settings, nothing if the panel is off.
+pub fn update_stats_panel_system(
+    stats: Res<Stats>,
+    engine: Res<Engine>,
+    settings: Res<Settings>,
+    mut query: Query<&mut Text, With<StatsPanel>>,
+) {
+    let value = match settings.stats.show {
+        true => stats.lines(&settings.stats.shown, engine.time()).join("\n"),
+        false => String::new(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Boards of a versus match, side by side.
+//!
+//! Each board is a grid of cell sprites redrawn from the engine of its player,
+//! with the hold piece on its left and the next piece on its right.
+
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::game::{
+    components::{GameDisplay, MatrixPosition, VersusArea, VersusCell, VersusStats},
+    engine::Engine,
+    global::{
+        get_versus_player_width, get_versus_window_min_size, BLOCK_SIZE, BLOCK_SPACE, FIELD_HEIGHT,
+        FIELD_WIDTH, MIN_LAYOUT_SCALE, VERSUS_MARGIN, VERSUS_PREVIEW_SCALE, VERSUS_SCALE,
+        VERSUS_TEXT_HEIGHT,
+    },
+    settings::Settings,
+    style::get_game_text_style,
+    systems::garbage_meter::spawn_garbage_meter,
+    tetromino::{Tetromino, TetrominoType},
+    theme::ActiveTheme,
+    versus::{VersusPlayer, PLAYERS},
+};
+
+/// Font size of the versus texts.
+const VERSUS_FONT_SIZE: f32 = 20.0;
+
+/// Distance between two cells of a board.
+fn cell_step() -> f32 {
+    (BLOCK_SIZE + BLOCK_SPACE) * VERSUS_SCALE
+}
+
+/// Top-left corner of `area` of player `index`.
+pub fn area_origin(index: usize, area: VersusArea) -> Vec2 {
+    let board_width = FIELD_WIDTH as f32 * cell_step();
+    let preview_width = 4.0 * cell_step() * VERSUS_PREVIEW_SCALE;
+    let center = (index as f32 - 0.5) * (get_versus_player_width() + 2.0 * VERSUS_MARGIN);
+    let top = FIELD_HEIGHT as f32 * cell_step() / 2.0;
+    let left = match area {
+        VersusArea::Board => center - board_width / 2.0,
+        VersusArea::Hold => center - board_width / 2.0 - VERSUS_MARGIN - preview_width,
+        VersusArea::Next => center + board_width / 2.0 + VERSUS_MARGIN,
+    };
+    Vec2::new(left, top)
+}
+
+/// Spawn the board, hold and next cells and the texts of both players.
+pub fn spawn_versus_layout_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<ActiveTheme>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let (min_width, min_height) = get_versus_window_min_size();
+    if let Ok(mut window) = window.get_single_mut() {
+        window.resize_constraints = WindowResizeConstraints {
+            min_width: min_width * MIN_LAYOUT_SCALE,
+            min_height: min_height * MIN_LAYOUT_SCALE,
+            ..Default::default()
+        };
+    }
+
+    let empty = theme.theme.empty;
+    let text_style = TextStyle {
+        font_size: VERSUS_FONT_SIZE,
+        ..get_game_text_style(&asset_server)
+    };
+    for player in 0..PLAYERS {
+        for (area, width, height, scale) in [
+            (VersusArea::Board, FIELD_WIDTH, FIELD_HEIGHT, 1.0),
+            (VersusArea::Hold, 4, 2, VERSUS_PREVIEW_SCALE),
+            (VersusArea::Next, 4, 2, VERSUS_PREVIEW_SCALE),
+        ] {
+            let origin = area_origin(player, area);
+            let step = cell_step() * scale;
+            for y in 0..height {
+                for x in 0..width {
+                    // the board shows its empty cells, the pieces beside it don't
+                    let visibility = match area {
+                        VersusArea::Board => Visibility::Inherited,
+                        _ => Visibility::Hidden,
+                    };
+                    commands.spawn((
+                        SpriteSheetBundle {
+                            sprite: TextureAtlasSprite {
+                                index: empty.index,
+                                color: empty.tint,
+                                custom_size: Some(Vec2::splat(BLOCK_SIZE * VERSUS_SCALE * scale)),
+                                anchor: bevy::sprite::Anchor::TopLeft,
+                                ..default()
+                            },
+                            texture_atlas: theme.atlas.clone(),
+                            transform: Transform::from_xyz(
+                                origin.x + x as f32 * step,
+                                origin.y - y as f32 * step,
+                                0.0,
+                            ),
+                            visibility,
+                            ..default()
+                        },
+                        VersusCell {
+                            player,
+                            area,
+                            position: MatrixPosition {
+                                x: x as i32,
+                                y: y as i32,
+                            },
+                            shown: None,
+                        },
+                        GameDisplay,
+                    ));
+                }
+            }
+        }
+
+        let board = area_origin(player, VersusArea::Board);
+        spawn_garbage_meter(
+            &mut commands,
+            Some(player),
+            board.x - VERSUS_MARGIN / 2.0,
+            board.y - FIELD_HEIGHT as f32 * cell_step(),
+            VERSUS_MARGIN / 2.0,
+            cell_step(),
+        );
+        let center = board.x + FIELD_WIDTH as f32 * cell_step() / 2.0;
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(format!("Player {}", player + 1), text_style.clone())
+                    .with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(center, board.y + VERSUS_TEXT_HEIGHT / 2.0, 0.0),
+                ..default()
+            },
+            GameDisplay,
+        ));
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section("", text_style.clone())
+                    .with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(center, -board.y - VERSUS_TEXT_HEIGHT / 2.0, 0.0),
+                ..default()
+            },
+            VersusStats(player),
+            GameDisplay,
+        ));
+    }
+}
+
+/// Occupation and ghost flag of each cell of `area`, row by row.
+fn area_cells(engine: &Engine, area: VersusArea, ghost_piece: bool) -> Vec<Option<(u8, bool)>> {
+    let piece = |ty: Option<TetrominoType>| {
+        let mut cells = vec![None; 4 * 2];
+        if let Some(ty) = ty {
+            for p in Tetromino::new(ty).get_blocks_position() {
+                cells[p.x as usize + p.y as usize * 4] = Some((ty as u8 + 1, false));
+            }
+        }
+        cells
+    };
+    match area {
+        VersusArea::Hold => piece(engine.hold),
+        VersusArea::Next => piece(engine.queue.values.front().map(|t| t.ty)),
+        VersusArea::Board => {
+            let matrix = &engine.matrix;
+            let mut cells: Vec<_> = matrix
+                .occupation
+                .iter()
+                .map(|c| (*c != 0).then_some((*c, false)))
+                .collect();
+            if let Some(tetromino) = engine.current.as_ref() {
+                let value = tetromino.ty as u8 + 1;
+                if ghost_piece {
+                    for p in engine.ghost_blocks() {
+                        cells[matrix.get_index(&p)].get_or_insert((value, true));
+                    }
+                }
+                for p in engine.current_blocks() {
+                    cells[matrix.get_index(&p)] = Some((value, false));
+                }
+            }
+            cells
+        }
+    }
+}
+
+/// Redraw the cells which changed and the texts of each player.
+pub fn update_versus_board_system(
+    players: Query<(&VersusPlayer, &Engine)>,
+    mut cells: Query<(
+        &mut VersusCell,
+        &mut Handle<TextureAtlas>,
+        &mut TextureAtlasSprite,
+        &mut Visibility,
+    )>,
+    mut stats: Query<(&VersusStats, &mut Text)>,
+    theme: Res<ActiveTheme>,
+    settings: Res<Settings>,
+) {
+    let mut shown = vec![vec![]; PLAYERS];
+    for (player, engine) in players.iter() {
+        shown[player.index] = [VersusArea::Board, VersusArea::Hold, VersusArea::Next]
+            .map(|area| area_cells(engine, area, settings.display.ghost_piece))
+            .to_vec();
+        for (VersusStats(index), mut text) in stats.iter_mut() {
+            if *index == player.index {
+                text.sections[0].value = format!(
+                    "Lines {}  Sent {}\nIncoming {}",
+                    engine.total_lines,
+                    player.garbage.sent,
+                    player.garbage.pending().0
+                );
+            }
+        }
+    }
+
+    for (mut cell, mut atlas, mut sprite, mut visibility) in cells.iter_mut() {
+        let Some(areas) = shown.get(cell.player).filter(|areas| !areas.is_empty()) else {
+            continue;
+        };
+        let (index, width) = match cell.area {
+            VersusArea::Board => (0, FIELD_WIDTH),
+            VersusArea::Hold => (1, 4),
+            VersusArea::Next => (2, 4),
+        };
+        let look = areas[index][cell.position.x as usize + cell.position.y as usize * width];
+        if cell.shown == look && !settings.is_changed() && !theme.is_changed() {
+            continue;
+        }
+        cell.shown = look;
+        let (value, ghost) = look.unwrap_or((0, false));
+        let tile = theme.theme.cell(value, ghost);
+        *atlas = theme.atlas.clone();
+        sprite.index = tile.index;
+        sprite.color = tile.tint;
+        *visibility = match (look, cell.area) {
+            (None, VersusArea::Hold | VersusArea::Next) => Visibility::Hidden,
+            _ => Visibility::Inherited,
+        };
+    }
+}
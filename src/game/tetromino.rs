@@ -170,7 +170,7 @@ impl Distribution<Rotation> for Standard {
     }
 }
 
-#[derive(Clone, Component)]
+#[derive(Debug, Clone, Component)]
 pub struct Tetromino {
     pub ty: TetrominoType,
     pub rotation: Rotation,
@@ -178,9 +178,8 @@ pub struct Tetromino {
 }
 
 impl Tetromino {
-    /// Create a new Tetromino
-    pub fn new() -> Tetromino {
-        let ty: TetrominoType = rand::random();
+    /// Create a new Tetromino of the given type in spawn rotation.
+    pub fn new(ty: TetrominoType) -> Tetromino {
         let _offset_data = match ty {
             TetrominoType::I => OFFSET_DATA_I.to_vec(),
             TetrominoType::O => OFFSET_DATA_O.to_vec(),
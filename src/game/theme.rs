@@ -0,0 +1,294 @@
+//! Block themes, loaded from the `.theme.ron` files of `assets/themes`.
+//!
+//! A theme draws every block from one texture atlas: each piece, the ghost,
+//! the garbage and the empty cells are a tile of the atlas with a tint. It
+//! also picks the image behind the side panels and the colors of the board.
+//! The theme named in the settings is applied as soon as it is loaded or
+//! selected, without restarting the game.
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engine::GARBAGE_CELL, global::BLOCK_SIZE, settings::Settings, systems::minos::RefreshBlocks,
+    tetromino::TetrominoType,
+};
+
+/// Folder of the theme files, in the assets.
+const THEMES_FOLDER: &str = "themes";
+/// Opacity of the ghost piece of themes without a ghost tile.
+pub const GHOST_ALPHA: f32 = 0.3;
+
+fn white() -> Color {
+    Color::WHITE
+}
+
+/// Tile `index` of the atlas, multiplied by `tint`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tile {
+    pub index: usize,
+    #[serde(default = "white")]
+    pub tint: Color,
+}
+
+/// Image holding the tiles, `columns` by `rows` tiles of `tile_size` pixels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Atlas {
+    pub path: String,
+    pub tile_size: f32,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct Pieces {
+    pub i: Tile,
+    pub o: Tile,
+    pub t: Tile,
+    pub s: Tile,
+    pub z: Tile,
+    pub j: Tile,
+    pub l: Tile,
+}
+
+impl Pieces {
+    pub fn get(&self, ty: TetrominoType) -> Tile {
+        match ty {
+            TetrominoType::I => self.i,
+            TetrominoType::O => self.o,
+            TetrominoType::T => self.t,
+            TetrominoType::S => self.s,
+            TetrominoType::Z => self.z,
+            TetrominoType::J => self.j,
+            TetrominoType::L => self.l,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    /// Behind everything.
+    pub clear: Color,
+    pub board: Color,
+    pub border: Color,
+    /// Tint of the side panels.
+    pub panel: Color,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5d0c3a4e-7f52-4b6a-9a0e-2c8f6b1d4e73"]
+pub struct Theme {
+    pub name: String,
+    pub atlas: Atlas,
+    pub pieces: Pieces,
+    /// `None` draws the ghost with the tile of its piece, at `GHOST_ALPHA`.
+    #[serde(default)]
+    pub ghost: Option<Tile>,
+    pub garbage: Tile,
+    /// Empty cells of the board.
+    pub empty: Tile,
+    /// Image behind the side panels, in the assets.
+    #[serde(default)]
+    pub background: Option<String>,
+    pub colors: Palette,
+}
+
+impl Default for Theme {
+    /// The classic theme, used until the theme files are loaded.
+    fn default() -> Self {
+        let tile = |index| Tile {
+            index,
+            tint: Color::WHITE,
+        };
+        Theme {
+            name: "Classic".to_string(),
+            atlas: Atlas {
+                path: "themes/blocks.png".to_string(),
+                tile_size: BLOCK_SIZE,
+                columns: 9,
+                rows: 1,
+            },
+            pieces: Pieces {
+                i: tile(0),
+                o: tile(1),
+                t: tile(2),
+                s: tile(3),
+                z: tile(4),
+                j: tile(5),
+                l: tile(6),
+            },
+            ghost: None,
+            garbage: Tile {
+                index: 7,
+                tint: Color::rgb(0.45, 0.45, 0.45),
+            },
+            empty: tile(8),
+            background: Some("bg.png".to_string()),
+            colors: Palette {
+                clear: Color::GRAY,
+                board: Color::DARK_GRAY,
+                border: Color::DARK_GRAY,
+                panel: Color::WHITE,
+            },
+        }
+    }
+}
+
+impl Theme {
+    /// Tile of a cell of the matrix, `0` for an empty one.
+    pub fn cell(&self, value: u8, ghost: bool) -> Tile {
+        match value {
+            0 => self.empty,
+            GARBAGE_CELL => self.garbage,
+            value => {
+                let piece = self.pieces.get(TetrominoType::from(value as i32 - 1));
+                match (ghost, self.ghost) {
+                    (false, _) => piece,
+                    (true, Some(tile)) => tile,
+                    (true, None) => {
+                        let mut tint = piece.tint;
+                        tint.set_a(tint.a() * GHOST_ALPHA);
+                        Tile { tint, ..piece }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let theme: Theme = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(theme));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+/// Handles of the files of the themes folder, which keep them loaded.
+#[derive(Resource, Default)]
+pub struct Themes(Vec<HandleUntyped>);
+
+impl Themes {
+    /// Name of the theme `step` themes away from the one of the settings.
+    pub fn cycle(&self, themes: &Assets<Theme>, current: &str, step: i32) -> String {
+        let mut names: Vec<String> = themes.iter().map(|(_, theme)| theme.name.clone()).collect();
+        let default = Theme::default().name;
+        if !names.contains(&default) {
+            names.push(default);
+        }
+        names.sort();
+        let index = names.iter().position(|n| n == current).unwrap_or(0) as i32;
+        names[(index + step).rem_euclid(names.len() as i32) as usize].clone()
+    }
+}
+
+/// The theme in use and its atlas.
+#[derive(Resource, Default)]
+pub struct ActiveTheme {
+    pub theme: Theme,
+    pub atlas: Handle<TextureAtlas>,
+}
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_resource::<Themes>()
+            .init_resource::<ActiveTheme>()
+            .add_startup_system(load_themes)
+            .add_system(apply_theme_system);
+    }
+}
+
+fn load_themes(mut themes: ResMut<Themes>, asset_server: Res<AssetServer>) {
+    match asset_server.load_folder(THEMES_FOLDER) {
+        Ok(handles) => themes.0 = handles,
+        Err(e) => warn!("failed to load the themes: {}", e),
+    }
+}
+
+/// Switch to the theme of the settings once it is loaded, and whenever it is
+/// selected or its file changes.
+#[allow(clippy::too_many_arguments)]
+fn apply_theme_system(
+    mut active: ResMut<ActiveTheme>,
+    mut events: EventReader<AssetEvent<Theme>>,
+    mut refresh: EventWriter<RefreshBlocks>,
+    mut clear_color: ResMut<ClearColor>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    themes: Res<Assets<Theme>>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
+    let loaded = events.iter().count() > 0;
+    if !loaded && !settings.is_changed() && active.atlas != Handle::default() {
+        return;
+    }
+    let theme = themes
+        .iter()
+        .map(|(_, theme)| theme)
+        .find(|theme| theme.name == settings.display.theme)
+        .cloned()
+        .unwrap_or_default();
+    if theme == active.theme && active.atlas != Handle::default() {
+        return;
+    }
+    let atlas = &theme.atlas;
+    active.atlas = atlases.add(TextureAtlas::from_grid(
+        asset_server.load(atlas.path.as_str()),
+        Vec2::splat(atlas.tile_size),
+        atlas.columns,
+        atlas.rows,
+        None,
+        None,
+    ));
+    clear_color.0 = theme.colors.clear;
+    active.theme = theme;
+    refresh.send(RefreshBlocks);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Theme, GHOST_ALPHA};
+    use crate::game::{engine::GARBAGE_CELL, tetromino::TetrominoType};
+
+    #[test]
+    fn test_classic_theme_file_is_the_default() {
+        let theme: Theme =
+            ron::from_str(include_str!("../../assets/themes/classic.theme.ron")).unwrap();
+        assert_eq!(theme, Theme::default());
+    }
+
+    #[test]
+    fn test_cells_get_their_tiles() {
+        let theme = Theme::default();
+        assert_eq!(theme.cell(0, false), theme.empty);
+        assert_eq!(theme.cell(GARBAGE_CELL, false), theme.garbage);
+        let t = TetrominoType::T as u8 + 1;
+        assert_eq!(theme.cell(t, false), theme.pieces.t);
+        let ghost = theme.cell(t, true);
+        assert_eq!(ghost.index, theme.pieces.t.index);
+        assert_eq!(ghost.tint.a(), GHOST_ALPHA);
+    }
+}
@@ -0,0 +1,283 @@
+//! Local two-player versus.
+//!
+//! Each player is an entity holding its own `Engine`, pending actions and
+//! garbage, and plays with its own keys (see `VersusSettings`) or gamepad.
+//! Both engines start from the same seed so the players get the same pieces.
+//! Clearing lines sends garbage to the opponent (see `garbage`), which rises
+//! under its stack the next time it locks a piece without clearing a line.
+//! The last player standing wins.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::{
+    components::GameDisplay,
+    engine::{Engine, Ruleset},
+    garbage::{GarbageSettings, GarbageState},
+    input::{queue_actions, ActionEvent, ActionInput, InputAction},
+    net::NetMatch,
+    settings::Settings,
+    GameState,
+};
+
+/// Players of a versus match.
+pub const PLAYERS: usize = 2;
+
+/// The running versus match.
+#[derive(Resource, Clone)]
+pub struct VersusMatch {
+    pub active: bool,
+    /// Index of the winner once the match is over, `None` for a draw.
+    pub winner: Option<usize>,
+    rules: GarbageSettings,
+    /// Picks the hole columns of the garbage.
+    rng: StdRng,
+}
+
+impl Default for VersusMatch {
+    fn default() -> Self {
+        VersusMatch {
+            active: false,
+            winner: None,
+            rules: GarbageSettings::default(),
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl VersusMatch {
+    pub fn new() -> Self {
+        VersusMatch {
+            active: true,
+            ..Default::default()
+        }
+    }
+
+    /// A match whose garbage holes are picked from `seed`.
+    pub fn seeded(seed: u64, rules: GarbageSettings) -> Self {
+        VersusMatch {
+            active: true,
+            winner: None,
+            rules,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// A player of the versus match, next to its `Engine`.
+#[derive(Component, Default)]
+pub struct VersusPlayer {
+    pub index: usize,
+    /// Actions collected since the last fixed frame.
+    pub actions: Vec<ActionEvent>,
+    pub garbage: GarbageState,
+}
+
+pub fn is_versus(versus: Res<VersusMatch>) -> bool {
+    versus.active
+}
+
+pub fn is_not_versus(versus: Res<VersusMatch>) -> bool {
+    !versus.active
+}
+
+/// Spawn the players of a new match, both on the same fresh seed, or on the
+/// one of the server with the handling of each player when playing online.
+pub fn start_versus(
+    mut commands: Commands,
+    mut versus: ResMut<VersusMatch>,
+    settings: Res<Settings>,
+    net: Res<NetMatch>,
+) {
+    let (seed, rules) = match net.active {
+        true => (net.seed, net.rules),
+        false => (rand::random(), settings.garbage),
+    };
+    *versus = VersusMatch::seeded(seed, rules);
+    for index in 0..PLAYERS {
+        let ruleset = Ruleset {
+            handling: match net.active {
+                true => net.handling[index],
+                false => settings.handling,
+            },
+            ..Ruleset::default()
+        };
+        commands.spawn((
+            VersusPlayer {
+                index,
+                ..Default::default()
+            },
+            Engine::new(seed, ruleset),
+            GameDisplay,
+        ));
+    }
+}
+
+/// Translate the keys and gamepad of each player into its actions. Online, the
+/// local player plays with the single player controls.
+pub fn versus_input_system(
+    input: ActionInput,
+    mut held: Local<[HashSet<InputAction>; PLAYERS]>,
+    mut players: Query<&mut VersusPlayer>,
+    net: Res<NetMatch>,
+) {
+    for mut player in players.iter_mut() {
+        let index = player.index;
+        if net.active {
+            if index == net.player {
+                queue_actions(
+                    &mut held[index],
+                    &mut player.actions,
+                    |action| input.just_pressed(action),
+                    |action| input.pressed(action),
+                );
+            }
+            continue;
+        }
+        queue_actions(
+            &mut held[index],
+            &mut player.actions,
+            |action| input.player_just_pressed(index, action),
+            |action| input.player_pressed(index, action),
+        );
+    }
+}
+
+/// Step both engines by one frame, exchange garbage and end the match when a
+/// player tops out.
+pub fn versus_step_system(
+    mut versus: ResMut<VersusMatch>,
+    mut players: Query<(&mut VersusPlayer, &mut Engine)>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let mut players: Vec<_> = players.iter_mut().collect();
+    players.sort_by_key(|(player, _)| player.index);
+    let mut players: Vec<_> = players
+        .iter_mut()
+        .map(|(player, engine)| (player.as_mut(), engine.as_mut()))
+        .collect();
+    if versus.step(&mut players) {
+        game_state.set(GameState::Over);
+    }
+}
+
+impl VersusMatch {
+    /// Step the engine of each player, sorted by index, with its actions and
+    /// exchange the garbage. Return whether the match is over.
+    pub fn step(&mut self, players: &mut [(&mut VersusPlayer, &mut Engine)]) -> bool {
+        let mut attacks = [0; PLAYERS];
+        for (player, engine) in players.iter_mut() {
+            let actions = std::mem::take(&mut player.actions);
+            engine.step(&actions);
+            let events = engine.drain_events();
+            attacks[player.index] =
+                player
+                    .garbage
+                    .update(engine, &events, &self.rules, &mut self.rng);
+        }
+        for (index, lines) in attacks.into_iter().enumerate() {
+            let (opponent, engine) = &mut players[(index + 1) % PLAYERS];
+            let width = engine.matrix.field_width;
+            opponent
+                .garbage
+                .receive(lines, width, &self.rules, &mut self.rng);
+        }
+
+        let standing: Vec<usize> = players
+            .iter()
+            .filter(|(_, engine)| !engine.is_over())
+            .map(|(player, _)| player.index)
+            .collect();
+        if standing.len() == PLAYERS {
+            return false;
+        }
+        self.winner = standing.first().copied();
+        true
+    }
+}
+
+/// Back to single player when leaving the game.
+pub fn stop_versus(mut versus: ResMut<VersusMatch>) {
+    *versus = VersusMatch::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VersusMatch, VersusPlayer};
+    use crate::game::{
+        engine::{Engine, Ruleset, GARBAGE_CELL},
+        garbage::GarbageSettings,
+        input::{ActionEvent, InputAction},
+        tetromino::{Tetromino, TetrominoType},
+    };
+
+    fn player(index: usize) -> (VersusPlayer, Engine) {
+        let mut engine = Engine::new(5, Ruleset::default());
+        engine.step(&[]);
+        let player = VersusPlayer {
+            index,
+            ..Default::default()
+        };
+        (player, engine)
+    }
+
+    #[test]
+    fn test_cleared_lines_send_garbage() {
+        let mut versus = VersusMatch {
+            rules: GarbageSettings {
+                delay: 0,
+                ..Default::default()
+            },
+            ..VersusMatch::new()
+        };
+        let (mut first, mut first_engine) = player(0);
+        let (mut second, mut second_engine) = player(1);
+        // an O piece dropped in the gap of the two bottom rows clears them
+        let width = first_engine.matrix.field_width;
+        let len = first_engine.matrix.occupation.len();
+        for (index, cell) in first_engine.matrix.occupation[len - 2 * width..]
+            .iter_mut()
+            .enumerate()
+        {
+            *cell = u8::from(!matches!(index % width, 4 | 5));
+        }
+        // a block left above so that it isn't a perfect clear
+        first_engine.matrix.occupation[len - 3 * width] = 1;
+        first_engine.current = Some(Tetromino::new(TetrominoType::O));
+
+        first.actions = vec![ActionEvent::press(InputAction::HardDrop)];
+        let mut players = [
+            (&mut first, &mut first_engine),
+            (&mut second, &mut second_engine),
+        ];
+        assert!(!versus.step(&mut players));
+        assert_eq!(players[0].0.garbage.sent, 1);
+        assert_eq!(players[1].0.garbage.pending(), (1, 1));
+
+        // the garbage rises when the opponent locks without clearing
+        players[1].0.actions = vec![ActionEvent::press(InputAction::HardDrop)];
+        versus.step(&mut players);
+        assert_eq!(players[1].0.garbage.pending(), (0, 0));
+        let bottom = &players[1].1.matrix.occupation[len - width..];
+        assert_eq!(
+            bottom.iter().filter(|c| **c == GARBAGE_CELL).count(),
+            width - 1
+        );
+    }
+
+    #[test]
+    fn test_last_player_standing_wins() {
+        let mut versus = VersusMatch::new();
+        let (mut first, mut first_engine) = player(0);
+        let (mut second, mut second_engine) = player(1);
+        second_engine.add_garbage(second_engine.matrix.field_height, 0);
+        let mut players = [
+            (&mut first, &mut first_engine),
+            (&mut second, &mut second_engine),
+        ];
+        assert!(versus.step(&mut players));
+        assert_eq!(versus.winner, Some(0));
+    }
+}
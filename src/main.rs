@@ -1,30 +1,69 @@
+mod autoplay;
 mod game;
+mod server;
 mod ui;
+mod verify;
 
-use crate::game::{resources::ImageLoadPlugin, GamePlugin, GameState};
+use std::path::Path;
+use std::process::ExitCode;
+
+use crate::game::{
+    settings::{get_settings_path, Settings},
+    spectator::{Spectator, DEFAULT_FEED_PORT},
+    theme::ThemePlugin,
+    GamePlugin, GameState,
+};
 use crate::ui::*;
 use bevy::prelude::*;
 use bevy::window::exit_on_primary_closed;
 
-fn main() {
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("verify") => {
+            return match args.get(2) {
+                Some(path) => verify::run(Path::new(path)),
+                None => {
+                    eprintln!("usage: tetris verify <replay>");
+                    ExitCode::from(2)
+                }
+            };
+        }
+        Some("ai") => return autoplay::run(&args[2..]),
+        Some("server") => return server::run(&args[2..]),
+        _ => {}
+    }
+    let spectator = match args.get(1).map(String::as_str) {
+        Some("spectate") => {
+            let default = format!("127.0.0.1:{}", DEFAULT_FEED_PORT);
+            Spectator::watch(args.get(2).unwrap_or(&default))
+        }
+        _ => Spectator::default(),
+    };
+
+    let settings = Settings::load(&get_settings_path());
     App::new()
         .insert_resource(ClearColor(Color::GRAY))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Tetris".to_string(),
+                mode: settings.display.window_mode.into(),
                 ..default()
             }),
             ..default()
         }))
+        .insert_resource(settings)
+        .insert_resource(spectator)
         .add_state::<AppState>()
         .add_state::<GameState>()
         .add_plugin(game::timer::TimerPlugin)
         .add_plugin(MainMenuPlugin)
         .add_plugin(GamePlugin)
-        .add_plugin(ImageLoadPlugin)
+        .add_plugin(ThemePlugin)
         .add_startup_system(setup)
         .add_system(exit_on_primary_closed)
         .run();
+    ExitCode::SUCCESS
 }
 
 pub fn setup(mut commands: Commands) {
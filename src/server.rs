@@ -0,0 +1,279 @@
+//! Headless versus server: `tetris server [--port N] [--matches N]`.
+//!
+//! Pairs the first two clients which say hello with the current protocol
+//! version, sends both the seed of the match and the handling of each player,
+//! then relays their inputs to each other. Both clients simulate the whole
+//! match, so the server only waits for one of them to report the result, or to
+//! leave, which loses the match. Matches are played one after the other;
+//! clients connecting meanwhile wait for the next one. The garbage rules are
+//! the `[garbage]` section of the settings file.
+
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener},
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
+
+use crate::game::{
+    engine::Handling,
+    garbage::GarbageSettings,
+    net::{
+        connection::Connection,
+        protocol::{Message, DEFAULT_PORT, PROTOCOL_VERSION},
+    },
+    settings::{get_settings_path, Settings},
+    versus::PLAYERS,
+};
+
+/// Pause of the server loop when nothing arrived.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Wait for two players, then relay their messages until the match is over.
+/// Return the index of the winner, `None` for a draw.
+pub fn serve_match(
+    listener: &TcpListener,
+    seed: u64,
+    garbage: GarbageSettings,
+) -> io::Result<Option<usize>> {
+    listener.set_nonblocking(true)?;
+    let mut greeting: Vec<Connection> = vec![];
+    let mut players: Vec<Connection> = vec![];
+    let mut handling = [Handling::default(); PLAYERS];
+    while players.len() < PLAYERS {
+        let mut idle = true;
+        match listener.accept() {
+            Ok((stream, _)) => {
+                greeting.push(Connection::new(stream)?);
+                idle = false;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        let mut index = 0;
+        while index < greeting.len() && players.len() < PLAYERS {
+            let hello = match greeting[index].poll() {
+                Ok(messages) if messages.is_empty() => {
+                    index += 1;
+                    continue;
+                }
+                Ok(messages) => messages.into_iter().next(),
+                Err(_) => None,
+            };
+            idle = false;
+            let mut client = greeting.remove(index);
+            match hello {
+                Some(Message::Hello {
+                    version,
+                    handling: played,
+                }) if version == PROTOCOL_VERSION => {
+                    let player = players.len();
+                    if client
+                        .send(&Message::Welcome {
+                            player: player as u8,
+                        })
+                        .is_ok()
+                    {
+                        handling[player] = played;
+                        players.push(client);
+                    }
+                }
+                Some(Message::Hello { version, .. }) => {
+                    let reason = format!(
+                        "protocol version {} expected, got {}",
+                        PROTOCOL_VERSION, version
+                    );
+                    client.send(&Message::Reject { reason }).ok();
+                    client.close().ok();
+                }
+                _ => {}
+            }
+        }
+        if idle {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    let start = Message::Start {
+        seed,
+        garbage,
+        handling,
+    };
+    let mut left = [false; PLAYERS];
+    for (index, player) in players.iter_mut().enumerate() {
+        left[index] = player.send(&start).is_err();
+    }
+    let mut reported = None;
+    while reported.is_none() && !left.contains(&true) {
+        let mut idle = true;
+        for index in 0..PLAYERS {
+            let messages = match players[index].poll() {
+                Ok(messages) => messages,
+                Err(_) => {
+                    left[index] = true;
+                    continue;
+                }
+            };
+            for message in messages {
+                idle = false;
+                match message {
+                    Message::Inputs { .. } => {
+                        // a failing opponent is noticed when it is polled
+                        players[(index + 1) % PLAYERS].send(&message).ok();
+                    }
+                    Message::Result { winner } => {
+                        let winner = winner.map(usize::from).filter(|w| *w < PLAYERS);
+                        reported.get_or_insert(winner);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if idle {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // leaving the match loses it
+    let winner = reported.unwrap_or(match left {
+        [false, true] => Some(0),
+        [true, false] => Some(1),
+        _ => None,
+    });
+    let result = Message::Result {
+        winner: winner.map(|w| w as u8),
+    };
+    for mut player in players {
+        if player.send(&result).is_ok() {
+            player.close().ok();
+        }
+    }
+    Ok(winner)
+}
+
+pub fn run(args: &[String]) -> ExitCode {
+    let mut port = DEFAULT_PORT;
+    let mut matches = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|v| v.parse::<u64>().ok());
+        match (arg.as_str(), value) {
+            ("--port", Some(n)) if n <= u16::MAX as u64 => port = n as u16,
+            ("--matches", Some(n)) => matches = Some(n),
+            _ => {
+                eprintln!("usage: tetris server [--port N] [--matches N]");
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let listener = match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("failed to listen on port {}: {}", port, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let garbage = Settings::load(&get_settings_path()).garbage;
+    println!("listening on port {}", port);
+    let mut played = 0;
+    while matches.is_none_or(|matches| played < matches) {
+        let seed = rand::random();
+        match serve_match(&listener, seed, garbage) {
+            Ok(Some(winner)) => println!("match {}: player {} wins", played + 1, winner + 1),
+            Ok(None) => println!("match {}: draw", played + 1),
+            Err(e) => {
+                eprintln!("server failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        played += 1;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::serve_match;
+    use crate::game::{
+        engine::Handling,
+        garbage::GarbageSettings,
+        input::{ActionEvent, InputAction},
+        net::{
+            connection::Connection,
+            protocol::{Message, PROTOCOL_VERSION},
+        },
+    };
+
+    /// Poll `connection` until `count` messages arrived.
+    fn receive(connection: &mut Connection, count: usize) -> Vec<Message> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut messages = vec![];
+        while messages.len() < count {
+            assert!(Instant::now() < deadline, "got {:?}", messages);
+            messages.extend(connection.poll().unwrap());
+            thread::sleep(Duration::from_millis(1));
+        }
+        messages
+    }
+
+    fn join(address: &str, version: u16, handling: Handling) -> Connection {
+        let mut client = Connection::connect(address).unwrap();
+        client.send(&Message::Hello { version, handling }).unwrap();
+        client
+    }
+
+    #[test]
+    fn test_match_on_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server =
+            thread::spawn(move || serve_match(&listener, 42, GarbageSettings::default()).unwrap());
+
+        let fast = Handling {
+            das: 6,
+            arr: 0,
+            sdf: 40,
+        };
+        let mut outdated = join(&address, PROTOCOL_VERSION + 1, fast);
+        assert!(matches!(
+            receive(&mut outdated, 1)[0],
+            Message::Reject { .. }
+        ));
+
+        let mut first = join(&address, PROTOCOL_VERSION, Handling::default());
+        assert_eq!(receive(&mut first, 1), [Message::Welcome { player: 0 }]);
+        let mut second = join(&address, PROTOCOL_VERSION, fast);
+        let start = Message::Start {
+            seed: 42,
+            garbage: GarbageSettings::default(),
+            handling: [Handling::default(), fast],
+        };
+        assert_eq!(
+            receive(&mut second, 2),
+            [Message::Welcome { player: 1 }, start.clone()]
+        );
+        assert_eq!(receive(&mut first, 1), [start]);
+
+        // the inputs of a player reach the other one
+        let inputs = Message::Inputs {
+            frame: 1,
+            actions: vec![ActionEvent::press(InputAction::HardDrop)],
+        };
+        first.send(&inputs).unwrap();
+        assert_eq!(receive(&mut second, 1), [inputs]);
+
+        second.send(&Message::Result { winner: Some(0) }).unwrap();
+        for client in [&mut first, &mut second] {
+            assert_eq!(receive(client, 1), [Message::Result { winner: Some(0) }]);
+        }
+        assert_eq!(server.join().unwrap(), Some(0));
+    }
+}
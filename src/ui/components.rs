@@ -1,10 +1,189 @@
-use bevy::prelude::Component;
+use std::path::PathBuf;
+
+use bevy::prelude::{Component, Entity, GamepadButtonType, KeyCode, Resource, States};
+
+use crate::game::{config::GameOption, input::InputAction, settings::Setting};
 
 #[derive(Component)]
 pub(crate) struct MainMenu;
 
+#[derive(Component)]
+pub(crate) struct NewGameMenu;
+
+#[derive(Component)]
+pub(crate) struct ReplaysMenu;
+
+#[derive(Component)]
+pub(crate) struct HighScoresMenu;
+
+#[derive(Component)]
+pub(crate) struct SettingsMenu;
+
+#[derive(Component)]
+pub(crate) struct VersusMenu;
+
+/// Text showing the address of the LAN server, edited by typing.
+#[derive(Component)]
+pub(crate) struct ServerAddressText;
+
+/// Text telling where joining the LAN server stands.
+#[derive(Component)]
+pub(crate) struct JoinStatusText;
+
+/// Text showing the value of a setting.
+#[derive(Component)]
+pub(crate) struct SettingValue(pub Setting);
+
+/// Text showing the value of an option of the next game.
+#[derive(Component)]
+pub(crate) struct GameOptionValue(pub GameOption);
+
+/// Text describing the value of an option of the next game.
+#[derive(Component)]
+pub(crate) struct GameOptionDescription(pub GameOption);
+
+/// Text showing the keys or buttons bound to an action.
+#[derive(Component)]
+pub(crate) struct BindingText(pub InputAction);
+
+/// Text of the button switching between keyboard and gamepad bindings.
+#[derive(Component)]
+pub(crate) struct BindingDeviceText;
+
+/// Text asking for the key to bind.
+#[derive(Component)]
+pub(crate) struct BindingPrompt;
+
+/// Button activated by the menu "back" input.
+#[derive(Component)]
+pub(crate) struct BackButton;
+
+/// Bindings shown and changed in the settings menu.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum BindingDevice {
+    #[default]
+    Keyboard,
+    Gamepad,
+}
+
+/// A key or gamepad button to bind.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum BoundInput {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+}
+
+impl BoundInput {
+    pub fn name(&self) -> String {
+        match self {
+            BoundInput::Key(key) => format!("{:?}", key),
+            BoundInput::Button(button) => format!("{:?}", button),
+        }
+    }
+}
+
+/// Action waiting for a key in the settings menu.
+#[derive(Resource, Default)]
+pub(crate) struct Rebinding {
+    pub device: BindingDevice,
+    pub action: Option<InputAction>,
+    /// Input already bound to another action, pressed once.
+    pub conflict: Option<(BoundInput, InputAction)>,
+}
+
+/// Button focused by gamepad or keyboard menu navigation.
+#[derive(Resource, Default)]
+pub(crate) struct MenuFocus {
+    pub entity: Option<Entity>,
+    /// Button clicked through navigation, released at the end of the frame.
+    pub activated: Option<Entity>,
+}
+
+/// Menu input from a gamepad or the keyboard.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum MenuNavigation {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Next button in reading order.
+    Next,
+    Previous,
+    Activate,
+    Back,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Component)]
 pub(crate) enum MainMenuButtonAction {
-    Play,
+    NewGame,
+    Versus,
+    WatchAi,
+    Replays,
+    HighScores,
+    Settings,
     Exit,
 }
+
+#[derive(Clone, Copy, PartialEq, Eq, Component)]
+pub(crate) enum NewGameMenuButtonAction {
+    Decrease(GameOption),
+    Increase(GameOption),
+    Start,
+    Back,
+}
+
+#[derive(Clone, PartialEq, Eq, Component)]
+pub(crate) enum ReplaysMenuButtonAction {
+    Watch(PathBuf),
+    Back,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Component)]
+pub(crate) enum VersusMenuButtonAction {
+    Local,
+    Practice,
+    Join,
+    Back,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Component)]
+pub(crate) enum HighScoresMenuButtonAction {
+    Previous,
+    Next,
+    Back,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Component)]
+pub(crate) enum SettingsMenuButtonAction {
+    Decrease(Setting),
+    Increase(Setting),
+    Bind(InputAction),
+    Unbind(InputAction),
+    SwitchDevice,
+    Back,
+}
+
+/// Index of the variant shown in the high scores screen.
+#[derive(Resource, Default)]
+pub(crate) struct SelectedVariant(pub usize);
+
+/// Screens of the main menu.
+#[derive(States, Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub(crate) enum MenuState {
+    Main,
+    NewGame,
+    Versus,
+    Replays,
+    HighScores,
+    Settings,
+    #[default]
+    Disabled,
+}
+
+/// Whether the settings menu is shown, over the main menu or the paused game.
+#[derive(States, Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub(crate) enum SettingsState {
+    #[default]
+    Closed,
+    Open,
+}
@@ -2,21 +2,124 @@ pub(crate) mod components;
 pub(crate) mod style;
 pub(crate) mod systems;
 
+use crate::game::net::lobby_system;
+use crate::game::settings::save_settings;
 use crate::ui::systems::interactions::*;
-use crate::ui::systems::layout::spawn_main_menu_system;
+use crate::ui::systems::layout::{
+    spawn_high_scores_menu_system, spawn_main_menu_system, spawn_new_game_menu_system,
+    spawn_replays_menu_system, spawn_settings_menu_system, spawn_versus_menu_system,
+};
+use crate::ui::systems::navigation::{
+    gamepad_navigation_system, keyboard_navigation_system, navigate_focus_system,
+    release_activated_button,
+};
 use crate::{despawn_components, AppState};
-use bevy::prelude::{IntoSystemAppConfig, IntoSystemConfigs, OnEnter, OnExit, OnUpdate, Plugin};
+use bevy::prelude::{
+    CoreSet, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfig, IntoSystemConfigs,
+    OnEnter, OnExit, OnUpdate, Plugin,
+};
+use bevy::ui::UiSystem;
 
-use self::components::MainMenu;
+use self::components::{
+    HighScoresMenu, MainMenu, MenuFocus, MenuNavigation, MenuState, NewGameMenu, Rebinding,
+    ReplaysMenu, SelectedVariant, SettingsMenu, SettingsState, VersusMenu,
+};
 
 pub struct MainMenuPlugin;
 
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(spawn_main_menu_system.in_schedule(OnEnter(AppState::MainMenu)));
-        app.add_system(despawn_components::<MainMenu>.in_schedule(OnExit(AppState::MainMenu)));
+        app.add_state::<MenuState>();
+        app.add_state::<SettingsState>();
+        app.init_resource::<Rebinding>();
+        app.init_resource::<SelectedVariant>();
+        app.init_resource::<MenuFocus>();
+        app.add_event::<MenuNavigation>();
+        app.add_system(enter_main_menu.in_schedule(OnEnter(AppState::MainMenu)));
+        app.add_system(leave_main_menu.in_schedule(OnExit(AppState::MainMenu)));
+        app.add_system(button_system.in_set(OnUpdate(AppState::MainMenu)));
+
+        // focus navigation, clicks are seen by the button actions in this frame
+        app.add_systems(
+            (
+                keyboard_navigation_system,
+                gamepad_navigation_system,
+                navigate_focus_system,
+            )
+                .chain()
+                .in_base_set(CoreSet::PreUpdate)
+                .after(UiSystem::Focus),
+        );
+        app.add_system(release_activated_button.in_base_set(CoreSet::PostUpdate));
+
+        // main screen
+        app.add_system(spawn_main_menu_system.in_schedule(OnEnter(MenuState::Main)));
+        app.add_system(despawn_components::<MainMenu>.in_schedule(OnExit(MenuState::Main)));
+        app.add_system(main_menu_button_action.in_set(OnUpdate(MenuState::Main)));
+
+        // new game screen, the options of the next game
+        app.add_system(spawn_new_game_menu_system.in_schedule(OnEnter(MenuState::NewGame)));
+        app.add_system(despawn_components::<NewGameMenu>.in_schedule(OnExit(MenuState::NewGame)));
+        app.add_systems(
+            (new_game_menu_button_action, update_game_option_values)
+                .chain()
+                .in_set(OnUpdate(MenuState::NewGame)),
+        );
+
+        // versus screen, waits there for the LAN server to start the match
+        app.add_system(spawn_versus_menu_system.in_schedule(OnEnter(MenuState::Versus)));
+        app.add_system(despawn_components::<VersusMenu>.in_schedule(OnExit(MenuState::Versus)));
+        app.add_systems(
+            (
+                versus_menu_button_action,
+                server_address_input_system,
+                lobby_system,
+                update_versus_menu_text,
+            )
+                .chain()
+                .in_set(OnUpdate(MenuState::Versus)),
+        );
+
+        // replays screen
+        app.add_system(spawn_replays_menu_system.in_schedule(OnEnter(MenuState::Replays)));
+        app.add_system(despawn_components::<ReplaysMenu>.in_schedule(OnExit(MenuState::Replays)));
+        app.add_system(replays_menu_button_action.in_set(OnUpdate(MenuState::Replays)));
+
+        // high scores screen, respawned when another variant is selected
+        app.add_system(enter_high_scores_menu.in_schedule(OnEnter(MenuState::HighScores)));
+        app.add_system(
+            despawn_components::<HighScoresMenu>.in_schedule(OnExit(MenuState::HighScores)),
+        );
+        app.add_systems(
+            (
+                high_scores_menu_button_action,
+                spawn_high_scores_menu_system,
+            )
+                .chain()
+                .in_set(OnUpdate(MenuState::HighScores)),
+        );
+
+        // settings screen, opened from the main menu or the pause layout
+        app.add_system(enter_settings_menu.in_schedule(OnEnter(MenuState::Settings)));
+        app.add_systems(
+            (reset_rebinding, spawn_settings_menu_system).in_schedule(OnEnter(SettingsState::Open)),
+        );
+        app.add_systems(
+            (
+                despawn_components::<SettingsMenu>,
+                save_settings,
+                leave_settings_menu,
+            )
+                .in_schedule(OnExit(SettingsState::Open)),
+        );
         app.add_systems(
-            (button_system, main_menu_button_action).in_set(OnUpdate(AppState::MainMenu)),
+            (
+                rebind_key_system,
+                settings_menu_button_action,
+                update_setting_values,
+            )
+                .chain()
+                .in_set(OnUpdate(SettingsState::Open)),
         );
     }
 }
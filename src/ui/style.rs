@@ -4,6 +4,8 @@ use bevy::ui::{AlignItems, JustifyContent, Style};
 pub(crate) const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 pub(crate) const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 pub(crate) const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+pub(crate) const SETTINGS_BACKGROUND_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.95);
+pub(crate) const HIGHLIGHTED_TEXT: Color = Color::rgb(1.0, 0.85, 0.2);
 
 pub(crate) const MAIN_MENU_STYLE: Style = Style {
     align_items: AlignItems::Center,
@@ -24,6 +26,41 @@ pub const BUTTON_STYLE: Style = Style {
     ..Style::DEFAULT
 };
 
+pub const SMALL_BUTTON_STYLE: Style = Style {
+    justify_content: JustifyContent::Center,
+    align_items: AlignItems::Center,
+    size: Size::new(Val::Px(40.0), Val::Px(40.0)),
+    margin: UiRect::all(Val::Px(4.0)),
+    ..Style::DEFAULT
+};
+
+pub const LIST_BUTTON_STYLE: Style = Style {
+    justify_content: JustifyContent::Center,
+    align_items: AlignItems::Center,
+    size: Size::new(Val::Px(520.0), Val::Px(48.0)),
+    margin: UiRect {
+        top: Val::Px(8.0),
+        ..UiRect::DEFAULT
+    },
+    ..Style::DEFAULT
+};
+
+pub(crate) fn get_list_text_style(asset_server: &Res<AssetServer>) -> TextStyle {
+    TextStyle {
+        font_size: 24.0,
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        color: Color::WHITE,
+    }
+}
+
+pub(crate) fn get_table_text_style(asset_server: &Res<AssetServer>) -> TextStyle {
+    TextStyle {
+        font_size: 20.0,
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        color: Color::WHITE,
+    }
+}
+
 pub(crate) fn get_text_style(asset_server: &Res<AssetServer>) -> TextStyle {
     TextStyle {
         font_size: 32.0,
@@ -1,43 +1,431 @@
+use crate::game::ai::AiPlayer;
+use crate::game::config::{get_game_config_path, GameConfig};
+use crate::game::garbage::Practice;
+use crate::game::leaderboard::Leaderboard;
+use crate::game::net::{NetMatch, NetStatus};
+use crate::game::replay::{Replay, ReplayPlayer};
+use crate::game::settings::{get_settings_path, Setting, Settings};
+use crate::game::theme::{Theme, Themes};
+use crate::game::versus::VersusMatch;
 use crate::game::GameState;
 use crate::ui::components::*;
 use crate::ui::style::*;
+use crate::ui::systems::layout::format_bindings;
 use crate::AppState;
 use bevy::app::AppExit;
 use bevy::prelude::*;
 
+/// Menu buttons of the `T` actions which were just interacted with.
+type ButtonActions<'w, 's, T> =
+    Query<'w, 's, (&'static Interaction, &'static T), (Changed<Interaction>, With<Button>)>;
+
+pub(crate) fn enter_main_menu(mut menu_state: ResMut<NextState<MenuState>>) {
+    menu_state.set(MenuState::Main);
+}
+
+pub(crate) fn leave_main_menu(mut menu_state: ResMut<NextState<MenuState>>) {
+    menu_state.set(MenuState::Disabled);
+}
+
 pub(crate) fn main_menu_button_action(
     mut writer: EventWriter<AppExit>,
-    query: Query<(&Interaction, &MainMenuButtonAction), (Changed<Interaction>, With<Button>)>,
+    query: ButtonActions<MainMenuButtonAction>,
     mut app_state: ResMut<NextState<AppState>>,
     mut game_state: ResMut<NextState<GameState>>,
-    key_code: Res<Input<KeyCode>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut ai: ResMut<AiPlayer>,
 ) {
     for (interaction, action) in query.iter() {
         if *interaction == Interaction::Clicked {
             match *action {
-                MainMenuButtonAction::Play => {
+                MainMenuButtonAction::NewGame => menu_state.set(MenuState::NewGame),
+                MainMenuButtonAction::Versus => menu_state.set(MenuState::Versus),
+                MainMenuButtonAction::WatchAi => {
+                    *ai = AiPlayer::new();
                     app_state.set(AppState::Game);
                     game_state.set(GameState::New);
                 }
+                MainMenuButtonAction::Replays => menu_state.set(MenuState::Replays),
+                MainMenuButtonAction::HighScores => menu_state.set(MenuState::HighScores),
+                MainMenuButtonAction::Settings => menu_state.set(MenuState::Settings),
                 MainMenuButtonAction::Exit => writer.send(AppExit),
             }
         }
     }
+}
+
+pub(crate) fn new_game_menu_button_action(
+    query: ButtonActions<NewGameMenuButtonAction>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut config: ResMut<GameConfig>,
+) {
+    for (interaction, action) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            match *action {
+                NewGameMenuButtonAction::Decrease(option) => config.adjust(option, -1),
+                NewGameMenuButtonAction::Increase(option) => config.adjust(option, 1),
+                NewGameMenuButtonAction::Start => {
+                    // offered again the next time
+                    if let Err(e) = config.save(&get_game_config_path()) {
+                        warn!("failed to save game options: {}", e);
+                    }
+                    app_state.set(AppState::Game);
+                    game_state.set(GameState::New);
+                }
+                NewGameMenuButtonAction::Back => menu_state.set(MenuState::Main),
+            }
+        }
+    }
+}
+
+pub(crate) fn update_game_option_values(
+    config: Res<GameConfig>,
+    mut values: Query<(&mut Text, &GameOptionValue)>,
+    mut descriptions: Query<(&mut Text, &GameOptionDescription), Without<GameOptionValue>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for (mut text, value) in values.iter_mut() {
+        text.sections[0].value = config.value(value.0);
+    }
+    for (mut text, description) in descriptions.iter_mut() {
+        text.sections[0].value = config.description(description.0).to_string();
+    }
+}
+
+/// Longest server address typed in the versus screen.
+const MAX_ADDRESS_LENGTH: usize = 64;
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn versus_menu_button_action(
+    query: ButtonActions<VersusMenuButtonAction>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut versus: ResMut<VersusMatch>,
+    mut practice: ResMut<Practice>,
+    mut net: ResMut<NetMatch>,
+    settings: Res<Settings>,
+) {
+    for (interaction, action) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            match action {
+                VersusMenuButtonAction::Local => {
+                    *versus = VersusMatch::new();
+                    app_state.set(AppState::Game);
+                    game_state.set(GameState::New);
+                }
+                VersusMenuButtonAction::Practice => {
+                    *practice = Practice::new();
+                    app_state.set(AppState::Game);
+                    game_state.set(GameState::New);
+                }
+                VersusMenuButtonAction::Join => {
+                    // remember the address for the next time
+                    if let Err(e) = settings.save(&get_settings_path()) {
+                        warn!("failed to save settings: {}", e);
+                    }
+                    net.join(&settings.versus.server, settings.handling);
+                }
+                VersusMenuButtonAction::Back => {
+                    net.leave();
+                    menu_state.set(MenuState::Main);
+                }
+            }
+        }
+    }
+}
+
+/// Type the address of the server, unless already waiting there.
+pub(crate) fn server_address_input_system(
+    mut characters: EventReader<ReceivedCharacter>,
+    key_code: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    net: Res<NetMatch>,
+) {
+    if net.status == NetStatus::Waiting {
+        characters.clear();
+        return;
+    }
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '-' | '[' | ']');
+    let typed: Vec<char> = characters
+        .iter()
+        .map(|ev| ev.char)
+        .filter(|c| valid(*c))
+        .collect();
+    let erase = key_code.just_pressed(KeyCode::Back);
+    if typed.is_empty() && !erase {
+        return;
+    }
+    let address = &mut settings.versus.server;
+    for c in typed {
+        if address.len() < MAX_ADDRESS_LENGTH {
+            address.push(c);
+        }
+    }
+    if erase {
+        address.pop();
+    }
+}
+
+pub(crate) fn update_versus_menu_text(
+    settings: Res<Settings>,
+    net: Res<NetMatch>,
+    mut address: Query<&mut Text, (With<ServerAddressText>, Without<JoinStatusText>)>,
+    mut status: Query<&mut Text, (With<JoinStatusText>, Without<ServerAddressText>)>,
+) {
+    if settings.is_changed() {
+        for mut text in address.iter_mut() {
+            text.sections[0].value = format!("Server: {}_", settings.versus.server);
+        }
+    }
+    if net.is_changed() {
+        for mut text in status.iter_mut() {
+            text.sections[0].value = match &net.status {
+                NetStatus::Idle | NetStatus::Playing => String::new(),
+                NetStatus::Waiting => "Waiting for an opponent...".to_string(),
+                NetStatus::Failed(reason) => format!("Failed: {}", reason),
+            };
+        }
+    }
+}
 
-    if key_code.just_pressed(KeyCode::Return) {
-        app_state.set(AppState::Game);
-        game_state.set(GameState::New);
+pub(crate) fn replays_menu_button_action(
+    query: ButtonActions<ReplaysMenuButtonAction>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut player: ResMut<ReplayPlayer>,
+) {
+    for (interaction, action) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            match action {
+                ReplaysMenuButtonAction::Watch(path) => match Replay::load(path) {
+                    Ok(replay) => {
+                        player.start(replay);
+                        app_state.set(AppState::Game);
+                        game_state.set(GameState::New);
+                    }
+                    Err(e) => warn!("failed to load replay {}: {}", path.display(), e),
+                },
+                ReplaysMenuButtonAction::Back => menu_state.set(MenuState::Main),
+            }
+        }
+    }
+}
+
+/// Show the first variant when entering the high scores screen.
+pub(crate) fn enter_high_scores_menu(mut selected: ResMut<SelectedVariant>) {
+    selected.0 = 0;
+}
+
+pub(crate) fn high_scores_menu_button_action(
+    query: ButtonActions<HighScoresMenuButtonAction>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut selected: ResMut<SelectedVariant>,
+    leaderboard: Res<Leaderboard>,
+) {
+    let variants = leaderboard.tables.len().max(1);
+    for (interaction, action) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            match action {
+                HighScoresMenuButtonAction::Previous => {
+                    selected.0 = (selected.0 + variants - 1) % variants
+                }
+                HighScoresMenuButtonAction::Next => selected.0 = (selected.0 + 1) % variants,
+                HighScoresMenuButtonAction::Back => menu_state.set(MenuState::Main),
+            }
+        }
+    }
+}
+
+/// Open the settings menu over the main menu.
+pub(crate) fn enter_settings_menu(mut settings_state: ResMut<NextState<SettingsState>>) {
+    settings_state.set(SettingsState::Open);
+}
+
+/// Go back to where the settings menu was opened from.
+pub(crate) fn leave_settings_menu(
+    menu_state: Res<State<MenuState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+) {
+    if menu_state.0 == MenuState::Settings {
+        next_menu_state.set(MenuState::Main);
+    }
+}
+
+pub(crate) fn settings_menu_button_action(
+    query: ButtonActions<SettingsMenuButtonAction>,
+    mut settings_state: ResMut<NextState<SettingsState>>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    themes: Res<Themes>,
+    theme_assets: Res<Assets<Theme>>,
+) {
+    for (interaction, action) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            match *action {
+                SettingsMenuButtonAction::Decrease(Setting::Theme) => {
+                    settings.display.theme =
+                        themes.cycle(&theme_assets, &settings.display.theme, -1)
+                }
+                SettingsMenuButtonAction::Increase(Setting::Theme) => {
+                    settings.display.theme = themes.cycle(&theme_assets, &settings.display.theme, 1)
+                }
+                SettingsMenuButtonAction::Decrease(setting) => settings.adjust(setting, -1),
+                SettingsMenuButtonAction::Increase(setting) => settings.adjust(setting, 1),
+                SettingsMenuButtonAction::Bind(action) => {
+                    rebinding.action = Some(action);
+                    rebinding.conflict = None;
+                }
+                SettingsMenuButtonAction::Unbind(action) => match rebinding.device {
+                    BindingDevice::Keyboard => settings.controls.clear(action),
+                    BindingDevice::Gamepad => settings.gamepad.buttons.clear(action),
+                },
+                SettingsMenuButtonAction::SwitchDevice => {
+                    rebinding.device = match rebinding.device {
+                        BindingDevice::Keyboard => BindingDevice::Gamepad,
+                        BindingDevice::Gamepad => BindingDevice::Keyboard,
+                    };
+                    rebinding.action = None;
+                    rebinding.conflict = None;
+                }
+                SettingsMenuButtonAction::Back => settings_state.set(SettingsState::Closed),
+            }
+        }
+    }
+}
+
+/// Bind the next pressed key or gamepad button to the action waiting for one.
+/// An input bound to another action must be pressed twice to be moved, Esc
+/// cancels.
+pub(crate) fn rebind_key_system(
+    key_code: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(action) = rebinding.action else {
+        return;
+    };
+    if key_code.just_pressed(KeyCode::Escape) {
+        rebinding.action = None;
+        rebinding.conflict = None;
+        return;
+    }
+    let input = match rebinding.device {
+        BindingDevice::Keyboard => key_code
+            .get_just_pressed()
+            .next()
+            .copied()
+            .map(BoundInput::Key),
+        BindingDevice::Gamepad => buttons
+            .get_just_pressed()
+            .next()
+            .map(|button| BoundInput::Button(button.button_type)),
+    };
+    let Some(input) = input else {
+        return;
+    };
+    let bound_to = match input {
+        BoundInput::Key(key) => settings.controls.bound_to(key),
+        BoundInput::Button(button) => settings.gamepad.buttons.bound_to(button),
+    };
+    match bound_to {
+        Some(other) if other != action && rebinding.conflict != Some((input, other)) => {
+            rebinding.conflict = Some((input, other));
+        }
+        _ => {
+            match input {
+                BoundInput::Key(key) => settings.controls.bind(action, key),
+                BoundInput::Button(button) => settings.gamepad.buttons.bind(action, button),
+            }
+            rebinding.action = None;
+            rebinding.conflict = None;
+        }
+    }
+}
+
+/// Reset the key binding prompt when the settings menu is opened.
+pub(crate) fn reset_rebinding(mut rebinding: ResMut<Rebinding>) {
+    *rebinding = Rebinding::default();
+}
+
+/// The key binding prompt, apart from the other texts of the settings menu.
+type PromptText = (
+    With<BindingPrompt>,
+    Without<SettingValue>,
+    Without<BindingText>,
+);
+
+/// The name of the device being rebound.
+type DeviceText = (
+    With<BindingDeviceText>,
+    Without<SettingValue>,
+    Without<BindingText>,
+    Without<BindingPrompt>,
+);
+
+pub(crate) fn update_setting_values(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    mut values: Query<(&mut Text, &SettingValue)>,
+    mut bindings: Query<(&mut Text, &BindingText), Without<SettingValue>>,
+    mut prompts: Query<&mut Text, PromptText>,
+    mut devices: Query<&mut Text, DeviceText>,
+) {
+    if settings.is_changed() {
+        for (mut text, value) in values.iter_mut() {
+            text.sections[0].value = settings.value(value.0);
+        }
+    }
+    if settings.is_changed() || rebinding.is_changed() {
+        for (mut text, binding) in bindings.iter_mut() {
+            text.sections[0].value = format_bindings(&settings, rebinding.device, binding.0);
+        }
+    }
+    if rebinding.is_changed() {
+        let prompt = match (rebinding.action, rebinding.conflict) {
+            (Some(action), Some((input, other))) => format!(
+                "{} is bound to {:?}, press it again to move it to {:?} (Esc to cancel)",
+                input.name(),
+                other,
+                action
+            ),
+            (Some(action), None) => match rebinding.device {
+                BindingDevice::Keyboard => format!("Press a key for {:?} (Esc to cancel)", action),
+                BindingDevice::Gamepad => {
+                    format!("Press a gamepad button for {:?} (Esc to cancel)", action)
+                }
+            },
+            _ => String::new(),
+        };
+        for mut text in prompts.iter_mut() {
+            text.sections[0].value = prompt.clone();
+        }
+        for mut text in devices.iter_mut() {
+            text.sections[0].value = format!("{:?}", rebinding.device);
+        }
     }
 }
 
+/// Buttons are highlighted when hovered or focused through menu navigation.
 pub fn button_system(
-    mut query: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<Button>)>,
+    focus: Res<MenuFocus>,
+    mut query: Query<(Entity, &Interaction, &mut BackgroundColor), With<Button>>,
 ) {
-    for (interaction, mut bg) in query.iter_mut() {
-        *bg = match *interaction {
-            Interaction::Clicked => PRESSED_BUTTON.into(),
-            Interaction::Hovered => HOVERED_BUTTON.into(),
-            Interaction::None => NORMAL_BUTTON.into(),
+    for (entity, interaction, mut bg) in query.iter_mut() {
+        let color = match *interaction {
+            Interaction::Clicked => PRESSED_BUTTON,
+            Interaction::Hovered => HOVERED_BUTTON,
+            Interaction::None if focus.entity == Some(entity) => HOVERED_BUTTON,
+            Interaction::None => NORMAL_BUTTON,
+        };
+        if bg.0 != color {
+            bg.0 = color;
         }
     }
 }
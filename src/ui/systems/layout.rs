@@ -1,10 +1,32 @@
 use bevy::prelude::*;
 
+use crate::game::{
+    config::{GameConfig, GameOption},
+    engine::Ruleset,
+    global::{format_duration, format_timestamp, FRAME_DURATION},
+    input::InputAction,
+    leaderboard::{Leaderboard, ScoreEntry},
+    replay::{list_replays, Replay},
+    settings::{Setting, Settings},
+};
 use crate::ui::{
-    components::{MainMenu, MainMenuButtonAction},
-    style::{get_text_style, get_title_text_style, BUTTON_STYLE, MAIN_MENU_STYLE, NORMAL_BUTTON},
+    components::{
+        BackButton, BindingDevice, BindingDeviceText, BindingPrompt, BindingText,
+        GameOptionDescription, GameOptionValue, HighScoresMenu, HighScoresMenuButtonAction,
+        JoinStatusText, MainMenu, MainMenuButtonAction, NewGameMenu, NewGameMenuButtonAction,
+        ReplaysMenu, ReplaysMenuButtonAction, SelectedVariant, ServerAddressText, SettingValue,
+        SettingsMenu, SettingsMenuButtonAction, VersusMenu, VersusMenuButtonAction,
+    },
+    style::{
+        get_list_text_style, get_table_text_style, get_text_style, get_title_text_style,
+        BUTTON_STYLE, HIGHLIGHTED_TEXT, LIST_BUTTON_STYLE, MAIN_MENU_STYLE, NORMAL_BUTTON,
+        SETTINGS_BACKGROUND_COLOR, SMALL_BUTTON_STYLE,
+    },
 };
 
+/// Number of replays listed in the replays screen.
+const REPLAYS_LISTED: usize = 10;
+
 pub(crate) fn spawn_main_menu_system(mut commands: Commands, asset_server: Res<AssetServer>) {
     let entity = build_main_menu(&mut commands, &asset_server);
     commands.entity(entity).insert(MainMenu);
@@ -37,12 +59,135 @@ fn build_main_menu(commands: &mut Commands, asset_server: &Res<AssetServer>) ->
                         background_color: NORMAL_BUTTON.into(),
                         ..Default::default()
                     },
-                    MainMenuButtonAction::Play,
+                    MainMenuButtonAction::NewGame,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new(
+                                "New Game",
+                                get_text_style(asset_server),
+                            )],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    MainMenuButtonAction::Versus,
                 ))
                 .with_children(|parent| {
                     parent.spawn(TextBundle {
                         text: Text {
-                            sections: vec![TextSection::new("Play", get_text_style(asset_server))],
+                            sections: vec![TextSection::new(
+                                "Versus",
+                                get_text_style(asset_server),
+                            )],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    MainMenuButtonAction::WatchAi,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new(
+                                "Watch AI",
+                                get_text_style(asset_server),
+                            )],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    MainMenuButtonAction::Replays,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new(
+                                "Replays",
+                                get_text_style(asset_server),
+                            )],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    MainMenuButtonAction::HighScores,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new(
+                                "High Scores",
+                                get_text_style(asset_server),
+                            )],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    MainMenuButtonAction::Settings,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new(
+                                "Settings",
+                                get_text_style(asset_server),
+                            )],
                             alignment: TextAlignment::Center,
                             ..Default::default()
                         },
@@ -73,3 +218,682 @@ fn build_main_menu(commands: &mut Commands, asset_server: &Res<AssetServer>) ->
         })
         .id()
 }
+
+/// Spawn the new game screen: the options of the next game, each with what
+/// its value means, from the last choice.
+pub(crate) fn spawn_new_game_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+) {
+    let text_style = get_list_text_style(&asset_server);
+    let description_style = get_table_text_style(&asset_server);
+    let button = |parent: &mut ChildBuilder, label: &str, action: NewGameMenuButtonAction| {
+        let mut button = parent.spawn((
+            ButtonBundle {
+                style: BUTTON_STYLE,
+                background_color: NORMAL_BUTTON.into(),
+                ..Default::default()
+            },
+            action,
+        ));
+        if action == NewGameMenuButtonAction::Back {
+            button.insert(BackButton);
+        }
+        button.with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                get_text_style(&asset_server),
+            ));
+        });
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: MAIN_MENU_STYLE,
+                ..Default::default()
+            },
+            NewGameMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "New Game",
+                get_title_text_style(&asset_server),
+            ));
+            for option in GameOption::ALL {
+                spawn_game_option_row(parent, option, &config, &text_style);
+                parent.spawn((
+                    TextBundle::from_section(config.description(option), description_style.clone())
+                        .with_style(Style {
+                            margin: UiRect::bottom(Val::Px(12.0)),
+                            ..Default::default()
+                        }),
+                    GameOptionDescription(option),
+                ));
+            }
+            button(parent, "Start", NewGameMenuButtonAction::Start);
+            button(parent, "Back", NewGameMenuButtonAction::Back);
+        });
+}
+
+fn spawn_game_option_row(
+    parent: &mut ChildBuilder,
+    option: GameOption,
+    config: &GameConfig,
+    text_style: &TextStyle,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(option.label(), text_style.clone()).with_style(Style {
+                    size: Size::width(Val::Px(180.0)),
+                    ..Default::default()
+                }),
+            );
+            spawn_small_button(
+                parent,
+                "<",
+                NewGameMenuButtonAction::Decrease(option),
+                text_style,
+            );
+            parent.spawn((
+                TextBundle::from_section(config.value(option), text_style.clone())
+                    .with_style(Style {
+                        size: Size::width(Val::Px(160.0)),
+                        ..Default::default()
+                    })
+                    .with_text_alignment(TextAlignment::Center),
+                GameOptionValue(option),
+            ));
+            spawn_small_button(
+                parent,
+                ">",
+                NewGameMenuButtonAction::Increase(option),
+                text_style,
+            );
+        });
+}
+
+pub(crate) fn spawn_replays_menu_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let replays: Vec<(String, ReplaysMenuButtonAction)> = list_replays()
+        .into_iter()
+        .filter_map(|path| {
+            let replay = Replay::load(&path).ok()?;
+            let score = replay.result.map(|r| r.score).unwrap_or_default();
+            let label = format!("{}   {:07}", format_timestamp(replay.created), score);
+            Some((label, ReplaysMenuButtonAction::Watch(path)))
+        })
+        .take(REPLAYS_LISTED)
+        .collect();
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: MAIN_MENU_STYLE,
+                ..Default::default()
+            },
+            ReplaysMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    sections: vec![TextSection::new(
+                        "Replays",
+                        get_title_text_style(&asset_server),
+                    )],
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+            if replays.is_empty() {
+                parent.spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection::new(
+                            "No replay saved yet",
+                            get_list_text_style(&asset_server),
+                        )],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+            }
+            for (label, action) in replays {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: LIST_BUTTON_STYLE,
+                            background_color: NORMAL_BUTTON.into(),
+                            ..Default::default()
+                        },
+                        action,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text {
+                                sections: vec![TextSection::new(
+                                    label,
+                                    get_list_text_style(&asset_server),
+                                )],
+                                alignment: TextAlignment::Center,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        });
+                    });
+            }
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    ReplaysMenuButtonAction::Back,
+                    BackButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new("Back", get_text_style(&asset_server))],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+/// Spawn the versus screen: a local match, practice against the scripted
+/// opponent, or joining a LAN server at the address typed in.
+pub(crate) fn spawn_versus_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+) {
+    let button = |parent: &mut ChildBuilder, label: &str, action: VersusMenuButtonAction| {
+        let mut button = parent.spawn((
+            ButtonBundle {
+                style: BUTTON_STYLE,
+                background_color: NORMAL_BUTTON.into(),
+                ..Default::default()
+            },
+            action,
+        ));
+        if action == VersusMenuButtonAction::Back {
+            button.insert(BackButton);
+        }
+        button.with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    sections: vec![TextSection::new(label, get_text_style(&asset_server))],
+                    alignment: TextAlignment::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        });
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: MAIN_MENU_STYLE,
+                ..Default::default()
+            },
+            VersusMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Versus",
+                get_title_text_style(&asset_server),
+            ));
+            button(parent, "Local", VersusMenuButtonAction::Local);
+            button(parent, "Practice", VersusMenuButtonAction::Practice);
+            parent.spawn((
+                TextBundle::from_section(
+                    format!("Server: {}_", settings.versus.server),
+                    get_list_text_style(&asset_server),
+                )
+                .with_style(Style {
+                    margin: UiRect::top(Val::Px(24.0)),
+                    ..Default::default()
+                }),
+                ServerAddressText,
+            ));
+            parent.spawn((
+                TextBundle::from_section("", get_list_text_style(&asset_server)),
+                JoinStatusText,
+            ));
+            button(parent, "Join LAN", VersusMenuButtonAction::Join);
+            button(parent, "Back", VersusMenuButtonAction::Back);
+        });
+}
+
+/// Spawn a high-score table, `highlight` is the rank of the row to highlight.
+pub(crate) fn spawn_score_rows(
+    parent: &mut ChildBuilder,
+    entries: &[ScoreEntry],
+    highlight: Option<usize>,
+    asset_server: &Res<AssetServer>,
+) {
+    let style = get_table_text_style(asset_server);
+    if entries.is_empty() {
+        parent.spawn(TextBundle::from_section("No high score yet", style));
+        return;
+    }
+    parent.spawn(TextBundle::from_section(
+        format!(
+            "{:>2}  {:<12} {:>7} {:>5} {:>3} {:<8} {:<16}",
+            "#", "Name", "Score", "Lines", "Lvl", "Time", "Date"
+        ),
+        style.clone(),
+    ));
+    for (rank, entry) in entries.iter().enumerate() {
+        let color = if highlight == Some(rank) {
+            HIGHLIGHTED_TEXT
+        } else {
+            style.color
+        };
+        parent.spawn(TextBundle::from_section(
+            format!(
+                "{:>2}. {:<12} {:>7} {:>5} {:>3} {:<8} {:<16}",
+                rank + 1,
+                entry.name,
+                entry.score,
+                entry.lines,
+                entry.level,
+                format_duration(FRAME_DURATION * entry.frames as u32),
+                format_timestamp(entry.date),
+            ),
+            TextStyle {
+                color,
+                ..style.clone()
+            },
+        ));
+    }
+}
+
+/// Spawn the high scores of the selected variant, respawned when another one is selected.
+pub(crate) fn spawn_high_scores_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    leaderboard: Res<Leaderboard>,
+    selected: Res<SelectedVariant>,
+    menus: Query<Entity, With<HighScoresMenu>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+    for entity in menus.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut variants: Vec<String> = leaderboard.tables.keys().cloned().collect();
+    if variants.is_empty() {
+        variants.push(Ruleset::default().variant());
+    }
+    let variant = &variants[selected.0 % variants.len()];
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: MAIN_MENU_STYLE,
+                ..Default::default()
+            },
+            HighScoresMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "High Scores",
+                get_title_text_style(&asset_server),
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::vertical(Val::Px(12.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (label, action) in [
+                        ("<", Some(HighScoresMenuButtonAction::Previous)),
+                        (variant.as_str(), None),
+                        (">", Some(HighScoresMenuButtonAction::Next)),
+                    ] {
+                        let text =
+                            TextBundle::from_section(label, get_list_text_style(&asset_server));
+                        match action {
+                            Some(action) => {
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                size: Size::new(Val::Px(48.0), Val::Px(48.0)),
+                                                margin: UiRect::horizontal(Val::Px(16.0)),
+                                                ..Default::default()
+                                            },
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..Default::default()
+                                        },
+                                        action,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(text);
+                                    });
+                            }
+                            None => {
+                                parent.spawn(text);
+                            }
+                        }
+                    }
+                });
+            spawn_score_rows(parent, leaderboard.table(variant), None, &asset_server);
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    HighScoresMenuButtonAction::Back,
+                    BackButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new("Back", get_text_style(&asset_server))],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+/// Spawn the settings menu, shown over the main menu or the paused game.
+pub(crate) fn spawn_settings_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+) {
+    let text_style = get_list_text_style(&asset_server);
+    commands
+        .spawn((
+            NodeBundle {
+                style: MAIN_MENU_STYLE,
+                background_color: SETTINGS_BACKGROUND_COLOR.into(),
+                ..Default::default()
+            },
+            SettingsMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Settings",
+                get_title_text_style(&asset_server),
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::FlexStart,
+                        margin: UiRect::vertical(Val::Px(12.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    // one row per setting: label, decrease, value, increase
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                margin: UiRect::right(Val::Px(48.0)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            for setting in Setting::ALL {
+                                spawn_setting_row(parent, setting, &settings, &text_style);
+                            }
+                        });
+                    // key bindings
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            spawn_binding_header(parent, &text_style);
+                            for action in InputAction::ALL {
+                                spawn_binding_row(parent, action, &settings, &asset_server);
+                            }
+                        });
+                });
+            parent.spawn((
+                TextBundle::from_section("", text_style.clone()),
+                BindingPrompt,
+            ));
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: BUTTON_STYLE,
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    SettingsMenuButtonAction::Back,
+                    BackButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection::new("Back", get_text_style(&asset_server))],
+                            alignment: TextAlignment::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+fn spawn_setting_row(
+    parent: &mut ChildBuilder,
+    setting: Setting,
+    settings: &Settings,
+    text_style: &TextStyle,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(setting.label(), text_style.clone()).with_style(Style {
+                    size: Size::width(Val::Px(220.0)),
+                    ..Default::default()
+                }),
+            );
+            spawn_small_button(
+                parent,
+                "<",
+                SettingsMenuButtonAction::Decrease(setting),
+                text_style,
+            );
+            parent.spawn((
+                TextBundle::from_section(settings.value(setting), text_style.clone())
+                    .with_style(Style {
+                        size: Size::width(Val::Px(160.0)),
+                        ..Default::default()
+                    })
+                    .with_text_alignment(TextAlignment::Center),
+                SettingValue(setting),
+            ));
+            spawn_small_button(
+                parent,
+                ">",
+                SettingsMenuButtonAction::Increase(setting),
+                text_style,
+            );
+        });
+}
+
+fn spawn_binding_row(
+    parent: &mut ChildBuilder,
+    action: InputAction,
+    settings: &Settings,
+    asset_server: &Res<AssetServer>,
+) {
+    let text_style = get_table_text_style(asset_server);
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(format!("{:?}", action), text_style.clone()).with_style(
+                    Style {
+                        size: Size::width(Val::Px(130.0)),
+                        ..Default::default()
+                    },
+                ),
+            );
+            parent.spawn((
+                TextBundle::from_section(
+                    format_bindings(settings, BindingDevice::Keyboard, action),
+                    text_style.clone(),
+                )
+                .with_style(Style {
+                    size: Size::width(Val::Px(240.0)),
+                    ..Default::default()
+                }),
+                BindingText(action),
+            ));
+            spawn_small_button(
+                parent,
+                "+",
+                SettingsMenuButtonAction::Bind(action),
+                &text_style,
+            );
+            spawn_small_button(
+                parent,
+                "x",
+                SettingsMenuButtonAction::Unbind(action),
+                &text_style,
+            );
+        });
+}
+
+/// Title of the bindings column with the button switching between keyboard
+/// and gamepad bindings.
+fn spawn_binding_header(parent: &mut ChildBuilder, text_style: &TextStyle) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section("Controls", text_style.clone()).with_style(Style {
+                    size: Size::width(Val::Px(130.0)),
+                    ..Default::default()
+                }),
+            );
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(160.0), Val::Px(40.0)),
+                            ..SMALL_BUTTON_STYLE
+                        },
+                        background_color: NORMAL_BUTTON.into(),
+                        ..Default::default()
+                    },
+                    SettingsMenuButtonAction::SwitchDevice,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            format!("{:?}", BindingDevice::Keyboard),
+                            text_style.clone(),
+                        ),
+                        BindingDeviceText,
+                    ));
+                });
+        });
+}
+
+/// Keys or gamepad buttons bound to an action as shown in the settings menu.
+pub(crate) fn format_bindings(
+    settings: &Settings,
+    device: BindingDevice,
+    action: InputAction,
+) -> String {
+    match device {
+        BindingDevice::Keyboard => format_keys(settings.controls.keys(action)),
+        BindingDevice::Gamepad => format_keys(settings.gamepad.buttons.keys(action)),
+    }
+}
+
+fn format_keys<T: std::fmt::Debug>(keys: &[T]) -> String {
+    if keys.is_empty() {
+        return "-".to_string();
+    }
+    keys.iter()
+        .map(|key| format!("{:?}", key))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn spawn_small_button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    action: impl Component,
+    text_style: &TextStyle,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: SMALL_BUTTON_STYLE,
+                background_color: NORMAL_BUTTON.into(),
+                ..Default::default()
+            },
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, text_style.clone()));
+        });
+}
@@ -1,2 +1,3 @@
 pub(crate) mod interactions;
 pub(crate) mod layout;
+pub(crate) mod navigation;
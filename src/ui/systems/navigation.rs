@@ -0,0 +1,299 @@
+//! Menu navigation without a mouse.
+//!
+//! Keyboard and gamepad input is turned into `MenuNavigation` events which
+//! move `MenuFocus` between the visible buttons. Activating the focused button
+//! clicks it for one frame, so the button actions of every menu handle it like
+//! a mouse click.
+
+use bevy::prelude::*;
+
+use crate::{
+    game::{leaderboard::NewRecord, GameState},
+    ui::components::{BackButton, MenuFocus, MenuNavigation, MenuState, Rebinding, SettingsState},
+    AppState,
+};
+
+/// Translate arrows, Tab, Enter, Space and Esc into menu navigation. Typing the
+/// name of a new high score takes the keyboard.
+pub(crate) fn keyboard_navigation_system(
+    key_code: Res<Input<KeyCode>>,
+    game_state: Res<State<GameState>>,
+    record: Option<Res<NewRecord>>,
+    mut events: EventWriter<MenuNavigation>,
+) {
+    let typing_name = game_state.0 == GameState::Over && record.is_some_and(|r| !r.confirmed);
+    if typing_name {
+        return;
+    }
+    let shift = key_code.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    for key in key_code.get_just_pressed() {
+        let navigation = match key {
+            KeyCode::Up => MenuNavigation::Up,
+            KeyCode::Down => MenuNavigation::Down,
+            KeyCode::Left => MenuNavigation::Left,
+            KeyCode::Right => MenuNavigation::Right,
+            KeyCode::Tab if shift => MenuNavigation::Previous,
+            KeyCode::Tab => MenuNavigation::Next,
+            KeyCode::Return | KeyCode::NumpadEnter | KeyCode::Space => MenuNavigation::Activate,
+            KeyCode::Escape => MenuNavigation::Back,
+            _ => continue,
+        };
+        events.send(navigation);
+    }
+}
+
+/// Stick deflection which counts as a direction in menus.
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// Translate the D-pad, left stick, South (confirm) and East (back) buttons of
+/// every gamepad into menu navigation.
+pub(crate) fn gamepad_navigation_system(
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut stick: Local<Option<MenuNavigation>>,
+    mut events: EventWriter<MenuNavigation>,
+) {
+    for pad in gamepads.iter() {
+        for (button, navigation) in [
+            (GamepadButtonType::DPadUp, MenuNavigation::Up),
+            (GamepadButtonType::DPadDown, MenuNavigation::Down),
+            (GamepadButtonType::DPadLeft, MenuNavigation::Left),
+            (GamepadButtonType::DPadRight, MenuNavigation::Right),
+            (GamepadButtonType::South, MenuNavigation::Activate),
+            (GamepadButtonType::East, MenuNavigation::Back),
+        ] {
+            if buttons.just_pressed(GamepadButton::new(pad, button)) {
+                events.send(navigation);
+            }
+        }
+    }
+
+    // the stick moves the focus once each time it is pushed
+    let direction = gamepads.iter().find_map(|pad| {
+        let axis = |ty| axes.get(GamepadAxis::new(pad, ty)).unwrap_or_default();
+        stick_direction(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        )
+    });
+    if direction != *stick {
+        if let Some(direction) = direction {
+            events.send(direction);
+        }
+        *stick = direction;
+    }
+}
+
+fn stick_direction(x: f32, y: f32) -> Option<MenuNavigation> {
+    if x.abs() > y.abs() {
+        match x {
+            x if x > STICK_THRESHOLD => Some(MenuNavigation::Right),
+            x if x < -STICK_THRESHOLD => Some(MenuNavigation::Left),
+            _ => None,
+        }
+    } else {
+        match y {
+            y if y > STICK_THRESHOLD => Some(MenuNavigation::Up),
+            y if y < -STICK_THRESHOLD => Some(MenuNavigation::Down),
+            _ => None,
+        }
+    }
+}
+
+/// States which show a new screen when they change.
+type ScreenStates<'w> = (
+    Res<'w, State<AppState>>,
+    Res<'w, State<MenuState>>,
+    Res<'w, State<SettingsState>>,
+    Res<'w, State<GameState>>,
+);
+
+/// Move the focus, or click the focused or back button. A button is always
+/// focused: the first one of a new screen, the one closest to the lost focus
+/// when a screen is rebuilt, or the one under the mouse.
+pub(crate) fn navigate_focus_system(
+    mut events: EventReader<MenuNavigation>,
+    mut focus: ResMut<MenuFocus>,
+    mut last_position: Local<Option<Vec2>>,
+    mut buttons: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &ComputedVisibility,
+            &mut Interaction,
+        ),
+        With<Button>,
+    >,
+    back_buttons: Query<(), With<BackButton>>,
+    rebinding: Res<Rebinding>,
+    states: ScreenStates,
+) {
+    // menu input goes to the binding prompt while it waits for a key
+    if rebinding.action.is_some() {
+        events.clear();
+        return;
+    }
+
+    let mut positions = vec![];
+    for (entity, transform, visibility, interaction) in buttons.iter_mut() {
+        if !visibility.is_visible_in_hierarchy() {
+            continue;
+        }
+        if interaction.is_changed() && *interaction == Interaction::Hovered {
+            focus.entity = Some(entity);
+        }
+        positions.push((entity, transform.translation().truncate()));
+    }
+    // reading order, used by Tab
+    positions.sort_by(|(_, a), (_, b)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+    let position_of = |entity| {
+        positions
+            .iter()
+            .find(|(e, _)| *e == entity)
+            .map(|(_, position)| *position)
+    };
+
+    let (app_state, menu_state, settings_state, game_state) = states;
+    let new_screen = app_state.is_changed()
+        || menu_state.is_changed()
+        || settings_state.is_changed()
+        || game_state.is_changed();
+    if new_screen {
+        *last_position = None;
+    }
+    if focus.entity.and_then(position_of).is_none() {
+        focus.entity = match *last_position {
+            Some(position) => closest(&positions, position),
+            None => positions.first().map(|(entity, _)| *entity),
+        };
+    }
+
+    for navigation in events.iter() {
+        let Some(entity) = focus.entity else {
+            break;
+        };
+        focus.entity = match *navigation {
+            MenuNavigation::Activate => {
+                focus.activated = Some(entity);
+                Some(entity)
+            }
+            MenuNavigation::Back => {
+                let back = positions.iter().find(|(e, _)| back_buttons.contains(*e));
+                if let Some((back, _)) = back {
+                    focus.activated = Some(*back);
+                }
+                back.map(|(back, _)| *back).or(Some(entity))
+            }
+            MenuNavigation::Next | MenuNavigation::Previous => {
+                cycle(&positions, entity, *navigation == MenuNavigation::Next)
+            }
+            direction => position_of(entity)
+                .and_then(|from| next_in_direction(&positions, from, direction))
+                .or(Some(entity)),
+        };
+    }
+
+    if let Some(entity) = focus.activated {
+        if let Ok((_, _, _, mut interaction)) = buttons.get_mut(entity) {
+            *interaction = Interaction::Clicked;
+        }
+    }
+    if let Some(position) = focus.entity.and_then(position_of) {
+        *last_position = Some(position);
+    }
+}
+
+/// Let go of the button clicked through navigation once every menu has seen it.
+pub(crate) fn release_activated_button(
+    mut focus: ResMut<MenuFocus>,
+    mut interactions: Query<&mut Interaction>,
+) {
+    if let Some(entity) = focus.activated.take() {
+        if let Ok(mut interaction) = interactions.get_mut(entity) {
+            if *interaction == Interaction::Clicked {
+                *interaction = Interaction::None;
+            }
+        }
+    }
+}
+
+/// Button after (or before) `entity` in `positions`, wrapping around.
+fn cycle(positions: &[(Entity, Vec2)], entity: Entity, forward: bool) -> Option<Entity> {
+    let index = positions.iter().position(|(e, _)| *e == entity)?;
+    let step = if forward { 1 } else { positions.len() - 1 };
+    Some(positions[(index + step) % positions.len()].0)
+}
+
+fn closest(positions: &[(Entity, Vec2)], to: Vec2) -> Option<Entity> {
+    positions
+        .iter()
+        .min_by(|(_, a), (_, b)| a.distance(to).total_cmp(&b.distance(to)))
+        .map(|(entity, _)| *entity)
+}
+
+/// Closest button in `direction` from `from`, in UI coordinates where y grows
+/// downwards. Buttons off the axis count as farther away.
+fn next_in_direction(
+    positions: &[(Entity, Vec2)],
+    from: Vec2,
+    direction: MenuNavigation,
+) -> Option<Entity> {
+    let axis = match direction {
+        MenuNavigation::Up => Vec2::NEG_Y,
+        MenuNavigation::Down => Vec2::Y,
+        MenuNavigation::Left => Vec2::NEG_X,
+        MenuNavigation::Right => Vec2::X,
+        _ => return None,
+    };
+    positions
+        .iter()
+        .filter_map(|(entity, position)| {
+            let offset = *position - from;
+            let along = offset.dot(axis);
+            let across = offset.perp_dot(axis).abs();
+            (along > 1.0).then_some((*entity, along + 2.0 * across))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{Entity, Vec2};
+
+    use super::{cycle, next_in_direction, stick_direction};
+    use crate::ui::components::MenuNavigation;
+
+    #[test]
+    fn test_focus_moves_to_closest_button_in_direction() {
+        // a column of three buttons with a small one right of the middle
+        let positions = [
+            (Entity::from_raw(0), Vec2::new(100.0, 100.0)),
+            (Entity::from_raw(1), Vec2::new(100.0, 200.0)),
+            (Entity::from_raw(2), Vec2::new(100.0, 300.0)),
+            (Entity::from_raw(3), Vec2::new(300.0, 210.0)),
+        ];
+        let from = positions[1].1;
+        let next = |direction| next_in_direction(&positions, from, direction);
+        assert_eq!(next(MenuNavigation::Up), Some(Entity::from_raw(0)));
+        assert_eq!(next(MenuNavigation::Down), Some(Entity::from_raw(2)));
+        assert_eq!(next(MenuNavigation::Right), Some(Entity::from_raw(3)));
+        assert_eq!(next(MenuNavigation::Left), None);
+        assert_eq!(
+            cycle(&positions, Entity::from_raw(3), true),
+            Some(Entity::from_raw(0))
+        );
+        assert_eq!(
+            cycle(&positions, Entity::from_raw(0), false),
+            Some(Entity::from_raw(3))
+        );
+    }
+
+    #[test]
+    fn test_stick_direction_needs_a_clear_push() {
+        assert_eq!(stick_direction(0.3, 0.2), None);
+        assert_eq!(stick_direction(0.2, 0.8), Some(MenuNavigation::Up));
+        assert_eq!(stick_direction(-0.9, 0.6), Some(MenuNavigation::Left));
+    }
+}
@@ -0,0 +1,158 @@
+//! Headless replay verification: `tetris verify <replay>`.
+//!
+//! The replay is re-simulated with the engine alone, no window nor Bevy plugin
+//! is created. The results are printed as JSON and the process exits with 1 if
+//! they disagree with the ones claimed by the replay. Whether the coach was
+//! shown can't be simulated, it is reported as claimed.
+
+use std::{path::Path, process::ExitCode};
+
+use serde::Serialize;
+
+use crate::game::replay::{Replay, ReplayResult};
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub score: i32,
+    pub lines: usize,
+    /// Game time in seconds.
+    pub time: f64,
+    pub pieces: usize,
+    pub frames: u64,
+    /// The coach was shown, the game isn't eligible for the leaderboard.
+    pub coached: bool,
+    pub valid: bool,
+}
+
+/// Re-simulate `replay` and compare the results with the claimed ones. The game
+/// must end at the claimed frame: the simulation stops when it is over, so one
+/// ending earlier falls short of it, and a replay cut off mid-game isn't over.
+pub fn verify_replay(replay: &Replay) -> Option<VerifyReport> {
+    let claimed = replay.result?;
+    let engine = replay.simulate(claimed.frames);
+    let result = ReplayResult {
+        coached: claimed.coached,
+        ..ReplayResult::from(&engine)
+    };
+    Some(VerifyReport {
+        score: result.score,
+        lines: result.lines,
+        time: engine.time().as_secs_f64(),
+        pieces: result.pieces,
+        frames: result.frames,
+        coached: result.coached,
+        valid: engine.is_over() && result == claimed,
+    })
+}
+
+pub fn run(path: &Path) -> ExitCode {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("failed to load replay {}: {}", path.display(), e);
+            return ExitCode::from(2);
+        }
+    };
+    let Some(report) = verify_replay(&replay) else {
+        eprintln!("replay {} has no claimed result", path.display());
+        return ExitCode::from(2);
+    };
+
+    println!("{}", serde_json::to_string(&report).unwrap());
+    if report.valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify_replay;
+    use crate::game::{
+        ai::AiPlayer,
+        engine::{Engine, GameMode, Ruleset},
+        input::{ActionEvent, InputAction},
+        replay::{RecordedAction, Replay, ReplayResult},
+    };
+
+    /// Record a game of `ruleset` played with `play` until it is over.
+    fn record_with(
+        seed: u64,
+        ruleset: Ruleset,
+        mut play: impl FnMut(&Engine) -> Vec<ActionEvent>,
+    ) -> Replay {
+        let mut engine = Engine::new(seed, ruleset);
+        let mut replay = Replay::new(seed, ruleset);
+        while !engine.is_over() {
+            let actions = play(&engine);
+            replay
+                .inputs
+                .extend(actions.iter().map(|event| RecordedAction {
+                    frame: engine.frame,
+                    event: *event,
+                }));
+            engine.step(&actions);
+        }
+        replay.result = Some(ReplayResult::from(&engine));
+        replay
+    }
+
+    fn record(seed: u64) -> Replay {
+        record_with(seed, Ruleset::default(), |engine| match engine.frame % 30 {
+            2 => vec![ActionEvent::press(InputAction::MoveLeft)],
+            10 => vec![ActionEvent::press(InputAction::HardDrop)],
+            _ => vec![],
+        })
+    }
+
+    /// Record a game of `mode` played by the AI.
+    fn record_ai(seed: u64, mode: GameMode) -> Replay {
+        let ruleset = Ruleset {
+            mode,
+            ..Ruleset::default()
+        };
+        let mut player = AiPlayer::new();
+        record_with(seed, ruleset, |engine| player.next_actions(engine, 4))
+    }
+
+    #[test]
+    fn test_verify_replay() {
+        let mut replay = record(5);
+        assert!(verify_replay(&replay).unwrap().valid);
+
+        replay.result.as_mut().unwrap().score += 100;
+        assert!(!verify_replay(&replay).unwrap().valid);
+    }
+
+    #[test]
+    fn test_verify_goal_and_timed_replays() {
+        for mode in [GameMode::Sprint, GameMode::Ultra] {
+            let replay = record_ai(5, mode);
+            assert!(replay.simulate(u64::MAX).finished);
+            let report = verify_replay(&replay).unwrap();
+            assert!(report.valid, "{:?} replay doesn't verify", mode);
+        }
+    }
+
+    #[test]
+    fn test_verify_truncated_replay() {
+        let replay = record(5);
+        let end = replay.result.unwrap().frames / 2;
+        let mut truncated = replay.clone();
+        truncated.inputs.retain(|action| action.frame < end);
+        truncated.result = Some(ReplayResult::from(&replay.simulate(end)));
+        assert!(!verify_replay(&truncated).unwrap().valid);
+    }
+
+    #[test]
+    fn test_verify_reports_coached_replay() {
+        let mut replay = record(5);
+        assert!(!verify_replay(&replay).unwrap().coached);
+
+        replay.result.as_mut().unwrap().coached = true;
+        let report = verify_replay(&replay).unwrap();
+        assert!(report.coached);
+        assert!(report.valid);
+    }
+}